@@ -1,10 +1,13 @@
 use curl::easy::Easy;
+use encoding_rs::Encoding;
+use regex::bytes::Regex;
 use select::document::Document;
 use select::predicate::Name;
 use serde::{Deserialize, Serialize};
 
 use std::cmp::Ordering;
 use std::fmt::Display;
+use std::time::Duration;
 
 use utils::data::{Id, Searchable};
 
@@ -15,6 +18,14 @@ pub struct Bookmark {
     pub name: String,
     pub url: String,
     pub tags: Vec<String>,
+    /// When the bookmark was added, as a UNIX timestamp (in seconds). Bookmarks added before this was tracked don't
+    /// have one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub added: Option<u64>,
+    /// The url of an icon for the bookmark, for richer UIs. By default, it's the conventional favicon location of the
+    /// site (see [`default_icon_url`]), which may not exist.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub icon: Option<String>,
 }
 
 impl Ord for Bookmark {
@@ -35,13 +46,445 @@ impl Searchable for Bookmark {
     }
 }
 
+impl Bookmark {
+    /// Checks if the name, url or any of the tags of the bookmark contain `query`, ignoring case.
+    pub fn matches_query(&self, query: &str) -> bool {
+        let query = query.to_lowercase();
+
+        self.name.to_lowercase().contains(&query)
+            || self.url.to_lowercase().contains(&query)
+            || self
+                .tags
+                .iter()
+                .any(|tag| tag.to_lowercase().contains(&query))
+    }
+
+    /// Checks if the bookmark was added before `cutoff` (a UNIX timestamp). Bookmarks without a timestamp are only
+    /// considered old if `include_undated` is set.
+    pub fn added_before(&self, cutoff: u64, include_undated: bool) -> bool {
+        match self.added {
+            Some(added) => added < cutoff,
+            None => include_undated,
+        }
+    }
+
+    /// Builds a Markdown link to the bookmark (`[title](url)`). Brackets and backslashes on the title are escaped, and
+    /// spaces and parentheses on the url are percent-encoded, so neither can end the link early.
+    pub fn markdown_link(&self) -> String {
+        let mut title = String::with_capacity(self.name.len());
+        for c in self.name.chars() {
+            if matches!(c, '[' | ']' | '\\') {
+                title.push('\\');
+            }
+            title.push(c);
+        }
+
+        let url = self
+            .url
+            .replace(' ', "%20")
+            .replace('(', "%28")
+            .replace(')', "%29");
+
+        format!("[{}]({})", title, url)
+    }
+}
+
+/// What gets copied from a bookmark.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CopyFormat {
+    /// The url.
+    Url,
+    /// The title.
+    Title,
+    /// A Markdown link (see [`Bookmark::markdown_link`]).
+    ///
+    /// [`Bookmark::markdown_link`]: Bookmark::markdown_link
+    Markdown,
+}
+
+impl CopyFormat {
+    /// Parses a format name (`url`, `title` or `md`/`markdown`).
+    pub fn parse(string: &str) -> Result<Self, String> {
+        match string.trim().to_lowercase().as_str() {
+            "url" => Ok(Self::Url),
+            "title" => Ok(Self::Title),
+            "md" | "markdown" => Ok(Self::Markdown),
+            other => Err(format!(
+                "unknown format {:?} (expected url, title or md)",
+                other
+            )),
+        }
+    }
+
+    /// Builds the text to be copied from `bookmark`.
+    pub fn text(self, bookmark: &Bookmark) -> String {
+        match self {
+            Self::Url => bookmark.url.clone(),
+            Self::Title => bookmark.name.clone(),
+            Self::Markdown => bookmark.markdown_link(),
+        }
+    }
+}
+
+/// Returns the current time as a UNIX timestamp, in seconds.
+pub fn now_timestamp() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+/// Normalizes a list of tags, trimming and lowercasing each one, dropping empty ones and removing duplicates while
+/// keeping the order in which they first appeared.
+pub fn normalize_tags<I, S>(tags: I) -> Vec<String>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    let mut result: Vec<String> = Vec::new();
+
+    for tag in tags {
+        let tag = tag.as_ref().trim().to_lowercase();
+
+        if !tag.is_empty() && !result.contains(&tag) {
+            result.push(tag);
+        }
+    }
+
+    result
+}
+
+/// Splits a comma-separated list of tags and normalizes it.
+pub fn parse_tag_list(string: &str) -> Vec<String> {
+    normalize_tags(string.split(','))
+}
+
+/// Extracts the host of an url (e.g. `example.com` from `https://user@Example.com:8080/path?q`), lowercased.
+///
+/// The scheme is optional, so a bare `example.com/path` also works. Returns `None` if there's no host.
+pub fn url_domain(url: &str) -> Option<String> {
+    let rest = match url.find("://") {
+        Some(i) => &url[i + 3..],
+        None => url,
+    };
+
+    let authority = rest.split(['/', '?', '#']).next()?;
+    let host_port = authority.rsplit('@').next()?;
+    let host = match host_port.rfind(':') {
+        Some(i) if host_port[i + 1..].chars().all(|c| c.is_ascii_digit()) => &host_port[..i],
+        _ => host_port,
+    };
+
+    if host.is_empty() {
+        None
+    } else {
+        Some(host.to_lowercase())
+    }
+}
+
+/// Checks if `url` looks like something that can be fetched: no spaces or control characters, an alphabetic scheme
+/// (if any) and a host made of letters, digits, dots and dashes (see [`url_domain`]).
+///
+/// [`url_domain`]: url_domain
+pub fn url_is_well_formed(url: &str) -> bool {
+    let scheme_ok = match url.find("://") {
+        Some(i) => i > 0 && url[..i].chars().all(|c| c.is_ascii_alphabetic()),
+        None => true,
+    };
+
+    scheme_ok
+        && !url.chars().any(|c| c.is_whitespace() || c.is_control())
+        && url_domain(url).is_some_and(|host| {
+            host.chars()
+                .all(|c| c.is_alphanumeric() || matches!(c, '.' | '-'))
+        })
+}
+
+/// Builds the conventional favicon url of the site `url` is on (`{scheme}://{host}/favicon.ico`), assuming `https`
+/// if there's no scheme. Returns `None` if there's no host.
+pub fn default_icon_url(url: &str) -> Option<String> {
+    let scheme = match url.find("://") {
+        Some(i) => url[..i].to_lowercase(),
+        None => "https".into(),
+    };
+
+    url_domain(url).map(|host| format!("{}://{}/favicon.ico", scheme, host))
+}
+
+/// Checks if something exists at `url`, with a `HEAD` request (so the body isn't downloaded). Redirections are
+/// followed, and the status code is checked like when fetching titles (see [`check_status`]).
+///
+/// [`check_status`]: check_status
+pub fn url_check_exists(url: &str) -> Result<(), String> {
+    let mut easy = Easy::new();
+
+    easy.url(url)
+        .and_then(|_| easy.nobody(true))
+        .and_then(|_| easy.follow_location(true))
+        .and_then(|_| easy.connect_timeout(CONNECT_TIMEOUT))
+        .and_then(|_| easy.proxy(proxy_from_env(url).as_deref().unwrap_or("")))
+        .and_then(|_| easy.perform())
+        .map_err(|why| format!("Curl error: {}", why))?;
+
+    let code = easy
+        .response_code()
+        .map_err(|why| format!("Curl error: {}", why))?;
+
+    check_status(code).map_err(|e| e.why.to_string())
+}
+
+/// Builds the command that opens `url` in a private window from `template`, which is split on whitespace, with every
+/// `{url}` replaced by the url (e.g. `firefox --private-window {url}`). If there's no `{url}`, the url is added as the
+/// last argument.
+///
+/// Returns the program and its arguments, or an error if the template is empty.
+pub fn private_command(template: &str, url: &str) -> Result<(String, Vec<String>), String> {
+    let mut words = template.split_whitespace();
+    let program = words
+        .next()
+        .ok_or_else(|| "the private browsing command is empty".to_string())?;
+
+    let mut args: Vec<String> = words.map(|word| word.replace("{url}", url)).collect();
+    if !template.contains("{url}") {
+        args.push(url.into());
+    }
+
+    Ok((program.into(), args))
+}
+
+/// Opens `url` with `$OPENER` (or `xdg-open`), or in a private window with the command on `$BKMK_PRIVATE_CMD` (see
+/// [`private_command`]) if `private` is true. A non-zero exit code is an error.
+///
+/// [`private_command`]: private_command
+pub fn open_url(url: &str, private: bool) -> Result<(), String> {
+    let (program, args) = if private {
+        let template = std::env::var("BKMK_PRIVATE_CMD")
+            .ok()
+            .filter(|var| !var.trim().is_empty())
+            .ok_or_else(|| {
+                "no private browsing command configured; set $BKMK_PRIVATE_CMD (e.g. `firefox --private-window {url}`)"
+                    .to_string()
+            })?;
+
+        private_command(&template, url)?
+    } else {
+        let opener = std::env::var("OPENER").unwrap_or_else(|_| "xdg-open".into());
+        (opener, vec![url.into()])
+    };
+
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    match utils::proc::run(&program, &args, None)? {
+        0 => Ok(()),
+        code => Err(format!("{} exited with code {}", program, code)),
+    }
+}
+
+/// Separators commonly used between a page title and the site name.
+const TITLE_SEPARATORS: &[&str] = &[" | ", " - ", " — ", " – ", " · ", " :: ", " » "];
+
+/// Whether titles should be cleaned up with [`clean_title`] (`$BKMK_CLEAN_TITLES=1`).
+///
+/// [`clean_title`]: clean_title
+pub fn clean_titles_from_env() -> bool {
+    std::env::var("BKMK_CLEAN_TITLES").is_ok_and(|var| var.trim() == "1")
+}
+
+/// Removes the site name from a fetched title (e.g. `Some Article | Example` on `example.com` becomes `Some
+/// Article`), along with any dangling separators.
+///
+/// The site name is detected on either end of the title, by comparing the segment past the separator (ignoring case,
+/// spaces and punctuation) to the labels of the url's host. The segment has to start with the site name in whole
+/// words (`BBC News` on `bbc.co.uk`), so a title that only mentions it is left alone. If nothing would be left of
+/// the title, it's returned as-is.
+pub fn clean_title(raw: &str, url: &str) -> String {
+    let normalize = |s: &str| -> String {
+        s.chars()
+            .filter(|c| c.is_alphanumeric())
+            .flat_map(char::to_lowercase)
+            .collect()
+    };
+
+    // the labels that could be the site name: "en.wikipedia.org" => ["wikipedia"]
+    let host = url_domain(url).unwrap_or_default();
+    let host_labels: Vec<&str> = host.split('.').collect();
+    let site_names: Vec<String> = host_labels[..host_labels.len().saturating_sub(1)]
+        .iter()
+        .map(|label| normalize(label))
+        .filter(|label| label.len() >= 3 && label != "www")
+        .collect();
+
+    let is_site_name = |part: &str| {
+        let mut prefix = String::new();
+
+        part.split_whitespace().any(|word| {
+            prefix.push_str(&normalize(word));
+            site_names.contains(&prefix)
+        })
+    };
+
+    let trim = |s: &str| -> String {
+        let mut s = s.trim();
+
+        while let Some(stripped) = TITLE_SEPARATORS.iter().find_map(|sep| {
+            s.strip_suffix(sep.trim_end())
+                .or_else(|| s.strip_prefix(sep.trim_start()))
+        }) {
+            s = stripped.trim();
+        }
+
+        s.to_string()
+    };
+
+    let mut title = trim(raw);
+
+    // site name at the end, after the last separator
+    if let Some((i, sep)) = TITLE_SEPARATORS
+        .iter()
+        .filter_map(|sep| title.rfind(sep).map(|i| (i, sep)))
+        .max_by_key(|(i, _)| *i)
+    {
+        if is_site_name(&title[i + sep.len()..]) {
+            title = trim(&title[..i]);
+        }
+    }
+
+    // site name at the start, before the first separator
+    if let Some((i, sep)) = TITLE_SEPARATORS
+        .iter()
+        .filter_map(|sep| title.find(sep).map(|i| (i, sep)))
+        .min_by_key(|(i, _)| *i)
+    {
+        if is_site_name(&title[..i]) {
+            title = trim(&title[i + sep.len()..]);
+        }
+    }
+
+    if title.is_empty() {
+        raw.to_string()
+    } else {
+        title
+    }
+}
+
+/// The default amount of retries when fetching a title, if `$BKMK_RETRIES` is not set.
+const DEFAULT_RETRIES: u32 = 2;
+
+/// The delay before the first retry. Each subsequent retry waits twice as long as the previous one.
+const INITIAL_BACKOFF: Duration = Duration::from_millis(250);
+
+/// An error that happened during a single title fetch attempt.
+struct FetchError {
+    why: Box<dyn Display + 'static>,
+    /// If the error might go away by trying again (e.g. a connection or timeout error).
+    retryable: bool,
+}
+
+impl FetchError {
+    fn fatal<D: Display + 'static>(why: D) -> Self {
+        Self {
+            why: Box::new(why),
+            retryable: false,
+        }
+    }
+}
+
+/// How long to wait for a connection (to the server or to the proxy) before giving up.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Finds the proxy that should be used for `url`, from `$http_proxy`/`$https_proxy` (depending on the url's scheme)
+/// or `$all_proxy`, unless the host is on `$no_proxy`. Both lowercase and uppercase variables are checked, lowercase
+/// first.
+pub fn proxy_from_env(url: &str) -> Option<String> {
+    proxy_for(url, |var| std::env::var(var).ok())
+}
+
+/// Like [`proxy_from_env`], but getting the variables through `getvar`.
+///
+/// [`proxy_from_env`]: proxy_from_env
+fn proxy_for<G: Fn(&str) -> Option<String>>(url: &str, getvar: G) -> Option<String> {
+    let get = |name: &str| {
+        getvar(&name.to_lowercase())
+            .or_else(|| getvar(&name.to_uppercase()))
+            .filter(|value| !value.trim().is_empty())
+    };
+
+    if let (Some(no_proxy), Some(host)) = (get("no_proxy"), url_domain(url)) {
+        let bypassed = no_proxy.split(',').map(str::trim).any(|entry| {
+            let entry = entry.trim_start_matches('.').to_lowercase();
+            entry == "*" || host == entry || host.ends_with(&format!(".{}", entry))
+        });
+
+        if bypassed {
+            return None;
+        }
+    }
+
+    let scheme_var = match url.find("://").map(|i| url[..i].to_lowercase()) {
+        Some(ref scheme) if scheme == "https" => get("https_proxy"),
+        Some(ref scheme) if scheme == "http" => get("http_proxy"),
+        _ => None,
+    };
+
+    scheme_var.or_else(|| get("all_proxy"))
+}
+
+/// Gets the title of a webpage, retrying on transient failures.
+///
+/// The amount of retries can be set with `$BKMK_RETRIES` (default: 2). Client errors (4xx) are never retried.
 pub fn url_get_title(url: &str) -> Result<String, Box<dyn Display + 'static>> {
+    let retries = std::env::var("BKMK_RETRIES")
+        .ok()
+        .and_then(|var| var.trim().parse().ok())
+        .unwrap_or(DEFAULT_RETRIES);
+
+    url_get_title_with_retries(url, retries, INITIAL_BACKOFF)
+}
+
+/// Gets the title of a webpage, retrying up to `retries` times on transient failures and waiting `backoff` before the
+/// first retry (doubling it on each subsequent one). Returns the last error if all attempts fail.
+pub fn url_get_title_with_retries(
+    url: &str,
+    retries: u32,
+    backoff: Duration,
+) -> Result<String, Box<dyn Display + 'static>> {
+    let mut delay = backoff;
+    let mut attempt = 0;
+    let proxy = proxy_from_env(url);
+
+    if let Some(ref proxy) = proxy {
+        utils::debug!("fetching {} through proxy {}", url, proxy);
+    }
+
+    loop {
+        match url_get_title_once(url, proxy.as_deref()) {
+            Ok(title) => break Ok(title),
+            Err(e) if e.retryable && attempt < retries => {
+                attempt += 1;
+                utils::debug!(
+                    "fetching {} failed ({}), retrying ({}/{})",
+                    url,
+                    e.why,
+                    attempt,
+                    retries
+                );
+
+                std::thread::sleep(delay);
+                delay *= 2;
+            }
+            Err(e) => break Err(e.why),
+        }
+    }
+}
+
+fn url_get_title_once(url: &str, proxy: Option<&str>) -> Result<String, FetchError> {
     let mut vec = Vec::new();
 
     let mut easy = Easy::new();
 
     easy.url(url)
-        .map_err(|why| Box::new(format!("Curl error: {}", why)) as _)?;
+        .and_then(|_| easy.connect_timeout(CONNECT_TIMEOUT))
+        // an empty proxy disables curl's own environment lookup, so only the one found by `proxy_from_env` is used
+        .and_then(|_| easy.proxy(proxy.unwrap_or("")))
+        .map_err(|why| FetchError::fatal(format!("Curl error: {}", why)))?;
 
     {
         let mut transfer = easy.transfer();
@@ -52,21 +495,83 @@ pub fn url_get_title(url: &str) -> Result<String, Box<dyn Display + 'static>> {
             })
             .unwrap();
 
-        transfer
-            .perform()
-            .map_err(|_| Box::new("Failed to download/write to buffer") as _)?;
+        transfer.perform().map_err(|why| FetchError {
+            retryable: why.is_couldnt_connect()
+                || why.is_operation_timedout()
+                || why.is_got_nothing()
+                || why.is_send_error()
+                || why.is_recv_error()
+                || why.is_couldnt_resolve_host(),
+            why: Box::new(format!("Failed to download/write to buffer: {}", why)),
+        })?;
     }
 
-    let code = easy.response_code().unwrap();
+    check_status(easy.response_code().unwrap())?;
+
+    let content_type = easy.content_type().ok().flatten().map(String::from);
+    let page = decode_page(&vec, content_type.as_deref());
+
+    page_title(&page)
+}
+
+/// Turns the status code of a response into an error if it isn't a success. Only server errors are worth retrying.
+fn check_status(code: u32) -> Result<(), FetchError> {
     match code {
-        300..=399 => return Err(Box::new(format!("got redirection code {}", code))), // TODO: parse redirection codes
-        400..=499 => return Err(Box::new(format!("got client error code {}", code))),
-        500..=599 => return Err(Box::new(format!("got server error code {}", code))),
-        _ => (),
+        300..=399 => Err(FetchError::fatal(format!("got redirection code {}", code))), // TODO: parse redirection codes
+        400..=499 => Err(FetchError::fatal(format!("got client error code {}", code))),
+        500..=599 => Err(FetchError {
+            why: Box::new(format!("got server error code {}", code)),
+            retryable: true,
+        }),
+        _ => Ok(()),
     }
+}
+
+/// Decodes a downloaded page, using the charset on its `Content-Type` header or, failing that, the one on a `<meta>`
+/// tag near its start. Pages with an unknown charset (or none at all) are decoded as UTF-8, replacing invalid data.
+fn decode_page(bytes: &[u8], content_type: Option<&str>) -> String {
+    let encoding = content_type
+        .and_then(charset_from_content_type)
+        .and_then(|label| Encoding::for_label(label.as_bytes()))
+        .or_else(|| charset_from_meta(bytes).and_then(Encoding::for_label));
+
+    match encoding {
+        Some(encoding) => {
+            utils::debug!("decoding page as {}", encoding.name());
+            encoding.decode(bytes).0.into_owned()
+        }
+        None => String::from_utf8_lossy(bytes).into_owned(),
+    }
+}
 
-    let document = Document::from_read(String::from_utf8_lossy(&vec).as_bytes())
-        .map_err(|why| Box::new(format!("Failed to parse webpage: {}", why)) as _)?;
+/// Gets the charset from a `Content-Type` header value (e.g. `text/html; charset=ISO-8859-1`).
+fn charset_from_content_type(content_type: &str) -> Option<&str> {
+    content_type.split(';').skip(1).find_map(|param| {
+        let (key, value) = param.split_once('=')?;
+
+        if key.trim().eq_ignore_ascii_case("charset") {
+            Some(value.trim().trim_matches(|c| c == '"' || c == '\''))
+        } else {
+            None
+        }
+    })
+}
+
+/// Gets the charset from a `<meta charset=...>` or `<meta http-equiv="Content-Type" content="...; charset=...">` tag,
+/// if there's one on the first 1024 bytes of the page (where browsers look for it too).
+fn charset_from_meta(bytes: &[u8]) -> Option<&[u8]> {
+    let re = Regex::new(r#"(?i-u)<meta[^>]*?charset\s*=\s*["']?([a-z0-9_:.-]+)"#).unwrap();
+    let head = &bytes[..bytes.len().min(1024)];
+
+    re.captures(head)
+        .and_then(|captures| captures.get(1))
+        .map(|label| label.as_bytes())
+}
+
+/// Extracts the contents of the first `<title>` tag of a page.
+fn page_title(page: &str) -> Result<String, FetchError> {
+    let document = Document::from_read(page.as_bytes())
+        .map_err(|why| FetchError::fatal(format!("Failed to parse webpage: {}", why)))?;
 
     if let Some(title_tag) = document.find(Name("title")).nth(0) {
         // get the first text element of the title tag (can there even be more than that?), ignore the rest
@@ -77,9 +582,305 @@ pub fn url_get_title(url: &str) -> Result<String, Box<dyn Display + 'static>> {
         {
             Ok(title.to_string())
         } else {
-            Err(Box::new("Empty <title> tag"))
+            Err(FetchError::fatal("Empty <title> tag"))
         }
     } else {
-        Err(Box::new("Couldn't find any <title> tags in page"))
+        Err(FetchError::fatal("Couldn't find any <title> tags in page"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    #[test]
+    fn markdown_link_escaping() {
+        let bkmk = Bookmark {
+            id: 0,
+            name: r"[WIP] a\b".into(),
+            url: "https://en.wikipedia.org/wiki/Rust_(programming language)".into(),
+            tags: Vec::new(),
+            archived: false,
+            added: None,
+            icon: None,
+        };
+
+        assert_eq!(
+            bkmk.markdown_link(),
+            r"[\[WIP\] a\\b](https://en.wikipedia.org/wiki/Rust_%28programming%20language%29)"
+        );
+        assert_eq!(CopyFormat::parse("MD"), Ok(CopyFormat::Markdown));
+        assert_eq!(CopyFormat::Title.text(&bkmk), r"[WIP] a\b");
+        assert!(CopyFormat::parse("html").is_err());
+    }
+
+    #[test]
+    fn title_charset_decoding() {
+        let title = |bytes: &[u8], content_type: Option<&str>| {
+            page_title(&decode_page(bytes, content_type)).ok().unwrap()
+        };
+
+        // "Café" in Latin-1
+        let header_only = b"<html><head><title>Caf\xe9</title></head></html>";
+        let with_meta =
+            b"<html><head><meta charset=\"ISO-8859-1\"><title>Caf\xe9</title></head></html>";
+        let http_equiv = b"<meta http-equiv=\"Content-Type\" content=\"text/html; charset=latin1\"><title>Caf\xe9</title>";
+
+        assert_eq!(
+            title(header_only, Some("text/html; charset=ISO-8859-1")),
+            "Café"
+        );
+        assert_eq!(title(with_meta, Some("text/html")), "Café");
+        assert_eq!(title(http_equiv, None), "Café");
+        // the header wins over the meta tag
+        assert_eq!(
+            title(
+                "<meta charset=latin1><title>Café</title>".as_bytes(),
+                Some("text/html;charset=utf-8")
+            ),
+            "Café"
+        );
+        // unknown charsets fall back to (lossy) UTF-8
+        assert_eq!(
+            title(header_only, Some("text/html; charset=nope")),
+            "Caf\u{FFFD}"
+        );
+    }
+
+    #[test]
+    fn default_icons() {
+        assert_eq!(
+            default_icon_url("https://Example.com:8080/some/page?q#top").as_deref(),
+            Some("https://example.com/favicon.ico")
+        );
+        assert_eq!(
+            default_icon_url("HTTP://user@blog.example.org").as_deref(),
+            Some("http://blog.example.org/favicon.ico")
+        );
+        assert_eq!(
+            default_icon_url("example.com/path").as_deref(),
+            Some("https://example.com/favicon.ico")
+        );
+        assert_eq!(default_icon_url("file:///home/me/page.html"), None);
+    }
+
+    #[test]
+    fn well_formed_urls() {
+        assert!(url_is_well_formed("https://example.com/a%20b"));
+        assert!(url_is_well_formed("example.com"));
+        assert!(url_is_well_formed("http://localhost:8080/x"));
+        assert!(!url_is_well_formed("not a url"));
+        assert!(!url_is_well_formed("https://"));
+        assert!(!url_is_well_formed("ht tp://example.com"));
+        assert!(!url_is_well_formed("://example.com"));
+        assert!(!url_is_well_formed("https://exa_mple.com"));
+    }
+
+    #[test]
+    fn domain_extraction() {
+        assert_eq!(
+            url_domain("https://Example.com/a/b").as_deref(),
+            Some("example.com")
+        );
+        assert_eq!(
+            url_domain("http://user@host.org:8080?q=1").as_deref(),
+            Some("host.org")
+        );
+        assert_eq!(
+            url_domain("example.com#top").as_deref(),
+            Some("example.com")
+        );
+        assert_eq!(url_domain("file:///home/user"), None);
+    }
+
+    #[test]
+    fn title_retries_after_transient_failure() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+
+        let server = std::thread::spawn(move || {
+            // first connection: close it without replying
+            let (stream, _) = listener.accept().unwrap();
+            drop(stream);
+
+            // second connection: reply properly
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buf = [0u8; 1024];
+            let _ = stream.read(&mut buf).unwrap();
+
+            let body = "<html><head><title>Hello</title></head></html>";
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            )
+            .unwrap();
+        });
+
+        let title = url_get_title_with_retries(&url, 1, Duration::from_millis(10));
+        server.join().unwrap();
+
+        assert_eq!(title.ok(), Some("Hello".to_string()));
+    }
+
+    #[test]
+    fn clean_title_patterns() {
+        let cases = [
+            (
+                "rust-lang/rust: Empowering everyone | GitHub",
+                "https://github.com/rust-lang/rust",
+                "rust-lang/rust: Empowering everyone",
+            ),
+            (
+                "GitHub - rust-lang/rust: Empowering everyone",
+                "https://github.com/rust-lang/rust",
+                "rust-lang/rust: Empowering everyone",
+            ),
+            (
+                "Rust (programming language) - Wikipedia",
+                "https://en.wikipedia.org/wiki/Rust",
+                "Rust (programming language)",
+            ),
+            (
+                "How do I do this? - Stack Overflow",
+                "https://stackoverflow.com/questions/1",
+                "How do I do this?",
+            ),
+            (
+                "Some headline - BBC News",
+                "https://www.bbc.co.uk/news/1",
+                "Some headline",
+            ),
+            // the suffix isn't the site name, so it stays
+            (
+                "Traits - The Rust Programming Language",
+                "https://doc.rust-lang.org/book/ch10-02-traits.html",
+                "Traits - The Rust Programming Language",
+            ),
+            // the site name has to be the segment, not just a part of it
+            (
+                "Ten tips for GitHub - The GitHub Blog",
+                "https://github.blog/2020-01-01-tips",
+                "Ten tips for GitHub - The GitHub Blog",
+            ),
+            (
+                "Githubbing | Some blog",
+                "https://github.com/some/blog",
+                "Githubbing | Some blog",
+            ),
+            // dangling separators are removed
+            ("Just a title |", "https://example.com", "Just a title"),
+            // nothing would be left
+            ("Example", "https://example.com", "Example"),
+            ("Example | ", "https://example.com", "Example"),
+        ];
+
+        for (raw, url, expected) in cases.iter() {
+            assert_eq!(clean_title(raw, url), *expected, "cleaning {:?}", raw);
+        }
+    }
+
+    #[test]
+    fn private_command_templates() {
+        let url = "https://example.com/?q=a b";
+        let command = |template| private_command(template, url);
+
+        assert_eq!(
+            command("firefox --private-window {url}"),
+            Ok((
+                "firefox".to_string(),
+                vec!["--private-window".to_string(), url.to_string()]
+            ))
+        );
+        assert_eq!(
+            command("  chromium   --incognito "),
+            Ok((
+                "chromium".to_string(),
+                vec!["--incognito".to_string(), url.to_string()]
+            ))
+        );
+        assert_eq!(
+            command("open-private --url={url}"),
+            Ok(("open-private".to_string(), vec![format!("--url={}", url)]))
+        );
+        assert!(command("   ").is_err());
+    }
+
+    #[test]
+    fn proxy_selection() {
+        let env = |vars: &'static [(&'static str, &'static str)]| {
+            move |name: &str| {
+                vars.iter()
+                    .find(|(var, _)| *var == name)
+                    .map(|(_, value)| value.to_string())
+            }
+        };
+
+        let vars = env(&[
+            ("HTTPS_PROXY", "http://secure:3128"),
+            ("all_proxy", "http://any:3128"),
+            ("no_proxy", "localhost, .internal.org"),
+        ]);
+
+        assert_eq!(
+            proxy_for("https://example.com", vars).as_deref(),
+            Some("http://secure:3128")
+        );
+        assert_eq!(
+            proxy_for("http://example.com", vars).as_deref(),
+            Some("http://any:3128")
+        );
+        assert_eq!(proxy_for("http://localhost:8080/", vars), None);
+        assert_eq!(proxy_for("https://wiki.internal.org", vars), None);
+        assert_eq!(proxy_for("https://example.com", env(&[])), None);
+    }
+
+    #[test]
+    fn bad_proxy_fails_cleanly() {
+        // bind and drop a listener, so the port is (almost certainly) closed
+        let closed = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let proxy = format!("http://{}", closed);
+
+        let started = std::time::Instant::now();
+        let refused = url_get_title_once("http://example.com/", Some(&proxy));
+        let unresolvable =
+            url_get_title_once("http://example.com/", Some("http://nonexistent.invalid:1"));
+
+        assert!(refused.is_err());
+        assert!(matches!(unresolvable, Err(ref e) if !e.retryable));
+        assert!(started.elapsed() < CONNECT_TIMEOUT);
+    }
+
+    #[test]
+    fn title_no_retry_on_client_error() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buf = [0u8; 1024];
+            let _ = stream.read(&mut buf).unwrap();
+
+            write!(
+                stream,
+                "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+            )
+            .unwrap();
+
+            // keep the listener alive, so a retry would connect and then hang waiting for a reply
+            listener
+        });
+
+        let title = url_get_title_with_retries(&url, 3, Duration::from_millis(10));
+        server.join().unwrap();
+
+        assert!(title.is_err());
     }
 }
@@ -1,4 +1,6 @@
-use clap::Parser;
+use clap::{ArgAction, Parser};
+
+use crate::bookmark::CopyFormat;
 
 #[derive(Parser)]
 pub struct Options {
@@ -9,8 +11,28 @@ pub struct Options {
     )]
     pub path: Option<String>,
 
+    #[arg(short = 'V', long, help = "print version and build information")]
+    pub version: bool,
+
+    #[arg(
+        long,
+        global = true,
+        help = "don't take the lock that keeps other instances from running at the same time; another instance changing the file meanwhile could corrupt it or lose changes"
+    )]
+    pub no_lock: bool,
+
+    #[arg(
+        short,
+        long,
+        global = true,
+        action = ArgAction::Count,
+        help = "show more information on stderr; repeat for debug traces (also settable via $COMPSCRIPTS_LOG)"
+    )]
+    pub verbose: u8,
+
+    // not required by clap itself, so `--version` works on its own
     #[command(subcommand)]
-    pub subcmd: SubCmd,
+    pub subcmd: Option<SubCmd>,
 }
 
 #[derive(Parser)]
@@ -19,10 +41,58 @@ pub enum SubCmd {
     Add(AddParameters),
 
     #[command(about = "adds the URLs from a newline-delimited bookmarks list file")]
-    AddFromFile(FileParameters),
+    AddFromFile(AddFromFileParameters),
 
     #[command(about = "opens an interactive menu for managing bookmarks using fzagnostic")]
-    Menu,
+    Menu(MenuParameters),
+
+    #[command(about = "edits the tags of a bookmark")]
+    Tag(TagParameters),
+
+    #[command(about = "merges the bookmarks from another bookmarks file into this one")]
+    Merge(FileParameters),
+
+    #[command(about = "shows some numbers about the bookmarks")]
+    Stats(StatsParameters),
+
+    #[command(about = "prints all bookmarks (including archived ones) to stdout as a JSON array")]
+    Export(ExportParameters),
+
+    #[command(about = "archives every bookmark added more than some days ago")]
+    ArchiveOld(ArchiveOldParameters),
+
+    #[command(about = "copies the url, title or a markdown link of a bookmark to the clipboard")]
+    Copy(CopyParameters),
+
+    #[command(
+        about = "opens a bookmark with $OPENER (or xdg-open), or in a private window with --private"
+    )]
+    Open(OpenParameters),
+
+    #[command(
+        about = "prints the bookmarks whose name, url or tags contain a query (case-insensitive)"
+    )]
+    Search(SearchParameters),
+
+    #[command(
+        about = "prints the bookmarks (including archived ones) in the order they're stored, a page at a time"
+    )]
+    List(ListParameters),
+}
+
+impl SubCmd {
+    /// Whether the subcommand never changes the file, so it can run without taking the lock.
+    pub fn is_read_only(&self) -> bool {
+        matches!(
+            self,
+            Self::Stats(_)
+                | Self::Export(_)
+                | Self::Copy(_)
+                | Self::Search(_)
+                | Self::Open(_)
+                | Self::List(_)
+        )
+    }
 }
 
 #[derive(Parser)]
@@ -32,9 +102,203 @@ pub struct AddParameters {
 
     #[arg(short, long, help = "the title of the bookmark")]
     pub title: Option<String>,
+
+    #[arg(
+        long,
+        help = "remove the site name from the fetched title (also settable via $BKMK_CLEAN_TITLES=1)"
+    )]
+    pub clean_title: bool,
+
+    #[arg(
+        long,
+        help = "the url of an icon for the bookmark (default: the site's /favicon.ico)"
+    )]
+    pub icon: Option<String>,
+
+    #[arg(
+        long,
+        help = "check that the icon exists, leaving the bookmark without one if it doesn't"
+    )]
+    pub fetch_icon: bool,
 }
 
 #[derive(Parser)]
 pub struct FileParameters {
     pub file: String,
 }
+
+#[derive(Parser)]
+pub struct AddFromFileParameters {
+    pub file: String,
+
+    #[arg(
+        long,
+        help = "abort on the first url that can't be added (including duplicates) instead of skipping it"
+    )]
+    pub strict: bool,
+}
+
+#[derive(Parser)]
+pub struct MenuParameters {
+    #[arg(help = "only show bookmarks whose name, url or tags contain this (case-insensitive)")]
+    pub query: Option<String>,
+
+    #[arg(
+        short,
+        long,
+        help = "select many bookmarks (until the selector is cancelled) and apply the action to all of them"
+    )]
+    pub multi: bool,
+
+    #[arg(
+        short,
+        long,
+        help = "sort the bookmarks by domain (then by name) and show the domain on each line"
+    )]
+    pub group_by_domain: bool,
+}
+
+#[derive(Parser)]
+pub struct TagParameters {
+    #[arg(help = "the ID of the bookmark")]
+    pub id: u32,
+
+    #[arg(short, long, help = "a comma-separated list of tags to add")]
+    pub add: Option<String>,
+
+    #[arg(short, long, help = "a comma-separated list of tags to remove")]
+    pub remove: Option<String>,
+
+    #[arg(
+        short,
+        long,
+        help = "a comma-separated list of tags that replaces the current ones"
+    )]
+    pub set: Option<String>,
+}
+
+#[derive(Parser)]
+pub struct ExportParameters {
+    #[arg(short, long, help = "indent the JSON output")]
+    pub pretty: bool,
+}
+
+#[derive(Parser)]
+pub struct ArchiveOldParameters {
+    #[arg(
+        short,
+        long,
+        default_value_t = 365,
+        help = "archive bookmarks added more than this many days ago"
+    )]
+    pub days: u64,
+
+    #[arg(
+        long,
+        help = "also archive bookmarks with no date (added before dates were tracked)"
+    )]
+    pub include_undated: bool,
+}
+
+#[derive(Parser)]
+pub struct StatsParameters {
+    #[arg(
+        short = 'n',
+        long,
+        default_value_t = 5,
+        help = "how many of the most used tags to show"
+    )]
+    pub top: usize,
+}
+
+#[derive(Parser)]
+pub struct CopyParameters {
+    #[arg(help = "the ID of the bookmark")]
+    pub id: u32,
+
+    #[arg(
+        short,
+        long,
+        default_value = "url",
+        value_parser = CopyFormat::parse,
+        help = "what to copy (url, title or md)"
+    )]
+    pub format: CopyFormat,
+}
+
+#[derive(Parser)]
+pub struct OpenParameters {
+    #[arg(help = "the ID of the bookmark")]
+    pub id: u32,
+
+    #[arg(
+        short,
+        long,
+        help = "open it in a private window, with the command on $BKMK_PRIVATE_CMD (e.g. `firefox --private-window {url}`)"
+    )]
+    pub private: bool,
+}
+
+#[derive(Parser)]
+pub struct SearchParameters {
+    #[arg(help = "the text to search for")]
+    pub query: String,
+
+    #[arg(
+        short,
+        long,
+        default_value = "plain",
+        value_parser = SearchFormat::parse,
+        help = "how to print the results: plain (ID, url and title separated by tabs) or json"
+    )]
+    pub format: SearchFormat,
+
+    #[arg(short = 'a', long, help = "also search archived bookmarks")]
+    pub include_archived: bool,
+}
+
+#[derive(Parser)]
+pub struct ListParameters {
+    #[arg(
+        long,
+        default_value_t = 0,
+        help = "how many bookmarks to skip from the start"
+    )]
+    pub offset: usize,
+
+    #[arg(
+        short,
+        long,
+        help = "how many bookmarks to print at most (default: all)"
+    )]
+    pub limit: Option<usize>,
+
+    #[arg(
+        short,
+        long,
+        default_value = "plain",
+        value_parser = SearchFormat::parse,
+        help = "how to print the bookmarks: plain (ID, url and title separated by tabs) or json"
+    )]
+    pub format: SearchFormat,
+}
+
+/// How `bkmk search` (and `bkmk list`) print bookmarks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchFormat {
+    Plain,
+    Json,
+}
+
+impl SearchFormat {
+    pub fn parse(string: &str) -> Result<Self, String> {
+        match string.trim().to_lowercase().as_str() {
+            "plain" => Ok(Self::Plain),
+            "json" => Ok(Self::Json),
+            other => Err(format!(
+                "unknown output format {:?} (expected plain or json)",
+                other
+            )),
+        }
+    }
+}
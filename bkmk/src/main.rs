@@ -1,46 +1,30 @@
-use std::io::{Read, Write};
+use std::borrow::Cow;
+use std::io::Read;
 use std::path::Path;
-use std::process::Command;
 
-use clap::Parser;
+use clap::error::ErrorKind;
+use clap::{CommandFactory, Parser};
 
 mod cli;
 use cli::*;
 
 mod bookmark;
-use bookmark::Bookmark;
+use bookmark::{parse_tag_list, url_domain, Bookmark, CopyFormat};
 
 mod manager;
 use manager::BookmarkManager;
 
-use utils::aliases::getenv;
-use utils::data::{JsonSerializer, Manager};
-use utils::error::{CliResult, ExitCode};
-use utils::misc::fzagnostic;
-
-fn fallback_string_if_needed<'a>(string: &'a str) -> &'a str {
-    for ch in string.chars() {
-        if !matches!(ch, '\n' | ' ' | '\t' | '\r') {
-            return string;
-        }
-    }
-
-    "[]"
-}
+use utils::data::{empty_or, JsonSerializer, Manager};
+use utils::error::{CliError, CliResult, ExitCode};
+use utils::format::StorageFormat;
+use utils::misc::{confirm, parse_leading_index, Fzagnostic, Picker};
+use utils::text;
 
 fn main() -> ExitCode {
-    let home = getenv("HOME").expect("HOME directory is unset - it is needed");
-
-    let _cache_dir: String = std::env::var("XDG_CACHE_DIR")
-        .ok()
-        .unwrap_or_else(|| format!("{}/.cache", home));
-
-    let data_dir: String = std::env::var("XDG_DATA_HOME")
-        .ok()
-        .or_else(|| std::env::var("XDG_DATA_DIR").ok())
-        .unwrap_or_else(|| format!("{}/.local/share", home));
-
-    let fallback_file = format!("{}/bkmk", data_dir);
+    let fallback_file = utils::paths::data_dir()
+        .join("bkmk")
+        .to_string_lossy()
+        .into_owned();
 
     let bkmk_file = match std::env::var("BKMK_FILE") {
         Err(_) => fallback_file,
@@ -49,32 +33,71 @@ fn main() -> ExitCode {
     };
 
     let options = cli::Options::parse();
+    utils::log::init(options.verbose);
 
-    // TODO: make this work again
-    // ctrlc::set_handler(|| panic!("CTRL-C found"));
+    if options.version {
+        utils::version::print_version("bkmk", env!("CARGO_PKG_VERSION"));
+        return ExitCode::SUCCESS;
+    }
+
+    let path_option = options.path;
+    let subcmd = match options.subcmd {
+        Some(subcmd) => subcmd,
+        None => cli::Options::command()
+            .error(ErrorKind::MissingSubcommand, "a subcommand is required")
+            .exit(),
+    };
+
+    utils::signal::release_locks_on_interrupt();
 
     const LOCK_NAME: &str = "bkmk";
-    let _lock = match utils::tmp::make_folder_lock(LOCK_NAME) {
-        Ok(lock) => lock,
-        Err(why) => {
-            eprintln!("Failed to create lock `{}`: {}", LOCK_NAME, why);
-            return ExitCode::new(1);
+    let _lock = if options.no_lock {
+        utils::warn!("running without a lock (--no-lock); changes from other instances running at the same time could corrupt the file or be lost");
+        None
+    } else if subcmd.is_read_only() {
+        utils::debug!("not locking, since the subcommand doesn't change anything");
+        None
+    } else {
+        match utils::tmp::make_folder_lock(LOCK_NAME) {
+            Ok(lock) => Some(lock),
+            Err(why) => {
+                eprintln!("Failed to create lock `{}`: {}", LOCK_NAME, why);
+                return ExitCode::new(1);
+            }
         }
     };
 
     // try blocks :))
     (|| -> CliResult {
-        let path_string = options.path.unwrap_or(bkmk_file);
+        let path_string = path_option.unwrap_or(bkmk_file);
         let path = Path::new(&path_string);
 
-        let contents = match utils::io::touch_read(&path) {
-            Ok(o) => o,
+        let contents = match utils::io::touch_read_lossy(&path) {
+            Ok((o, replaced)) => {
+                if replaced {
+                    utils::warn!("file has invalid UTF-8 data, which was replaced; it'll be lost if the file is saved");
+                }
+
+                o
+            }
             Err(e) => return CliResult::display_err(format!("Failed to load file: {}", e)),
         };
 
-        let new_contents = fallback_string_if_needed(&contents);
+        let contents = utils::io::sanitize_loaded(&contents);
+        if let Cow::Owned(_) = contents {
+            utils::warn!("file has control characters, which were removed; they'll be lost if the file is saved");
+        }
+
+        let new_contents = empty_or(&contents, "[]");
+        let loaded_format = StorageFormat::for_file(path, &contents);
+
+        // Keep the format the file already had, unless told otherwise.
+        let save_format = match StorageFormat::from_env("BKMK_FORMAT") {
+            Ok(format) => format.or(loaded_format).unwrap_or_default(),
+            Err(e) => return CliResult::display_err(format!("Invalid $BKMK_FORMAT: {}", e)),
+        };
 
-        let data: Vec<Bookmark> = match BookmarkManager::import(new_contents) {
+        let data: Vec<Bookmark> = match loaded_format.unwrap_or(save_format).import(new_contents) {
             Ok(o) => o,
             Err(e) => return CliResult::display_err(format!("Failed to parse file: {}", e)),
         };
@@ -84,14 +107,25 @@ fn main() -> ExitCode {
             Err(e) => return CliResult::display_err(e),
         };
 
-        match options.subcmd {
+        let result = match subcmd {
             SubCmd::Add(param) => subcmd_add(&mut manager, param),
             SubCmd::AddFromFile(param) => subcmd_add_from_file(&mut manager, param),
-            SubCmd::Menu => subcmd_menu(&mut manager),
-        }?;
+            SubCmd::Menu(param) => subcmd_menu(&mut manager, param, &Fzagnostic),
+            SubCmd::Tag(param) => subcmd_tag(&mut manager, param),
+            SubCmd::Merge(param) => subcmd_merge(&mut manager, param),
+            SubCmd::Stats(param) => subcmd_stats(&manager, param),
+            SubCmd::Export(param) => subcmd_export(&manager, param),
+            SubCmd::ArchiveOld(param) => subcmd_archive_old(&mut manager, param),
+            SubCmd::Copy(param) => subcmd_copy(&manager, param),
+            SubCmd::Open(param) => subcmd_open(&manager, param),
+            SubCmd::Search(param) => subcmd_search(&manager, param),
+            SubCmd::List(param) => subcmd_list(&manager, param),
+        };
 
-        match manager.save_if_modified(&path) {
-            Ok(_) => CliResult::EMPTY_OK,
+        // Whatever was changed before an error is still saved, e.g. when a batch action from the menu fails on only
+        // some of the bookmarks.
+        match manager.save_if_modified(&path, save_format) {
+            Ok(_) => result,
             Err(e) => CliResult::display_err(format!("Failed to save changes to file: {}", e)),
         }
     })()
@@ -99,14 +133,41 @@ fn main() -> ExitCode {
 }
 
 pub fn subcmd_add(manager: &mut BookmarkManager, param: AddParameters) -> CliResult {
+    // no point in fetching anything for a bookmark that won't be added
+    if let Some(id) = manager.already_has_url(&param.url) {
+        return CliResult::display_err(format!(
+            "Repeated url with bookmark #{} ({})",
+            id, param.url
+        ));
+    }
+
+    let icon = param
+        .icon
+        .clone()
+        .or_else(|| bookmark::default_icon_url(&param.url))
+        .filter(|icon| match param.fetch_icon {
+            true => match bookmark::url_check_exists(icon) {
+                Ok(()) => true,
+                Err(e) => {
+                    utils::warn!("not using icon {:?}: {}", icon, e);
+                    false
+                }
+            },
+            false => true,
+        });
+
     CliResult::from_display_result(if let Some(title) = param.title {
-        manager.add_bookmark(title, param.url, Vec::new())
+        manager.add_bookmark(title, param.url, Vec::new(), icon)
     } else {
-        manager.add_bookmark_from_url(param.url, true)
+        let clean_title = param.clean_title || bookmark::clean_titles_from_env();
+        manager.add_bookmark_from_url(param.url, true, clean_title, icon)
     })
 }
 
-pub fn subcmd_add_from_file(manager: &mut BookmarkManager, param: FileParameters) -> CliResult {
+pub fn subcmd_add_from_file(
+    manager: &mut BookmarkManager,
+    param: AddFromFileParameters,
+) -> CliResult {
     let path = Path::new(&param.file);
     let mut file = match utils::io::touch_and_open(path) {
         Ok(file) => file,
@@ -121,75 +182,335 @@ pub fn subcmd_add_from_file(manager: &mut BookmarkManager, param: FileParameters
         }
     };
 
-    for url in contents
+    let clean_title = bookmark::clean_titles_from_env();
+
+    let urls = contents
         .split('\n')
         .map(str::trim)
-        .filter(|line| !line.is_empty())
-    {
-        if let Err(e) = manager.add_bookmark_from_url(url.into(), true) {
-            return CliResult::display_err(e);
+        .filter(|line| !line.is_empty());
+
+    let report = match manager.add_urls(urls, param.strict, |manager, url| {
+        manager.add_bookmark_from_url(
+            url.into(),
+            false,
+            clean_title,
+            bookmark::default_icon_url(url),
+        )
+    }) {
+        Ok(report) => report,
+        Err(e) => return CliResult::display_err(e),
+    };
+
+    for (url, id) in &report.duplicates {
+        eprintln!("Skipped {:?}: already bookmarked as #{}", url, id);
+    }
+
+    for (url, e) in &report.failed {
+        eprintln!("Failed {:?}: {}", url, e);
+    }
+
+    eprintln!(
+        "Imported {:?}: {} added, {} skipped (repeated url), {} failed",
+        param.file,
+        report.added,
+        report.duplicates.len(),
+        report.failed.len()
+    );
+
+    CliResult::EMPTY_OK
+}
+
+pub fn subcmd_merge(manager: &mut BookmarkManager, param: FileParameters) -> CliResult {
+    let contents = match std::fs::read_to_string(&param.file) {
+        Ok(s) => s,
+        Err(e) => return CliResult::display_err(format!("failed to read file: {}", e)),
+    };
+
+    let contents = utils::io::sanitize_loaded(&contents);
+    if let Cow::Owned(_) = contents {
+        utils::warn!(
+            "{:?} has control characters, which were ignored",
+            param.file
+        );
+    }
+
+    let contents = empty_or(&contents, "[]");
+    let format = StorageFormat::for_file(Path::new(&param.file), contents).unwrap_or_default();
+    let data: Vec<Bookmark> = match format.import(contents) {
+        Ok(o) => o,
+        Err(e) => return CliResult::display_err(format!("failed to parse file: {}", e)),
+    };
+
+    let report = manager.merge_from(data);
+
+    eprintln!(
+        "Merged {:?}: {} added ({} with reassigned IDs), {} skipped (repeated url)",
+        param.file, report.added, report.reassigned, report.skipped
+    );
+
+    CliResult::EMPTY_OK
+}
+
+pub fn subcmd_tag(manager: &mut BookmarkManager, param: TagParameters) -> CliResult {
+    let parse = |list: Option<String>| list.as_deref().map_or_else(Vec::new, parse_tag_list);
+
+    let add = parse(param.add);
+    let remove = parse(param.remove);
+    let set = param.set.as_deref().map(parse_tag_list);
+
+    CliResult::from_display_result(manager.edit_tags(param.id, &add, &remove, set.as_deref()))
+}
+
+pub fn subcmd_stats(manager: &BookmarkManager, param: StatsParameters) -> CliResult {
+    let stats = manager.stats();
+
+    let lines = [
+        ("Bookmarks", stats.total),
+        ("Archived", stats.archived),
+        ("With tags", stats.tagged),
+        ("Without tags", stats.total - stats.tagged),
+        ("Domains", stats.domains),
+    ];
+
+    for (label, value) in lines.iter() {
+        println!("{:<14}{:>6}", format!("{}:", label), value);
+    }
+
+    if !stats.tag_frequency.is_empty() && param.top > 0 {
+        println!();
+        println!("Top tags:");
+
+        let top = &stats.tag_frequency[..param.top.min(stats.tag_frequency.len())];
+        let width = top.iter().map(|(tag, _)| tag.len()).max().unwrap_or(0);
+
+        for (tag, count) in top {
+            println!("  {:<width$}  {:>4}", tag, count, width = width);
         }
     }
 
     CliResult::EMPTY_OK
 }
 
-pub fn subcmd_menu(manager: &mut BookmarkManager) -> CliResult {
-    let not_archived: Vec<&Bookmark> = manager
-        .data()
+pub fn subcmd_archive_old(manager: &mut BookmarkManager, param: ArchiveOldParameters) -> CliResult {
+    let cutoff = bookmark::now_timestamp().saturating_sub(param.days.saturating_mul(24 * 60 * 60));
+    let old = manager.old_bookmarks(cutoff, param.include_undated);
+
+    if old.is_empty() {
+        eprintln!(
+            "There are no unarchived bookmarks older than {} days",
+            param.days
+        );
+        return CliResult::EMPTY_OK;
+    }
+
+    eprintln!("Bookmarks to be archived:");
+    for bkmk in &old {
+        eprintln!("  #{}: {:?} ({})", bkmk.id, bkmk.name, bkmk.url);
+    }
+
+    if !confirm(true)? {
+        return CliResult::silent_err();
+    }
+
+    let count = manager.archive_old(cutoff, param.include_undated);
+    eprintln!("Archived {} bookmark(s)", count);
+
+    CliResult::EMPTY_OK
+}
+
+pub fn subcmd_copy(manager: &BookmarkManager, param: CopyParameters) -> CliResult {
+    match manager.interact(param.id, |bkmk| param.format.text(bkmk)) {
+        Some(text) => match utils::clipboard::copy(&text) {
+            Ok(()) => CliResult::EMPTY_OK,
+            Err(e) => CliResult::display_err(format!("Failed to copy to clipboard: {}", e)),
+        },
+        None => CliResult::display_err(format!("No bookmark with ID #{}", param.id)),
+    }
+}
+
+pub fn subcmd_open(manager: &BookmarkManager, param: OpenParameters) -> CliResult {
+    match manager.interact(param.id, |bkmk| bkmk.url.clone()) {
+        Some(url) => CliResult::from_display_result(bookmark::open_url(&url, param.private)),
+        None => CliResult::display_err(format!("No bookmark with ID #{}", param.id)),
+    }
+}
+
+/// Prints the whole database as a JSON array, whatever the format of the file is. Never changes anything.
+pub fn subcmd_export(manager: &BookmarkManager, param: ExportParameters) -> CliResult {
+    match JsonSerializer::export(manager, param.pretty) {
+        Ok(json) => {
+            println!("{}", json);
+            CliResult::EMPTY_OK
+        }
+        Err(e) => CliResult::display_err(format!("failed to export bookmarks: {}", e)),
+    }
+}
+
+/// Prints the bookmarks matching a query, for use in scripts. Never changes anything.
+pub fn subcmd_search(manager: &BookmarkManager, param: SearchParameters) -> CliResult {
+    let found = manager.search(&param.query, param.include_archived);
+
+    print_bookmarks(&found, param.format)
+}
+
+/// Prints a page of the bookmarks, for use in scripts. Never changes anything.
+pub fn subcmd_list(manager: &BookmarkManager, param: ListParameters) -> CliResult {
+    let page: Vec<&Bookmark> = manager
+        .page(param.offset, param.limit.unwrap_or(usize::MAX))
         .iter()
-        .filter(|bkmk| !bkmk.archived)
         .collect();
 
+    print_bookmarks(&page, param.format)
+}
+
+/// Prints bookmarks for `bkmk search` and `bkmk list`.
+fn print_bookmarks(bookmarks: &[&Bookmark], format: SearchFormat) -> CliResult {
+    match format {
+        SearchFormat::Plain => {
+            for bkmk in bookmarks {
+                println!("{}\t{}\t{}", bkmk.id, bkmk.url, bkmk.name);
+            }
+        }
+        SearchFormat::Json => match serde_json::to_string(bookmarks) {
+            Ok(json) => println!("{}", json),
+            Err(e) => return CliResult::display_err(format!("failed to export bookmarks: {}", e)),
+        },
+    }
+
+    CliResult::EMPTY_OK
+}
+
+/// Opens a bookmark (in a private window, if `private` is true), as a menu action.
+fn open_action(manager: &mut BookmarkManager, id: u32, private: bool) -> CliResult {
+    let url = manager.interact(id, |bkmk| bkmk.url.clone()).unwrap();
+
+    CliResult::from_display_result(bookmark::open_url(&url, private))
+}
+
+/// Copies part of a bookmark to the clipboard, as a menu action.
+fn copy_action(manager: &mut BookmarkManager, id: u32, format: CopyFormat) -> CliResult {
+    let text = manager.interact(id, |bkmk| format.text(bkmk)).unwrap();
+
+    match utils::clipboard::copy(&text) {
+        Ok(()) => CliResult::EMPTY_OK,
+        Err(e) => CliResult::display_err(format!("Failed to copy to clipboard: {}", e)),
+    }
+}
+
+pub fn subcmd_menu(
+    manager: &mut BookmarkManager,
+    param: MenuParameters,
+    picker: &dyn Picker,
+) -> CliResult {
+    let mut not_archived = manager.search(param.query.as_deref().unwrap_or(""), false);
+
     if not_archived.len() == 0 {
-        return CliResult::display_err(format!("There are no unarchived bookmarks to select"));
+        return CliResult::display_err(match param.query {
+            Some(query) => format!("There are no unarchived bookmarks matching {:?}", query),
+            None => format!("There are no unarchived bookmarks to select"),
+        });
+    }
+
+    // Lines are identified by their position on `not_archived`, so sorting it here keeps the selection working.
+    if param.group_by_domain {
+        not_archived.sort_by_cached_key(|bkmk| {
+            let domain = url_domain(&bkmk.url);
+            (domain.is_none(), domain, bkmk.name.to_lowercase())
+        });
     }
 
-    let chosen_id = {
-        match fzagnostic(
-            "Bookmark:",
-            not_archived
+    let line_index = |line: &str| -> Result<usize, CliError> {
+        match parse_leading_index(line)? {
+            i if i < not_archived.len() => Ok(i),
+            i => Err(CliError::from_display(format!(
+                "the menu returned an invalid index: {}",
+                i
+            ))),
+        }
+    };
+
+    let menu_line = |i: usize, bkmk: &Bookmark| {
+        if param.group_by_domain {
+            let domain = url_domain(&bkmk.url).unwrap_or_else(|| "-".into());
+            format!(
+                "{:>3} {} {} ({})",
+                i,
+                text::fit(&domain, 24),
+                text::fit(&bkmk.name, 70),
+                bkmk.url
+            )
+        } else {
+            format!("{:>3} {} ({})", i, text::fit(&bkmk.name, 95), bkmk.url)
+        }
+    };
+
+    let chosen_ids: Vec<u32> = if param.multi {
+        // Keep asking for bookmarks until the selector is cancelled. Selectors that support picking many lines at once
+        // also work, since every returned line is parsed.
+        let mut chosen: Vec<usize> = Vec::new();
+
+        loop {
+            let remaining = not_archived
                 .iter()
                 .enumerate()
-                .map(|(i, bkmk)| format!("{:>3} {:<95} ({})", i, bkmk.name, bkmk.url)),
-            30,
-        ) {
-            Ok(s) => {
-                not_archived[s
-                    .trim()
-                    .split(" ")
-                    .next()
-                    .unwrap()
-                    .parse::<usize>()
-                    .unwrap()]
-                .id
+                .filter(|(i, _)| !chosen.contains(i))
+                .map(|(i, bkmk)| menu_line(i, bkmk))
+                .collect::<Vec<_>>();
+
+            if remaining.is_empty() {
+                break;
+            }
+
+            match picker.pick(
+                &format!("Bookmark ({} chosen, cancel to finish):", chosen.len()),
+                &remaining,
+                30,
+            ) {
+                Ok(s) => {
+                    for line in s.lines().filter(|l| !l.trim().is_empty()) {
+                        let i = CliResult::new(line_index(line))?;
+
+                        if !chosen.contains(&i) {
+                            chosen.push(i);
+                        }
+                    }
+                }
+                Err(CliError::Silent) => break,
+                Err(err) => return CliResult { inner: Err(err) },
             }
+        }
+
+        if chosen.is_empty() {
+            return CliResult::silent_err();
+        }
+
+        chosen.iter().map(|&i| not_archived[i].id).collect()
+    } else {
+        let lines: Vec<String> = not_archived
+            .iter()
+            .enumerate()
+            .map(|(i, bkmk)| menu_line(i, bkmk))
+            .collect();
+
+        match picker.pick("Bookmark:", &lines, 30) {
+            Ok(s) => vec![not_archived[CliResult::new(line_index(&s))?].id],
             Err(err) => return CliResult { inner: Err(err) },
         }
     };
 
     type ActionSig = fn(&mut BookmarkManager, u32) -> CliResult;
 
-    static ACTIONS: [(&str, ActionSig); 5] = [
-        ("open (via $OPENER || xdg-open)", |manager, id| {
-            manager
-                .interact(id, |bkmk| {
-                    let opener = getenv("OPENER").unwrap_or("xdg-open".into());
-
-                    match Command::new(opener).args(&[&bkmk.url]).spawn() {
-                        Ok(mut child) => match child.wait().unwrap().code().unwrap() {
-                            0 => CliResult::EMPTY_OK,
-                            _ => CliResult::silent_err(),
-                        },
-                        Err(why) => CliResult::display_err(format!(
-                            "failed to start opener command: {}",
-                            why
-                        )),
-                    }
-                })
-                .unwrap()
+    // (name, is destructive, function)
+    static ACTIONS: [(&str, bool, ActionSig); 8] = [
+        ("open (via $OPENER || xdg-open)", false, |manager, id| {
+            open_action(manager, id, false)
         }),
-        ("archive", |manager, id| {
+        (
+            "open in a private window (via $BKMK_PRIVATE_CMD)",
+            false,
+            |manager, id| open_action(manager, id, true),
+        ),
+        ("archive", true, |manager, id| {
             manager
                 .interact_mut(id, |bkmk| {
                     bkmk.archived = true;
@@ -198,33 +519,16 @@ pub fn subcmd_menu(manager: &mut BookmarkManager) -> CliResult {
                 })
                 .unwrap()
         }),
-        ("copy to clipboard (via xclip)", |manager, id| {
-            manager
-                .interact_mut(id, |bkmk| {
-                    match Command::new("xclip")
-                        .args(&["-sel", "clipboard"])
-                        .stdin(std::process::Stdio::piped())
-                        .spawn()
-                    {
-                        Ok(mut child) => {
-                            let stdin = child.stdin.as_mut().unwrap();
-                            write!(stdin, "{}", bkmk.url).unwrap();
-
-                            if child.wait().unwrap().code().unwrap() == 0 {
-                                CliResult::EMPTY_OK
-                            } else {
-                                CliResult::display_err("failed to save to clipboard")
-                            }
-                        }
-                        Err(why) => CliResult::display_err(format!(
-                            "failed to start xclip command: {}",
-                            why
-                        )),
-                    }
-                })
-                .unwrap()
+        ("copy url", false, |manager, id| {
+            copy_action(manager, id, CopyFormat::Url)
+        }),
+        ("copy title", false, |manager, id| {
+            copy_action(manager, id, CopyFormat::Title)
+        }),
+        ("copy markdown link", false, |manager, id| {
+            copy_action(manager, id, CopyFormat::Markdown)
         }),
-        ("delete", |manager, id| {
+        ("delete", true, |manager, id| {
             let pos = manager
                 .data()
                 .iter()
@@ -235,7 +539,7 @@ pub fn subcmd_menu(manager: &mut BookmarkManager) -> CliResult {
 
             CliResult::EMPTY_OK
         }),
-        ("edit title", |manager, id| {
+        ("edit title", false, |manager, id| {
             manager
                 .interact_mut(id, |bkmk| {
                     match utils::tmp::edit_text(&bkmk.name, Some("txt")) {
@@ -261,21 +565,148 @@ pub fn subcmd_menu(manager: &mut BookmarkManager) -> CliResult {
     ];
 
     let action_id = {
-        match fzagnostic(
-            "Action:",
-            ACTIONS
-                .iter()
-                .enumerate()
-                .map(|(i, (name, _))| format!("{} {}", i, name)),
-            30,
-        ) {
-            Ok(s) => s.split(" ").nth(0).unwrap().parse::<usize>().unwrap(),
+        let lines: Vec<String> = ACTIONS
+            .iter()
+            .enumerate()
+            .map(|(i, (name, _, _))| format!("{} {}", i, name))
+            .collect();
+
+        match picker.pick("Action:", &lines, 30) {
+            Ok(s) => CliResult::new(parse_leading_index(&s))?,
             Err(err) => return CliResult { inner: Err(err) },
         }
     };
 
     match ACTIONS.get(action_id) {
-        Some((_, func)) => func(manager, chosen_id),
+        Some((name, destructive, func)) => {
+            if *destructive && chosen_ids.len() > 1 {
+                eprintln!("About to {} {} bookmarks.", name, chosen_ids.len());
+
+                if !confirm(false)? {
+                    return CliResult::silent_err();
+                }
+            }
+
+            if let [id] = chosen_ids[..] {
+                return func(manager, id);
+            }
+
+            // Keep going on errors, so one failing bookmark doesn't stop (nor undo) the action on the others.
+            let mut failed = 0;
+            for &id in &chosen_ids {
+                match func(manager, id).inner {
+                    Ok(()) => (),
+                    Err(CliError::Silent) => failed += 1,
+                    Err(CliError::Display(why)) => {
+                        eprintln!("Error on bookmark #{}: {}", id, why);
+                        failed += 1;
+                    }
+                }
+            }
+
+            match failed {
+                0 => CliResult::EMPTY_OK,
+                _ => CliResult::display_err(format!(
+                    "failed to {} {} of {} bookmarks",
+                    name,
+                    failed,
+                    chosen_ids.len()
+                )),
+            }
+        }
         None => CliResult::display_err(format!("Invalid action ID: {}", action_id)),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use utils::misc::ScriptedPicker;
+
+    fn sample_manager() -> BookmarkManager {
+        let bookmark = |id, name: &str, url: &str| Bookmark {
+            id,
+            archived: false,
+            name: name.into(),
+            url: url.into(),
+            tags: Vec::new(),
+            added: None,
+            icon: None,
+        };
+
+        BookmarkManager::new(vec![
+            bookmark(0, "Rust", "https://rust-lang.org"),
+            bookmark(1, "Docs", "https://docs.rs"),
+            bookmark(2, "Weather", "https://wttr.in"),
+        ])
+        .unwrap()
+    }
+
+    fn menu(
+        manager: &mut BookmarkManager,
+        query: Option<&str>,
+        multi: bool,
+        picker: &ScriptedPicker,
+    ) -> bool {
+        let param = MenuParameters {
+            query: query.map(String::from),
+            multi,
+            group_by_domain: false,
+        };
+
+        subcmd_menu(manager, param, picker).inner.is_ok()
+    }
+
+    #[test]
+    fn menu_archives_the_chosen_bookmark() {
+        let mut manager = sample_manager();
+        let picker = ScriptedPicker::new(vec![Some("Docs"), Some("archive")]);
+
+        assert!(menu(&mut manager, None, false, &picker));
+        let archived: Vec<u32> = manager
+            .data()
+            .iter()
+            .filter(|b| b.archived)
+            .map(|b| b.id)
+            .collect();
+        assert_eq!(archived, vec![1]);
+        assert_eq!(picker.prompts(), vec!["Bookmark:", "Action:"]);
+    }
+
+    #[test]
+    fn menu_query_and_cancelling() {
+        let mut manager = sample_manager();
+
+        // only the matching bookmark is on the menu, so picking another one fails
+        let picker = ScriptedPicker::new(vec![Some("Rust"), Some("delete")]);
+        assert!(!menu(&mut manager, Some("wttr"), false, &picker));
+
+        // cancelling the action menu changes nothing
+        let picker = ScriptedPicker::new(vec![Some("Weather"), None]);
+        assert!(!menu(&mut manager, Some("wttr"), false, &picker));
+        assert_eq!(manager.data().len(), 3);
+
+        let picker = ScriptedPicker::new(vec![Some("Weather"), Some("delete")]);
+        assert!(menu(&mut manager, Some("wttr"), false, &picker));
+        assert!(manager.data().iter().all(|b| b.id != 2));
+    }
+
+    #[test]
+    fn menu_multi_selection_ends_when_cancelled() {
+        let mut manager = sample_manager();
+        let picker = ScriptedPicker::new(vec![Some("Rust"), None, Some("archive")]);
+
+        assert!(menu(&mut manager, None, true, &picker));
+        assert!(manager.data()[0].archived);
+        assert!(!manager.data()[1].archived);
+        assert_eq!(
+            picker.prompts(),
+            vec![
+                "Bookmark (0 chosen, cancel to finish):",
+                "Bookmark (1 chosen, cancel to finish):",
+                "Action:"
+            ]
+        );
+    }
+}
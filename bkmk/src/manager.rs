@@ -1,8 +1,77 @@
 use std::collections::HashSet;
 use std::path::Path;
 
-use crate::bookmark::Bookmark;
-use utils::data::{data_serialize::SaveToFileError, Id, JsonSerializer, Manager};
+use crate::bookmark::{normalize_tags, now_timestamp, url_domain, url_is_well_formed, Bookmark};
+use utils::data::{Id, Manager};
+use utils::format::{SaveError, StorageFormat};
+
+/// The outcome of a [`BookmarkManager::merge_from`] call.
+///
+/// [`BookmarkManager::merge_from`]: BookmarkManager::merge_from
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct MergeReport {
+    /// How many bookmarks were added.
+    pub added: usize,
+    /// How many bookmarks were skipped because their url was already present.
+    pub skipped: usize,
+    /// How many of the added bookmarks had to get a new ID because theirs was already in use.
+    pub reassigned: usize,
+}
+
+/// The outcome of a [`BookmarkManager::add_urls`] call.
+///
+/// [`BookmarkManager::add_urls`]: BookmarkManager::add_urls
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ImportReport {
+    /// How many bookmarks were added.
+    pub added: usize,
+    /// The urls that were skipped because they were already present (or repeated on the list), along with the ID of
+    /// the bookmark that has them.
+    pub duplicates: Vec<(String, Id)>,
+    /// The urls that couldn't be added (malformed, or their title couldn't be fetched), along with why.
+    pub failed: Vec<(String, String)>,
+}
+
+/// Summary numbers about a bookmark collection, as computed by [`BookmarkManager::stats`].
+///
+/// [`BookmarkManager::stats`]: BookmarkManager::stats
+#[derive(Debug, PartialEq, Eq)]
+pub struct Stats {
+    /// How many bookmarks there are, archived or not.
+    pub total: usize,
+    /// How many bookmarks are archived.
+    pub archived: usize,
+    /// How many bookmarks have at least one tag.
+    pub tagged: usize,
+    /// How many distinct domains the bookmarks point to.
+    pub domains: usize,
+    /// Every tag along with how many bookmarks have it, most used first (ties are sorted by name).
+    pub tag_frequency: Vec<(String, usize)>,
+}
+
+/// Counts how many bookmarks have each tag, sorting the result by frequency (most used first) and then by name.
+pub fn tag_frequency(bookmarks: &[Bookmark]) -> Vec<(String, usize)> {
+    let mut counts: Vec<(String, usize)> = Vec::new();
+
+    for tag in bookmarks.iter().flat_map(|b| b.tags.iter()) {
+        match counts.iter_mut().find(|(t, _)| t == tag) {
+            Some((_, count)) => *count += 1,
+            None => counts.push((tag.clone(), 1)),
+        }
+    }
+
+    counts.sort_by(|(tag_a, a), (tag_b, b)| b.cmp(a).then(tag_a.cmp(tag_b)));
+    counts
+}
+
+/// Counts how many distinct domains the bookmarks point to. Bookmarks whose url has no host aren't counted.
+pub fn domain_count(bookmarks: &[Bookmark]) -> usize {
+    bookmarks
+        .iter()
+        .filter_map(|b| url_domain(&b.url))
+        .collect::<HashSet<_>>()
+        .len()
+}
 
 pub struct BookmarkManager {
     data: Vec<Bookmark>,
@@ -77,6 +146,7 @@ impl BookmarkManager {
         name: String,
         url: String,
         tags: Vec<String>,
+        icon: Option<String>,
     ) -> Result<(), String> {
         if let Some(id) = self.already_has_url(&url) {
             return Err(format!("Repeated url with bookmark #{}", id));
@@ -87,9 +157,11 @@ impl BookmarkManager {
         self.data_mut().push(Bookmark {
             id: free_id,
             name: name,
+            icon: icon,
             url: url,
             tags: tags,
             archived: false,
+            added: Some(now_timestamp()),
         });
 
         self.used_ids.insert(free_id);
@@ -104,15 +176,27 @@ impl BookmarkManager {
     ///
     /// If `read_line` is true and the url couldn't be fetched, the user will be prompted to type a new title.
     ///
+    /// If `clean_title` is true, the site name is removed from the fetched title (see [`clean_title`]). Typed titles are
+    /// kept as-is.
+    ///
+    /// [`clean_title`]: crate::bookmark::clean_title
+    ///
     /// ## Error
     ///
     /// Returns an error if a bookmark with the same url already exists.
-    pub fn add_bookmark_from_url(&mut self, url: String, read_line: bool) -> Result<(), String> {
+    pub fn add_bookmark_from_url(
+        &mut self,
+        url: String,
+        read_line: bool,
+        clean_title: bool,
+        icon: Option<String>,
+    ) -> Result<(), String> {
         if let Some(id) = self.already_has_url(&url) {
             return Err(format!("Repeated url with bookmark #{} ({})", id, url));
         }
 
         let title = match crate::bookmark::url_get_title(&url) {
+            Ok(title) if clean_title => crate::bookmark::clean_title(&title, &url),
             Ok(title) => title,
             Err(e) => {
                 if read_line {
@@ -145,9 +229,11 @@ impl BookmarkManager {
         self.data_mut().push(Bookmark {
             id: free_id,
             name: title,
+            icon: icon,
             url: url,
             tags: Vec::new(),
             archived: false,
+            added: Some(now_timestamp()),
         });
         self.used_ids.insert(free_id);
         self.after_interact_mut_hook();
@@ -155,11 +241,441 @@ impl BookmarkManager {
         Ok(())
     }
 
-    pub fn save_if_modified(&self, path: &Path) -> Result<(), SaveToFileError> {
+    /// Edits the tags of a bookmark.
+    ///
+    /// If `set` is specified, it replaces the whole tag set before `add` and `remove` are applied. The resulting set is
+    /// normalized (see [`normalize_tags`]).
+    ///
+    /// ## Error
+    ///
+    /// Returns an error if there's no bookmark with the specified ID.
+    ///
+    /// [`normalize_tags`]: crate::bookmark::normalize_tags
+    pub fn edit_tags(
+        &mut self,
+        id: Id,
+        add: &[String],
+        remove: &[String],
+        set: Option<&[String]>,
+    ) -> Result<(), String> {
+        self.interact_mut(id, |bkmk| {
+            let base = match set {
+                Some(set) => set,
+                None => &bkmk.tags,
+            };
+
+            let removed = normalize_tags(remove);
+            bkmk.tags = normalize_tags(base.iter().chain(add.iter()))
+                .into_iter()
+                .filter(|tag| !removed.contains(tag))
+                .collect();
+        })
+        .ok_or_else(|| format!("No bookmark with ID #{}", id))
+    }
+
+    /// Appends bookmarks from another database into this one.
+    ///
+    /// Bookmarks whose url is already present are skipped, and bookmarks whose ID is already in use are given the
+    /// lowest free one.
+    pub fn merge_from(&mut self, other: Vec<Bookmark>) -> MergeReport {
+        let mut report = MergeReport::default();
+
+        for mut bookmark in other {
+            if self.already_has_url(&bookmark.url).is_some() {
+                report.skipped += 1;
+                continue;
+            }
+
+            if self.used_ids.contains(&bookmark.id) {
+                bookmark.id = utils::misc::find_lowest_free_value(&self.used_ids);
+                report.reassigned += 1;
+            }
+
+            self.used_ids.insert(bookmark.id);
+            self.data.push(bookmark);
+            report.added += 1;
+        }
+
+        if report.added > 0 {
+            self.after_interact_mut_hook();
+        }
+
+        report
+    }
+
+    /// Adds a bookmark for each url of `urls` with `add` (usually a wrapper around [`add_bookmark_from_url`]), going
+    /// past the urls that are duplicates, malformed or fail to be added, which are collected on the report.
+    ///
+    /// If `strict` is true, the first of those aborts the import with an error instead. The bookmarks added before it
+    /// are kept.
+    ///
+    /// [`add_bookmark_from_url`]: BookmarkManager::add_bookmark_from_url
+    pub fn add_urls<'a, I, F>(
+        &mut self,
+        urls: I,
+        strict: bool,
+        mut add: F,
+    ) -> Result<ImportReport, String>
+    where
+        I: IntoIterator<Item = &'a str>,
+        F: FnMut(&mut Self, &str) -> Result<(), String>,
+    {
+        let mut report = ImportReport::default();
+
+        for url in urls {
+            if let Some(id) = self.already_has_url(url) {
+                if strict {
+                    return Err(format!("Repeated url with bookmark #{} ({})", id, url));
+                }
+
+                report.duplicates.push((url.into(), id));
+                continue;
+            }
+
+            let result = match url_is_well_formed(url) {
+                true => add(self, url),
+                false => Err("malformed url".into()),
+            };
+
+            match result {
+                Ok(()) => report.added += 1,
+                Err(e) if strict => return Err(format!("{} ({})", e, url)),
+                Err(e) => report.failed.push((url.into(), e)),
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Returns the bookmarks that aren't archived yet and were added before `cutoff` (see [`Bookmark::added_before`]).
+    ///
+    /// [`Bookmark::added_before`]: Bookmark::added_before
+    pub fn old_bookmarks(&self, cutoff: u64, include_undated: bool) -> Vec<&Bookmark> {
+        self.data
+            .iter()
+            .filter(|b| !b.archived && b.added_before(cutoff, include_undated))
+            .collect()
+    }
+
+    /// Returns the bookmarks that match `query` (see [`Bookmark::matches_query`]), leaving out archived ones unless
+    /// `include_archived` is set. An empty query matches everything.
+    pub fn search(&self, query: &str, include_archived: bool) -> Vec<&Bookmark> {
+        self.data
+            .iter()
+            .filter(|b| include_archived || !b.archived)
+            .filter(|b| b.matches_query(query))
+            .collect()
+    }
+
+    /// Archives every bookmark returned by [`old_bookmarks`], returning how many were archived.
+    ///
+    /// [`old_bookmarks`]: BookmarkManager::old_bookmarks
+    pub fn archive_old(&mut self, cutoff: u64, include_undated: bool) -> usize {
+        let mut count = 0;
+
+        for bookmark in self.data_mut() {
+            if !bookmark.archived && bookmark.added_before(cutoff, include_undated) {
+                bookmark.archived = true;
+                count += 1;
+            }
+        }
+
+        if count > 0 {
+            self.after_interact_mut_hook();
+        }
+
+        count
+    }
+
+    /// Computes some summary numbers about the bookmarks.
+    pub fn stats(&self) -> Stats {
+        Stats {
+            total: self.data.len(),
+            archived: self.data.iter().filter(|b| b.archived).count(),
+            tagged: self.data.iter().filter(|b| !b.tags.is_empty()).count(),
+            domains: domain_count(&self.data),
+            tag_frequency: tag_frequency(&self.data),
+        }
+    }
+
+    /// Saves the bookmarks to `path` if anything changed, sorting them by ID first so the file stays ordered.
+    pub fn save_if_modified(
+        &mut self,
+        path: &Path,
+        format: StorageFormat,
+    ) -> Result<(), SaveError> {
         if self.modified {
-            self.save_to_file(path, true)
+            self.sort();
+
+            format.save_to_file(self.data(), path)
         } else {
             Ok(())
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manager_with_tags(tags: &[&str]) -> BookmarkManager {
+        BookmarkManager::new(vec![Bookmark {
+            id: 0,
+            archived: false,
+            name: "Example".into(),
+            url: "https://example.com".into(),
+            tags: tags.iter().map(|&t| t.into()).collect(),
+            added: None,
+            icon: None,
+        }])
+        .unwrap()
+    }
+
+    fn strings(list: &[&str]) -> Vec<String> {
+        list.iter().map(|&s| s.into()).collect()
+    }
+
+    #[test]
+    fn add_urls_collects_duplicates_and_failures() {
+        let add = |manager: &mut BookmarkManager, url: &str| match url.contains("offline") {
+            true => Err("failed to get title: couldn't connect".into()),
+            false => manager.add_bookmark(url.into(), url.into(), Vec::new(), None),
+        };
+        let urls = [
+            "https://rust-lang.org",
+            "https://example.com",
+            "not a url",
+            "https://offline.example",
+            "https://rust-lang.org",
+            "https://docs.rs/serde",
+        ];
+
+        let mut manager = manager_with_tags(&[]);
+        let report = manager.add_urls(urls.iter().cloned(), false, add).unwrap();
+
+        assert_eq!(report.added, 2);
+        assert_eq!(
+            report.duplicates,
+            vec![
+                ("https://example.com".into(), 0),
+                ("https://rust-lang.org".into(), 1)
+            ]
+        );
+        assert_eq!(
+            report.failed,
+            vec![
+                ("not a url".into(), "malformed url".into()),
+                (
+                    "https://offline.example".into(),
+                    "failed to get title: couldn't connect".into()
+                ),
+            ]
+        );
+
+        let mut manager = manager_with_tags(&[]);
+        assert!(manager
+            .add_urls(urls.iter().cloned(), true, add)
+            .unwrap_err()
+            .contains("#0"));
+        assert_eq!(manager.data().len(), 2);
+    }
+
+    #[test]
+    fn search_matches_name_url_and_tags() {
+        let mut manager = manager_with_tags(&["Rust", "docs"]);
+        manager
+            .add_bookmark(
+                "Weather".into(),
+                "https://wttr.in".into(),
+                strings(&["daily"]),
+                None,
+            )
+            .unwrap();
+        let ids = |manager: &BookmarkManager, query: &str, include_archived: bool| -> Vec<Id> {
+            manager
+                .search(query, include_archived)
+                .iter()
+                .map(|b| b.id)
+                .collect()
+        };
+
+        assert_eq!(ids(&manager, "exam", false), vec![0]);
+        assert_eq!(ids(&manager, "WTTR.IN", false), vec![1]);
+        assert_eq!(ids(&manager, "rust", false), vec![0]);
+        assert_eq!(ids(&manager, "DAI", false), vec![1]);
+        assert_eq!(ids(&manager, "https://", false), vec![0, 1]);
+        assert_eq!(ids(&manager, "", false), vec![0, 1]);
+        assert!(ids(&manager, "nothing", false).is_empty());
+
+        manager.interact_mut(0, |b| b.archived = true);
+        assert!(ids(&manager, "rust", false).is_empty());
+        assert_eq!(ids(&manager, "rust", true), vec![0]);
+    }
+
+    #[test]
+    fn saving_sorts_by_id() {
+        let bookmark = |id| Bookmark {
+            id,
+            archived: false,
+            name: format!("B{}", id),
+            url: format!("https://example.com/{}", id),
+            tags: Vec::new(),
+            added: None,
+            icon: None,
+        };
+        let mut manager =
+            BookmarkManager::new(vec![bookmark(2), bookmark(0), bookmark(1)]).unwrap();
+        manager.after_interact_mut_hook();
+
+        let path = utils::tmp::make_tmp(Some("json"));
+        let saved = manager
+            .save_if_modified(&path, StorageFormat::Lines)
+            .is_ok();
+        let contents = std::fs::read_to_string(&path).unwrap_or_default();
+        let _ = std::fs::remove_file(&path);
+
+        assert!(saved);
+        let ids: Vec<u32> = StorageFormat::Lines
+            .import(&contents)
+            .unwrap_or_default()
+            .iter()
+            .map(|b: &Bookmark| b.id)
+            .collect();
+        assert_eq!(ids, vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn tags_add_and_remove() {
+        let mut manager = manager_with_tags(&["rust", "web"]);
+        manager
+            .edit_tags(0, &strings(&["Docs", "rust"]), &strings(&["WEB"]), None)
+            .unwrap();
+
+        assert_eq!(manager.data()[0].tags, strings(&["rust", "docs"]));
+    }
+
+    #[test]
+    fn tags_set_replaces() {
+        let mut manager = manager_with_tags(&["rust", "web"]);
+        manager
+            .edit_tags(0, &[], &[], Some(&strings(&["a", " B ", "a", ""])))
+            .unwrap();
+
+        assert_eq!(manager.data()[0].tags, strings(&["a", "b"]));
+    }
+
+    #[test]
+    fn merge_reassigns_and_skips() {
+        let mut manager = manager_with_tags(&[]);
+        let other = vec![
+            Bookmark {
+                id: 0,
+                archived: false,
+                name: "Other".into(),
+                url: "https://other.com".into(),
+                tags: Vec::new(),
+                added: None,
+                icon: None,
+            },
+            Bookmark {
+                id: 5,
+                archived: false,
+                name: "Same".into(),
+                url: "https://example.com/".into(),
+                tags: Vec::new(),
+                added: None,
+                icon: None,
+            },
+        ];
+
+        let report = manager.merge_from(other);
+
+        assert_eq!(
+            report,
+            MergeReport {
+                added: 1,
+                skipped: 1,
+                reassigned: 1
+            }
+        );
+        assert_eq!(manager.data()[1].id, 1);
+    }
+
+    #[test]
+    fn stats_tags_and_domains() {
+        let bookmark = |id, url: &str, tags: &[&str]| Bookmark {
+            id,
+            archived: id == 2,
+            name: String::new(),
+            url: url.into(),
+            tags: strings(tags),
+            added: None,
+            icon: None,
+        };
+        let manager = BookmarkManager::new(vec![
+            bookmark(0, "https://example.com/a", &["rust", "docs"]),
+            bookmark(1, "http://EXAMPLE.com:80/b", &["docs"]),
+            bookmark(2, "https://other.org", &["web", "docs", "rust"]),
+            bookmark(3, "/local/path", &[]),
+        ])
+        .unwrap();
+
+        assert_eq!(
+            manager.stats(),
+            Stats {
+                total: 4,
+                archived: 1,
+                tagged: 3,
+                domains: 2,
+                tag_frequency: vec![("docs".into(), 3), ("rust".into(), 2), ("web".into(), 1)],
+            }
+        );
+    }
+
+    #[test]
+    fn archive_old_by_timestamp() {
+        let bookmark = |id, archived, added| Bookmark {
+            id,
+            archived,
+            name: String::new(),
+            url: format!("https://example.com/{}", id),
+            tags: Vec::new(),
+            added,
+            icon: None,
+        };
+        let build = || {
+            BookmarkManager::new(vec![
+                bookmark(0, false, Some(100)),
+                bookmark(1, false, Some(200)),
+                bookmark(2, true, Some(50)),
+                bookmark(3, false, None),
+            ])
+            .unwrap()
+        };
+
+        let mut manager = build();
+        let old: Vec<u32> = manager
+            .old_bookmarks(150, false)
+            .iter()
+            .map(|b| b.id)
+            .collect();
+        assert_eq!(old, vec![0]);
+        assert_eq!(manager.archive_old(150, false), 1);
+        assert!(manager.modified);
+        assert_eq!(manager.archive_old(150, false), 0);
+
+        let mut manager = build();
+        assert_eq!(manager.archive_old(150, true), 2);
+        assert!(manager.data().iter().all(|b| b.archived != (b.id == 1)));
+
+        let mut manager = build();
+        assert_eq!(manager.archive_old(0, false), 0);
+        assert!(!manager.modified);
+    }
+
+    #[test]
+    fn tags_missing_id() {
+        let mut manager = manager_with_tags(&[]);
+        assert!(manager.edit_tags(1, &strings(&["a"]), &[], None).is_err());
+    }
+}
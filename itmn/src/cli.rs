@@ -4,11 +4,17 @@
 //!
 //! [`clap`]: clap
 
-use clap::{Parser, Subcommand};
+use clap::{ArgAction, Args, Parser, Subcommand};
 
 use std::borrow::Cow;
 
-use crate::item::{Item, ItemState};
+use utils::data::tree;
+use utils::format::StorageFormat;
+use utils::misc::IdBase;
+
+use crate::item::{Item, ItemSkeleton, ItemState};
+use crate::report::IndentStyle;
+use crate::report::ReportConfig;
 
 #[derive(Debug, Parser, Clone)]
 pub struct Options {
@@ -19,6 +25,103 @@ pub struct Options {
     )]
     pub path: Option<String>,
 
+    #[arg(
+        long,
+        value_name = "FORMAT",
+        value_parser = StorageFormat::parse,
+        help = "Save the file as pretty, compact, lines or yaml (default: $ITMN_FORMAT => the format it already had, or yaml for .yaml/.yml files)"
+    )]
+    pub format: Option<StorageFormat>,
+
+    #[arg(
+        short,
+        long,
+        global = true,
+        help = "Write the listings (and the diff) to this file instead of the standard output; confirmations still go to the terminal"
+    )]
+    pub output: Option<String>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Show the internal ID of items even if they have a reference ID"
+    )]
+    pub show_internal: bool,
+
+    #[arg(
+        long,
+        alias = "no-header",
+        global = true,
+        help = "Don't print the report headers, only the items (useful for pipelines)"
+    )]
+    pub plain: bool,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Don't show item annotations (set with `mod --note-text`) next to their names"
+    )]
+    pub hide_annotations: bool,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Color each context on reports, always the same way ($ITMN_CONTEXT_COLORS can pick some, e.g. work=blue,home=green)"
+    )]
+    pub context_color: bool,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "BASE",
+        value_parser = IdBase::parse,
+        help = "Show IDs in this base, dec or hex (default: $ITMN_ID_BASE => dec); hex IDs (0x1f) are also accepted on selections"
+    )]
+    pub id_base: Option<IdBase>,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "STYLE",
+        value_parser = IndentStyle::parse,
+        help = "Indent children with this style: spaces (the default), a number of spaces, tabs or guides"
+    )]
+    pub indent: Option<IndentStyle>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Never save changes to the file (useful to rehearse an operation)"
+    )]
+    pub no_save: bool,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Don't take the lock that keeps other instances from running at the same time; another instance changing the file meanwhile could corrupt it or lose changes"
+    )]
+    pub no_lock: bool,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "CONTEXT",
+        help = "The context of new items when none is given (default: $ITMN_DEFAULT_CONTEXT); set to an empty string to disable"
+    )]
+    pub context_default: Option<String>,
+
+    #[arg(
+        short,
+        long,
+        global = true,
+        action = ArgAction::Count,
+        help = "Show more information on stderr; repeat for debug traces (also settable via $COMPSCRIPTS_LOG)"
+    )]
+    pub verbose: u8,
+
+    #[arg(short = 'V', long, help = "Print version and build information")]
+    pub version: bool,
+
     #[command(subcommand)]
     pub subcmd: Option<SubCmd>,
 }
@@ -28,9 +131,9 @@ pub enum SubCmd {
     // #[command(about = "Shows a report - defaults to [next]")]
     // TODO: Report(ReportSelection),
     #[command(alias = "ls", about = "An alias to the [except-done] report")]
-    List,
+    List(ListOptions),
     #[command(about = "An alias to the [next] report")]
-    Next,
+    Next(ListOptions),
     #[command(about = "Add an item")]
     Add(ItemAddDetails),
     #[command(
@@ -40,35 +143,478 @@ pub enum SubCmd {
     SelRefID(SelectionDetails),
     #[command(
         aliases = &["flatlist", "fl"],
-        about = "List all visible items, prepended by the ID",
+        about = "List all visible items, prepended by a contiguous number (see `sel --by-flat-index`)",
+    )]
+    FlatList(ListOptions),
+    #[command(about = "List items modified recently, newest first")]
+    Recent(RecentOptions),
+    #[command(
+        alias = "done",
+        about = "List done items (shallow, with their internal IDs), in tree order"
+    )]
+    Completed(CompletedOptions),
+    #[command(
+        alias = "count-by-state",
+        about = "Print a one-line count of open and total items (e.g. for status bars)"
+    )]
+    Badge(BadgeOptions),
+    #[command(about = "Pick an item and an action to apply to it with fzagnostic")]
+    Menu,
+    #[command(
+        about = "Edit the whole tree (names, states, contexts and structure) as text, on $EDITOR"
+    )]
+    EditAll,
+    #[command(
+        alias = "validate",
+        about = "Check the file for problems (repeated IDs etc.) without changing it"
+    )]
+    Doctor,
+    #[command(about = "Show what changed since the last save (or since another file)")]
+    Diff(DiffOptions),
+    #[command(
+        aliases = &["sel-internal", "sii"],
+        about = "Select items by internal ID (e.g. done items) and do something with them"
     )]
-    FlatList,
-    // #[command(aliases = &["sel-internal", "sii"], about = "Select items by internal ID and do something with them")]
-    // TODO: SelInternalID(SelectionDetails),
+    SelInternalID(InternalSelectionDetails),
+    #[command(
+        about = "Show a report, then show it again whenever the file changes (e.g. for a dashboard), until interrupted"
+    )]
+    Watch(WatchOptions),
+    #[command(about = "Mark every open item with a context as done (e.g. for end-of-day cleanup)")]
+    DoneContext(DoneContextArgs),
     // TODO: Search,
     // TODO: RegexMatch,
 }
 
+impl ItemAddDetails {
+    /// Returns the description the new item(s) should have: the one given with `--description`, or the contents of the
+    /// `--desc-template`. Giving both is an error, since it's not clear which one should be used.
+    pub fn initial_description(&self) -> Result<String, String> {
+        match (&self.description, &self.desc_template) {
+            (Some(_), Some(_)) => {
+                Err("--description and --desc-template can't be used together".into())
+            }
+            (Some(description), None) => Ok(description.clone()),
+            (None, Some(name)) => crate::template::load_description(name),
+            (None, None) => Ok(String::new()),
+        }
+    }
+
+    /// Returns the item tree given with `--from-json` or `--from-json-file`, if any. With `--done`, it's a single done
+    /// item with `description`, since done items are added like trees (without reference IDs).
+    pub fn skeleton(&self, description: &str) -> Result<Option<ItemSkeleton>, String> {
+        match (&self.from_json, &self.from_json_file) {
+            (Some(json), _) => ItemSkeleton::parse(json).map(Some),
+            (None, Some(path)) => std::fs::read_to_string(path)
+                .map_err(|e| format!("failed to read {}: {}", path, e))
+                .and_then(|json| ItemSkeleton::parse(&json))
+                .map(Some),
+            (None, None) if self.done => Ok(Some(ItemSkeleton::leaf(
+                self.name
+                    .as_deref()
+                    .expect("clap requires a name without --from-json"),
+                self.context.as_deref().unwrap_or(""),
+                ItemState::Done,
+                description.into(),
+            ))),
+            (None, None) => Ok(None),
+        }
+    }
+
+    /// Returns the reference ID given with `--after`, read in `base`.
+    pub fn after_id(&self, base: IdBase) -> Result<Option<u32>, String> {
+        parse_optional_id(self.after.as_deref(), base)
+    }
+
+    /// Returns the reference ID given with `--under`, read in `base`.
+    pub fn under_id(&self, base: IdBase) -> Result<Option<u32>, String> {
+        parse_optional_id(self.under.as_deref(), base)
+    }
+}
+
+/// Reads a reference ID given as an option (with or without a leading `#`) in `base`.
+fn parse_optional_id(id: Option<&str>, base: IdBase) -> Result<Option<u32>, String> {
+    id.map(|id| base.parse_id(id.trim_start_matches('#')))
+        .transpose()
+}
+
+impl SubCmd {
+    /// Whether the subcommand never changes the file (or anything else), so it can run without taking the lock.
+    pub fn is_read_only(&self) -> bool {
+        matches!(
+            self,
+            Self::List(_)
+                | Self::Next(_)
+                | Self::Recent(_)
+                | Self::Completed(_)
+                | Self::Badge(_)
+                | Self::Doctor
+                | Self::Diff(_)
+                | Self::Watch(_)
+        )
+    }
+
+    /// Sets the context of the items added by this subcommand (if it adds any) to `default`, unless a context was
+    /// given explicitly. An explicit empty context is kept, so it can be used to add an item without context.
+    pub fn apply_default_context(&mut self, default: &str) {
+        let details = match self {
+            Self::Add(details) => details,
+            Self::SelRefID(SelectionDetails {
+                action: Some(SelectionAction::Add(details)),
+                ..
+            }) => details,
+            _ => return,
+        };
+
+        if details.context.is_none() {
+            details.context = Some(default.into());
+        }
+    }
+}
+
+#[derive(Debug, Args, Clone, Default)]
+pub struct ListOptions {
+    #[arg(
+        short,
+        long,
+        help = "Show the surface items in reverse order (last ones first)"
+    )]
+    pub reverse: bool,
+    #[arg(long, help = "Show the children of each item in reverse order")]
+    pub reverse_children: bool,
+    #[arg(
+        long,
+        overrides_with = "no_summary",
+        help = "Show a summary of the amount of items per context (default for [next])"
+    )]
+    pub summary: bool,
+    #[arg(long, help = "Don't show the per-context summary")]
+    pub no_summary: bool,
+    #[arg(
+        short = 'n',
+        long,
+        value_name = "N",
+        help = "Show only the first N surface items (default: all of them)"
+    )]
+    pub limit: Option<usize>,
+    #[arg(
+        short,
+        long,
+        value_name = "TAG",
+        help = "Show only the items with this tag (and the items leading to them)"
+    )]
+    pub tag: Option<String>,
+    #[arg(
+        short,
+        long = "context",
+        value_name = "CONTEXT",
+        help = "Show only the items with this context (and the items leading to them); can be repeated, and .none matches the items without one"
+    )]
+    pub contexts: Vec<String>,
+    #[arg(
+        long = "exclude-context",
+        value_name = "CONTEXT",
+        help = "Hide the items with this context (along with their children), even if --context matches them; can be repeated, and .none matches the items without one"
+    )]
+    pub excluded_contexts: Vec<String>,
+    #[arg(
+        long,
+        conflicts_with = "notes_only",
+        help = "Show only the actionable items, hiding notes (and the items leading to them)"
+    )]
+    pub open_only: bool,
+    #[arg(
+        long,
+        help = "Show only the notes, hiding actionable items (and the items leading to them)"
+    )]
+    pub notes_only: bool,
+    #[arg(
+        long,
+        help = "Group the surface items under a header for each context, shown as a single tree (only on [list])"
+    )]
+    pub context_tree: bool,
+    #[arg(
+        long,
+        requires = "context_tree",
+        help = "With --context-tree, also show the headers of the contexts whose items were all filtered out"
+    )]
+    pub show_empty: bool,
+    #[arg(
+        long,
+        conflicts_with = "context_tree",
+        help = "Show only the surface items, each with how many children it has (not on [flat-list])"
+    )]
+    pub collapsed: bool,
+}
+
+impl ListOptions {
+    /// Derives a report config from `base`, applying the options that affect how each item is displayed.
+    pub fn report_config(&self, base: &ReportConfig) -> ReportConfig {
+        ReportConfig {
+            reverse_children: self.reverse_children,
+            ..base.clone()
+        }
+    }
+
+    /// Whether `item` itself passes the state (`--open-only`, `--notes-only`), `--tag` and `--context` filters, and
+    /// isn't excluded by `--exclude-context`. Done items never pass.
+    fn matches(&self, item: &Item) -> bool {
+        let state = match item.state {
+            ItemState::Done => false,
+            ItemState::Todo => !self.notes_only,
+            ItemState::Note => !self.open_only,
+        };
+
+        state
+            && self.tag.as_ref().is_none_or(|tag| item.has_tag(tag))
+            && (self.contexts.is_empty() || has_any_context(item, &self.contexts))
+            && !self.excluded(item)
+    }
+
+    /// Whether `item` has one of the contexts given with `--exclude-context`.
+    fn excluded(&self, item: &Item) -> bool {
+        has_any_context(item, &self.excluded_contexts)
+    }
+
+    /// Whether `item` should be shown on the listing: it passes if it, or any of its children (recursively), matches the
+    /// filters, so the items leading to a match are still shown. Done and excluded items are never shown.
+    pub fn shows(&self, item: &Item) -> bool {
+        item.state != ItemState::Done
+            && !self.excluded(item)
+            && (self.matches(item)
+                || tree::find(&item.children, &|i: &Item| self.matches(i)).is_some())
+    }
+
+    /// Whether the per-context summary should be shown, given the default for the report.
+    pub fn show_summary(&self, default: bool) -> bool {
+        if self.summary {
+            true
+        } else if self.no_summary {
+            false
+        } else {
+            default
+        }
+    }
+}
+
+/// Whether the context of `item` is one of `contexts`, which can have a leading @. The ones that translate to no
+/// context (see [`Item::context_translates_to_null`]) match the items without one.
+///
+/// [`Item::context_translates_to_null`]: Item::context_translates_to_null
+fn has_any_context(item: &Item, contexts: &[String]) -> bool {
+    contexts.iter().any(|context| {
+        let context = context.strip_prefix('@').unwrap_or(context);
+
+        match item.context() {
+            _ if Item::context_translates_to_null(context) => item.context().is_none(),
+            Some(own) => own == context,
+            None => false,
+        }
+    })
+}
+
+#[derive(Debug, Args, Clone)]
+pub struct RecentOptions {
+    #[arg(
+        short,
+        long,
+        default_value_t = 7,
+        help = "How many days back to look for changes"
+    )]
+    pub days: u64,
+    #[arg(
+        short,
+        long,
+        value_name = "DATE",
+        conflicts_with = "days",
+        allow_hyphen_values = true,
+        help = "Look for changes since this date (e.g. 2021-03-04, -2w, yesterday) instead"
+    )]
+    pub since: Option<String>,
+}
+
+#[derive(Debug, Args, Clone)]
+pub struct CompletedOptions {
+    #[arg(
+        short,
+        long,
+        value_name = "DATE",
+        allow_hyphen_values = true,
+        help = "Only show items modified (usually: marked as done) since this date (e.g. 2021-03-04, -2w, yesterday)"
+    )]
+    pub since: Option<String>,
+}
+
+#[derive(Debug, Args, Clone)]
+pub struct BadgeOptions {
+    #[arg(
+        short,
+        long,
+        default_value = "{open}/{total}",
+        help = "What to print; {open}, {done}, {notes} and {total} (open + done) are replaced by their counts"
+    )]
+    pub format: String,
+}
+
+#[derive(Debug, Args, Clone)]
+pub struct DiffOptions {
+    #[arg(
+        help = "The file to compare against (default: the backup made on the last save, e.g. ~/.local/share/itmn.bak)"
+    )]
+    pub against: Option<String>,
+}
+
+#[derive(Debug, Args, Clone)]
+pub struct DoneContextArgs {
+    #[arg(help = "The context of the items (a leading @ is ignored)")]
+    pub context: String,
+    #[arg(
+        short,
+        long,
+        help = "Also mark the open items under them as done, whatever their context"
+    )]
+    pub recursive: bool,
+    #[arg(short, long, help = "Don't ask for confirmation")]
+    pub yes: bool,
+}
+
+impl DoneContextArgs {
+    /// Returns the context without the leading @, if any.
+    pub fn context(&self) -> &str {
+        self.context.strip_prefix('@').unwrap_or(&self.context)
+    }
+}
+
+#[derive(Debug, Args, Clone)]
+pub struct WatchOptions {
+    #[arg(
+        short,
+        long,
+        value_name = "SECONDS",
+        default_value_t = 1,
+        value_parser = clap::value_parser!(u64).range(1..),
+        help = "How often to check if the file changed"
+    )]
+    pub interval: u64,
+    #[command(subcommand)]
+    pub report: WatchedReport,
+}
+
+/// The reports that can be shown by [`SubCmd::Watch`].
+///
+/// [`SubCmd::Watch`]: SubCmd::Watch
+#[derive(Debug, Subcommand, Clone)]
+pub enum WatchedReport {
+    #[command(alias = "ls", about = "Watch the [except-done] report")]
+    List(ListOptions),
+    #[command(about = "Watch the [next] report")]
+    Next(ListOptions),
+    #[command(about = "Watch the items modified recently")]
+    Recent(RecentOptions),
+    #[command(alias = "done", about = "Watch the done items")]
+    Completed(CompletedOptions),
+    #[command(about = "Watch the count of open and total items")]
+    Badge(BadgeOptions),
+}
+
 #[derive(Debug, Parser, Clone)]
 pub struct ItemAddDetails {
-    #[arg(help = "The name of the item")]
-    pub name: String,
+    #[arg(
+        required_unless_present_any = ["from_json", "from_json_file"],
+        help = "The name of the item"
+    )]
+    pub name: Option<String>,
     #[arg(short, long, help = "The context of the item")]
     pub context: Option<String>,
     #[arg(short, long, help = "If the item is a note")]
     pub note: Option<bool>,
+    #[arg(
+        long,
+        conflicts_with_all = ["note", "template"],
+        help = "Add the item as already done (it gets no reference ID), e.g. to record something finished earlier"
+    )]
+    pub done: bool,
     #[arg(short, long, help = "The description of the item")]
     pub description: Option<String>,
+    #[arg(
+        long,
+        value_name = "NAME",
+        conflicts_with = "description",
+        help = "Use a description template (from $XDG_CONFIG_HOME/itmn/descriptions/NAME.md) as the description"
+    )]
+    pub desc_template: Option<String>,
+    #[arg(
+        short,
+        long,
+        help = "The name of a template (from $XDG_CONFIG_HOME/itmn/templates.json) to expand as children of the item"
+    )]
+    pub template: Option<String>,
+    #[arg(
+        long,
+        value_name = "REF_ID",
+        help = "Insert the item right after this sibling, instead of at the end"
+    )]
+    pub after: Option<String>,
+    #[arg(
+        long,
+        value_name = "REF_ID",
+        help = "Add the item as a child of this one, instead of at the root (only on `add`)"
+    )]
+    pub under: Option<String>,
+    #[arg(
+        long,
+        value_name = "JSON",
+        conflicts_with_all = ["name", "context", "note", "done", "description", "desc_template", "template"],
+        help = "Add a whole item tree, given as JSON with the same shape as the file's items (IDs are ignored)"
+    )]
+    pub from_json: Option<String>,
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with_all = ["from_json", "name", "context", "note", "done", "description", "desc_template", "template"],
+        help = "Like --from-json, but reading the JSON from a file"
+    )]
+    pub from_json_file: Option<String>,
 }
 
 #[derive(Debug, Parser, Clone)]
 pub struct SelectionDetails {
     #[arg(help = "The selection range")]
     pub range: String, // TODO: document range syntax
+    #[arg(
+        short = 'f',
+        long,
+        help = "Interpret the selection as the numbers shown on the last flat-list, instead of reference IDs"
+    )]
+    pub by_flat_index: bool,
     #[command(subcommand)]
     pub action: Option<SelectionAction>,
 }
 
+#[derive(Debug, Parser, Clone)]
+pub struct InternalSelectionDetails {
+    #[arg(help = "The selection range, of internal IDs (the 'i' prefix is optional)")]
+    pub range: String,
+    #[command(subcommand)]
+    pub action: InternalSelectionAction,
+}
+
+/// The actions for `sel-internal`. Only the ones that make sense for items without reference IDs are here; the others
+/// are on [`SelectionAction`].
+#[derive(Debug, Subcommand, Clone)]
+pub enum InternalSelectionAction {
+    #[command(
+        alias = "bump",
+        about = "Mark done items as TODO again, giving them new reference IDs"
+    )]
+    Reopen(ForceArgs),
+    #[command(
+        alias = "path",
+        about = "Print the names of the items leading to each selected item (e.g. `Project > Phase 1 > Task`)"
+    )]
+    ShowPath,
+}
+
 #[derive(Debug, Subcommand, Clone)]
 pub enum SelectionAction {
     #[command(alias = "mod", about = "Modify the matches")]
@@ -95,6 +641,50 @@ pub enum SelectionAction {
     EditDescription,
     #[command(aliases = &["d", "desc"], about = "Print the description of an item")]
     PrintDescription,
+    #[command(
+        aliases = &["vd", "view"],
+        about = "View the description of an item with $MARKDOWN_VIEWER, or print it if that's not available"
+    )]
+    ViewDescription,
+    #[command(aliases = &["ctx", "sc"], about = "Set the context of the selected items")]
+    SetContext(SetContextArgs),
+    #[command(
+        aliases = &["copy-to", "export"],
+        about = "Write the selected items (and their children) to a file of their own"
+    )]
+    ExportSubtree(ExportSubtreeArgs),
+    #[command(
+        aliases = &["promote", "reparent-children-to-root"],
+        about = "Move the children of the selected items to their parents' level, leaving the items childless"
+    )]
+    PromoteChildren(ForceArgs),
+    #[command(about = "Make the selected item a child of its preceding sibling")]
+    Indent,
+    #[command(about = "Make the selected item a sibling of its parent, right after it")]
+    Outdent,
+    #[command(
+        alias = "path",
+        about = "Print the names of the items leading to each selected item (e.g. `Project > Phase 1 > Task`)"
+    )]
+    ShowPath,
+    #[command(
+        alias = "pin",
+        about = "Give the selected item a reference ID of your choice (if it's free, or with --swap)"
+    )]
+    SetRefId(SetRefIdArgs),
+}
+
+#[derive(Debug, Parser, Clone)]
+pub struct SetRefIdArgs {
+    #[arg(help = "The new reference ID (in the base given by --id-base)")]
+    pub new_id: String,
+    #[arg(
+        long,
+        help = "If another item has the ID, give it the selected item's ID instead of failing"
+    )]
+    pub swap: bool,
+    #[command(flatten)]
+    pub force: ForceArgs,
 }
 
 #[derive(Debug, Parser, Clone)]
@@ -109,6 +699,32 @@ pub struct ItemBatchMod {
     pub context: Option<String>,
     #[arg(short, long, help = "The item's new type")]
     pub note: Option<bool>,
+    #[arg(
+        short,
+        long,
+        conflicts_with = "note",
+        value_parser = Item::parse_state,
+        help = "The item's new state (todo, done or note); done items lose their reference ID"
+    )]
+    pub state: Option<ItemState>,
+    #[arg(
+        long,
+        value_name = "TAG",
+        help = "Add a tag to the item (can be repeated)"
+    )]
+    pub tag: Vec<String>,
+    #[arg(
+        long,
+        value_name = "TAG",
+        help = "Remove a tag from the item (can be repeated)"
+    )]
+    pub untag: Vec<String>,
+    #[arg(
+        long,
+        value_name = "TEXT",
+        help = "The item's new annotation, a one-line remark shown next to its name; set to an empty string to remove"
+    )]
+    pub note_text: Option<String>,
 }
 
 impl ItemBatchMod {
@@ -136,6 +752,30 @@ impl ItemBatchMod {
             }
         }
 
+        if let Some(state) = self.state {
+            vec.push(match state {
+                ItemState::Todo => "Mark as TODO".into(),
+                ItemState::Done => "Mark as DONE (removing the reference ID)".into(),
+                ItemState::Note => "Transform into a note".into(),
+            });
+        }
+
+        for tag in &self.tag {
+            vec.push(format!("Add tag {:?}", tag).into());
+        }
+
+        for tag in &self.untag {
+            vec.push(format!("Remove tag {:?}", tag).into());
+        }
+
+        if let Some(text) = &self.note_text {
+            vec.push(if text.trim().is_empty() {
+                "Remove annotation".into()
+            } else {
+                format!("Change annotation to {:?}", text).into()
+            });
+        }
+
         vec
     }
 
@@ -143,6 +783,8 @@ impl ItemBatchMod {
     ///
     /// Might clone some of the contents of self, but not necessarily all.
     pub fn mod_item_by_ref(&self, item: &mut Item) {
+        item.touch();
+
         if let Some(ref name) = self.name {
             item.name = name.clone();
         }
@@ -161,11 +803,29 @@ impl ItemBatchMod {
                 }
             }
         }
+
+        if let Some(state) = self.state {
+            item.set_state(state);
+        }
+
+        for tag in &self.tag {
+            item.add_tag(tag);
+        }
+
+        for tag in &self.untag {
+            item.remove_tag(tag);
+        }
+
+        if let Some(ref text) = self.note_text {
+            item.set_annotation(text);
+        }
     }
 
     /// Apply modifications to an item, consuming self.
     #[allow(unused)]
     pub fn mod_item(self, item: &mut Item) {
+        item.touch();
+
         if let Some(name) = self.name {
             item.name = name;
         }
@@ -184,6 +844,22 @@ impl ItemBatchMod {
                 }
             }
         }
+
+        if let Some(state) = self.state {
+            item.set_state(state);
+        }
+
+        for tag in &self.tag {
+            item.add_tag(tag);
+        }
+
+        for tag in &self.untag {
+            item.remove_tag(tag);
+        }
+
+        if let Some(ref text) = self.note_text {
+            item.set_annotation(text);
+        }
     }
 }
 
@@ -200,6 +876,45 @@ impl Into<bool> for ForceArgs {
     }
 }
 
+#[derive(Debug, Parser, Clone)]
+pub struct SetContextArgs {
+    #[arg(
+        help = "The new context (a leading @ is ignored); set to an empty string, @ or .none to unset"
+    )]
+    pub context: String,
+    #[arg(
+        short,
+        long,
+        help = "Also set the context on all descendants of the selected items"
+    )]
+    pub recursive: bool,
+}
+
+impl SetContextArgs {
+    /// Returns the context without the leading @, if any.
+    pub fn context(&self) -> &str {
+        self.context.strip_prefix('@').unwrap_or(&self.context)
+    }
+}
+
+#[derive(Debug, Parser, Clone)]
+pub struct ExportSubtreeArgs {
+    #[arg(
+        help = "The file to write to, in the same format the items are saved in (compressed if it ends in .gz) unless --append is given"
+    )]
+    pub path: String,
+    #[arg(
+        long = "move",
+        help = "Also remove the items from this file, like a cut"
+    )]
+    pub move_items: bool,
+    #[arg(
+        long,
+        help = "Append the items to the file, one per line (like the lines format), instead of overwriting it (e.g. to archive done items)"
+    )]
+    pub append: bool,
+}
+
 #[derive(Debug, Parser, Clone)]
 pub struct ChownArgs {
     #[arg(
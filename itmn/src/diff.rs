@@ -0,0 +1,204 @@
+//! Stores data structures related to comparing two versions of the database.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::item::{InternalId, Item, ItemState};
+
+/// A single difference between two versions of the database. Items are matched by their internal ID, since it never
+/// changes.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Change {
+    /// The item only exists on the new version.
+    Added { id: InternalId, name: String },
+    /// The item only exists on the old version.
+    Removed { id: InternalId, name: String },
+    /// The item's name changed.
+    Renamed {
+        id: InternalId,
+        old: String,
+        new: String,
+    },
+    /// The item's state changed.
+    StateChanged {
+        id: InternalId,
+        name: String,
+        old: ItemState,
+        new: ItemState,
+    },
+    /// The item's context changed.
+    ContextChanged {
+        id: InternalId,
+        name: String,
+        old: Option<String>,
+        new: Option<String>,
+    },
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let context = |ctx: &Option<String>| match ctx {
+            Some(ctx) => format!("@{}", ctx),
+            None => "(no context)".into(),
+        };
+
+        match self {
+            Self::Added { id, name } => write!(f, "+ i{} {:?}", id.0, name),
+            Self::Removed { id, name } => write!(f, "- i{} {:?}", id.0, name),
+            Self::Renamed { id, old, new } => write!(f, "~ i{} {:?} -> {:?}", id.0, old, new),
+            Self::StateChanged { id, name, old, new } => {
+                write!(f, "~ i{} {:?}: {:?} -> {:?}", id.0, name, old, new)
+            }
+            Self::ContextChanged { id, name, old, new } => write!(
+                f,
+                "~ i{} {:?}: {} -> {}",
+                id.0,
+                name,
+                context(old),
+                context(new)
+            ),
+        }
+    }
+}
+
+/// Compares two versions of the database recursively, returning the changes needed to go from `old` to `new`.
+///
+/// Changes to items that exist on both versions (and added items) come first, in the order they appear on `new`;
+/// removed items come last, in the order they appeared on `old`. Moving an item around isn't considered a change.
+pub fn diff(old: &[Item], new: &[Item]) -> Vec<Change> {
+    let mut old_items: HashMap<u32, &Item> = HashMap::new();
+    utils::data::tree::walk(old, &mut |item: &Item, _| {
+        old_items.insert(item.internal_id, item);
+    });
+
+    let mut new_order: Vec<&Item> = Vec::new();
+    utils::data::tree::walk(new, &mut |item: &Item, _| new_order.push(item));
+
+    let mut changes = Vec::new();
+
+    for item in &new_order {
+        let id = InternalId(item.internal_id);
+
+        let before = match old_items.remove(&item.internal_id) {
+            Some(before) => before,
+            None => {
+                changes.push(Change::Added {
+                    id,
+                    name: item.name.clone(),
+                });
+                continue;
+            }
+        };
+
+        if before.name != item.name {
+            changes.push(Change::Renamed {
+                id,
+                old: before.name.clone(),
+                new: item.name.clone(),
+            });
+        }
+
+        if before.state != item.state {
+            changes.push(Change::StateChanged {
+                id,
+                name: item.name.clone(),
+                old: before.state,
+                new: item.state,
+            });
+        }
+
+        if before.context() != item.context() {
+            changes.push(Change::ContextChanged {
+                id,
+                name: item.name.clone(),
+                old: before.context().map(String::from),
+                new: item.context().map(String::from),
+            });
+        }
+    }
+
+    // whatever wasn't matched was removed; walk again to keep the original order
+    utils::data::tree::walk(old, &mut |item: &Item, _| {
+        if old_items.contains_key(&item.internal_id) {
+            changes.push(Change::Removed {
+                id: InternalId(item.internal_id),
+                name: item.name.clone(),
+            });
+        }
+    });
+
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(
+        internal_id: u32,
+        name: &str,
+        ctx: &str,
+        state: ItemState,
+        children: Vec<Item>,
+    ) -> Item {
+        Item::new(None, internal_id, name, ctx, state, String::new(), children)
+    }
+
+    #[test]
+    fn diff_by_internal_id() {
+        let old = vec![
+            item(
+                0,
+                "parent",
+                "",
+                ItemState::Todo,
+                vec![item(1, "child", "work", ItemState::Todo, Vec::new())],
+            ),
+            item(2, "gone", "", ItemState::Note, Vec::new()),
+        ];
+        let new = vec![
+            item(0, "parent", "", ItemState::Todo, Vec::new()),
+            item(3, "fresh", "", ItemState::Todo, Vec::new()),
+            // moved to the root, renamed and finished
+            item(1, "kid", "", ItemState::Done, Vec::new()),
+        ];
+
+        let changes = diff(&old, &new);
+
+        assert_eq!(
+            changes,
+            vec![
+                Change::Added {
+                    id: InternalId(3),
+                    name: "fresh".into()
+                },
+                Change::Renamed {
+                    id: InternalId(1),
+                    old: "child".into(),
+                    new: "kid".into()
+                },
+                Change::StateChanged {
+                    id: InternalId(1),
+                    name: "kid".into(),
+                    old: ItemState::Todo,
+                    new: ItemState::Done
+                },
+                Change::ContextChanged {
+                    id: InternalId(1),
+                    name: "kid".into(),
+                    old: Some("work".into()),
+                    new: None
+                },
+                Change::Removed {
+                    id: InternalId(2),
+                    name: "gone".into()
+                },
+            ]
+        );
+        assert_eq!(
+            changes[3].to_string(),
+            r#"~ i1 "kid": @work -> (no context)"#
+        );
+        assert!(diff(&new, &new).is_empty());
+    }
+}
@@ -0,0 +1,116 @@
+//! Stores data structures related to the flat index, which maps the contiguous numbers shown by `flat-list` to the
+//! reference IDs of the items, so they can be selected with `sel --by-flat-index`.
+
+use serde::{Deserialize, Serialize};
+
+use std::path::{Path, PathBuf};
+
+use crate::item::RefId;
+
+/// The numbers shown on the last flat report, along with the file they refer to.
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct FlatIndex {
+    /// The database file the flat report was made for.
+    pub file: PathBuf,
+    /// The reference IDs of the numbered items, in order. Number `n` maps to `ref_ids[n - 1]`.
+    pub ref_ids: Vec<u32>,
+}
+
+impl FlatIndex {
+    pub fn new(file: &Path, ref_ids: Vec<RefId>) -> Self {
+        Self {
+            file: absolute(file),
+            ref_ids: ref_ids.into_iter().map(|RefId(id)| id).collect(),
+        }
+    }
+
+    /// Returns the path where the flat index is stored (`$XDG_CACHE_HOME/itmn/flat-index.json`).
+    pub fn default_path() -> PathBuf {
+        utils::paths::cache_dir()
+            .join("itmn")
+            .join("flat-index.json")
+    }
+
+    /// Saves the flat index to the default path.
+    pub fn save(&self) -> Result<(), String> {
+        let path = Self::default_path();
+
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| format!("failed to create {}: {}", parent.display(), e))?;
+        }
+
+        let contents = serde_json::to_string(self).map_err(|e| e.to_string())?;
+        std::fs::write(&path, contents)
+            .map_err(|e| format!("failed to write {}: {}", path.display(), e))
+    }
+
+    /// Loads the flat index from the default path, making sure it was made for `file`.
+    pub fn load(file: &Path) -> Result<Self, String> {
+        const HINT: &str = "run `itmn flat-list` first";
+
+        let path = Self::default_path();
+        let contents = std::fs::read_to_string(&path)
+            .map_err(|e| format!("failed to read the flat index ({}); {}", e, HINT))?;
+        let index: Self = serde_json::from_str(&contents)
+            .map_err(|e| format!("failed to parse the flat index ({}); {}", e, HINT))?;
+
+        if index.file != absolute(file) {
+            return Err(format!(
+                "the flat index was made for another file ({}); {}",
+                index.file.display(),
+                HINT
+            ));
+        }
+
+        Ok(index)
+    }
+
+    /// Translates flat numbers (starting at 1) into reference IDs, failing if any of them is out of range.
+    pub fn resolve(&self, numbers: &[u32]) -> Result<Vec<u32>, String> {
+        let invalid: Vec<String> = numbers
+            .iter()
+            .filter(|&&n| n == 0 || n as usize > self.ref_ids.len())
+            .map(u32::to_string)
+            .collect();
+
+        match invalid.as_slice() {
+            [] => Ok(numbers
+                .iter()
+                .map(|&n| self.ref_ids[n as usize - 1])
+                .collect()),
+            [n] => Err(format!("invalid flat index on the selection: {}", n)),
+            _ => Err(format!(
+                "invalid flat indexes on the selection: {}",
+                invalid.join(", ")
+            )),
+        }
+    }
+}
+
+/// Makes `path` absolute without touching the filesystem, so the same file is recognized from any directory.
+fn absolute(path: &Path) -> PathBuf {
+    if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        std::env::current_dir()
+            .map(|dir| dir.join(path))
+            .unwrap_or_else(|_| path.to_path_buf())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolve_numbers() {
+        let index = FlatIndex::new(Path::new("/db"), vec![RefId(4), RefId(0), RefId(7)]);
+
+        assert_eq!(index.resolve(&[1, 3]), Ok(vec![4, 7]));
+        assert_eq!(
+            index.resolve(&[0, 2, 4]),
+            Err("invalid flat indexes on the selection: 0, 4".to_string())
+        );
+    }
+}
@@ -1,7 +1,10 @@
 //! Stores data structures related to the database's storage unit.
 
+use serde::de::IgnoredAny;
 use serde::{Deserialize, Serialize};
 
+use utils::data::HasChildren;
+
 /// An item state describes whether said item is actionable (to do / done) or a note. More possible states might be
 /// added on the future.
 #[derive(Debug, Deserialize, Serialize, Eq, PartialEq, Copy, Clone)]
@@ -15,7 +18,7 @@ pub enum ItemState {
 }
 
 /// Used for reference ID search operations
-#[derive(PartialEq, Eq, Hash, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
 pub struct RefId(pub u32);
 
 impl From<u32> for RefId {
@@ -31,7 +34,7 @@ impl Into<u32> for RefId {
 }
 
 /// Used for internal ID search operations
-#[derive(PartialEq, Eq, Hash, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
 pub struct InternalId(pub u32);
 
 impl Into<u32> for InternalId {
@@ -47,7 +50,7 @@ impl From<u32> for InternalId {
 }
 
 /// The main data unit used to store information on this program's database.
-#[derive(Debug, Deserialize, Serialize, Eq, PartialEq)]
+#[derive(Debug, Clone, Deserialize, Serialize, Eq, PartialEq)]
 pub struct Item {
     /// The public name of the item. It usually appears on most reports.
     pub name: String,
@@ -77,6 +80,17 @@ pub struct Item {
     /// [`Vec::with_capacity(0)`]: std::vec::Vec::with_capacity
     /// [`shrink_to_fit`]: Vec::shrink_to_fit
     pub children: Vec<Item>,
+    /// When the item was last modified, as a UNIX timestamp (in seconds). Items created before this was tracked don't
+    /// have one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub modified: Option<u64>,
+    /// Free-form labels for the item (without the leading `#`). Unlike the context, an item can have many of them.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+    /// A short, single-line remark about the item, shown next to its name on reports. Unlike the description, it
+    /// doesn't need an editor.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    annotation: Option<String>,
     // pub creation_date: Option<String>,
     // TODO: defer_date: Option</* idk */>,
     // TODO: deprecate context (possibly)
@@ -95,6 +109,16 @@ impl PartialOrd for Item {
     }
 }
 
+impl HasChildren for Item {
+    fn children(&self) -> &[Self] {
+        &self.children
+    }
+
+    fn children_mut(&mut self) -> &mut Vec<Self> {
+        &mut self.children
+    }
+}
+
 impl Item {
     /// Creates a new item with the specified information.
     pub fn new(
@@ -114,9 +138,17 @@ impl Item {
             state,
             description,
             children,
+            modified: Some(now_timestamp()),
+            tags: Vec::new(),
+            annotation: None,
         }
     }
 
+    /// Marks the item as modified right now.
+    pub fn touch(&mut self) {
+        self.modified = Some(now_timestamp());
+    }
+
     /// Verifies if a context string would translate to a "no context" state.
     pub fn context_translates_to_null(string: &str) -> bool {
         matches!(string.to_lowercase().as_str(), ".void" | ".none" | "")
@@ -131,6 +163,22 @@ impl Item {
         }
     }
 
+    /// Processes a tag string, returning whatever should be stored on the `tags` field of the item, if anything. A
+    /// leading `#` is ignored, and whitespace isn't allowed, so the tag can be shown as `#tag`.
+    fn validate_tag(tag: &str) -> Option<String> {
+        let tag: String = tag
+            .trim_start_matches('#')
+            .chars()
+            .filter(|&c| validate_char(c) && !c.is_whitespace())
+            .collect();
+
+        if tag.is_empty() {
+            None
+        } else {
+            Some(tag)
+        }
+    }
+
     /// Processes a name string, returning whatever should be stored on the `name` field of the item.
     fn validate_name(name: &str) -> String {
         name.chars().filter(|&c| validate_char(c)).collect()
@@ -148,6 +196,12 @@ impl Item {
     /// Validates and sets the name of the item.
     pub fn set_name(&mut self, new_name: &str) {
         self.name = Self::validate_name(new_name);
+        self.touch();
+    }
+
+    /// Whether the item is done but still holds a reference ID, which done items shouldn't have.
+    pub fn is_done_with_ref_id(&self) -> bool {
+        self.state == ItemState::Done && self.ref_id.is_some()
     }
 
     #[inline]
@@ -160,6 +214,133 @@ impl Item {
     /// Validates and sets the context of the item.
     pub fn set_context(&mut self, new_context: &str) {
         self.context = Self::validate_context(new_context);
+        self.touch();
+    }
+
+    /// Returns the annotation of the item, if any.
+    pub fn annotation(&self) -> Option<&str> {
+        self.annotation.as_deref()
+    }
+
+    /// Validates and sets the annotation of the item. Like names, annotations are single-line; an empty (or blank)
+    /// annotation removes it.
+    pub fn set_annotation(&mut self, annotation: &str) {
+        let annotation = Self::validate_name(annotation).trim().to_string();
+        self.annotation = Some(annotation).filter(|a| !a.is_empty());
+        self.touch();
+    }
+
+    /// Returns the tags of the item.
+    pub fn tags(&self) -> &[String] {
+        &self.tags
+    }
+
+    /// Checks if the item has a tag. A leading `#` on `tag` is ignored.
+    pub fn has_tag(&self, tag: &str) -> bool {
+        match Self::validate_tag(tag) {
+            Some(tag) => self.tags.contains(&tag),
+            None => false,
+        }
+    }
+
+    /// Validates and adds a tag to the item, returning `false` if it was invalid or already there.
+    pub fn add_tag(&mut self, tag: &str) -> bool {
+        match Self::validate_tag(tag) {
+            Some(tag) if !self.tags.contains(&tag) => {
+                self.tags.push(tag);
+                self.touch();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Removes a tag from the item, returning `false` if it didn't have it.
+    pub fn remove_tag(&mut self, tag: &str) -> bool {
+        let len = self.tags.len();
+
+        if let Some(tag) = Self::validate_tag(tag) {
+            self.tags.retain(|t| *t != tag);
+        }
+
+        if self.tags.len() != len {
+            self.touch();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Sets the state of the item, dropping its reference ID if it's being marked as done.
+    pub fn set_state(&mut self, new_state: ItemState) {
+        if new_state == ItemState::Done {
+            self.ref_id = None;
+        }
+
+        self.state = new_state;
+        self.touch();
+    }
+
+    /// Parses the name of a state (`todo`, `done` or `note`), ignoring case.
+    pub fn parse_state(string: &str) -> Result<ItemState, String> {
+        match string.to_lowercase().as_str() {
+            "todo" => Ok(ItemState::Todo),
+            "done" => Ok(ItemState::Done),
+            "note" => Ok(ItemState::Note),
+            _ => Err(format!(
+                "unknown state {:?} (expected todo, done or note)",
+                string
+            )),
+        }
+    }
+
+    /// Checks if the item's own fields (not its children) are semantically valid, returning all violations found.
+    ///
+    /// Items created through [`Item::new`] and the setters are always valid, but items read from a file might not be,
+    /// since the deserialization bypasses the name/context validation. The ID and state types are already enforced by
+    /// the parser.
+    ///
+    /// [`Item::new`]: Item::new
+    pub fn validate(&self) -> Result<(), String> {
+        let mut violations = Vec::new();
+
+        if Self::validate_name(&self.name).is_empty() {
+            violations.push("name is empty");
+        } else if Self::validate_name(&self.name) != self.name {
+            violations.push("name has invalid characters");
+        }
+
+        if let Some(context) = &self.context {
+            if Self::context_translates_to_null(context) {
+                violations.push("context should be empty");
+            } else if Self::validate_context(context).as_ref() != Some(context) {
+                violations.push("context has invalid characters");
+            }
+        }
+
+        if let Some(annotation) = &self.annotation {
+            if Self::validate_name(annotation).trim() != annotation || annotation.is_empty() {
+                violations.push("annotation is empty or has invalid characters");
+            }
+        }
+
+        for (i, tag) in self.tags.iter().enumerate() {
+            if Self::validate_tag(tag).as_ref() != Some(tag) {
+                violations.push("tag is empty or has invalid characters");
+            } else if self.tags[..i].contains(tag) {
+                violations.push("tag is repeated");
+            }
+        }
+
+        if self.is_done_with_ref_id() {
+            violations.push("done item has a reference ID");
+        }
+
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(violations.join(", "))
+        }
     }
 
     pub fn has_child(&self, child: &Item) -> bool {
@@ -173,6 +354,85 @@ impl Item {
     }
 }
 
+/// An item tree as given to `add --from-json`: the same shape as [`Item`], but only the name is required.
+///
+/// The IDs and the modification date are accepted, so exported items can be fed back in, but they're ignored, since
+/// fresh ones are allocated when the tree is added. Any other unknown field is an error.
+///
+/// [`Item`]: Item
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ItemSkeleton {
+    pub name: String,
+    #[serde(default)]
+    pub state: Option<ItemState>,
+    #[serde(default)]
+    pub context: Option<String>,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub annotation: Option<String>,
+    #[serde(default)]
+    pub children: Vec<ItemSkeleton>,
+    #[serde(default, rename = "ref_id")]
+    _ref_id: Option<IgnoredAny>,
+    #[serde(default, rename = "internal_id")]
+    _internal_id: Option<IgnoredAny>,
+    #[serde(default, rename = "modified")]
+    _modified: Option<IgnoredAny>,
+}
+
+impl ItemSkeleton {
+    /// Makes a skeleton for a single item, without children.
+    pub fn leaf(name: &str, context: &str, state: ItemState, description: String) -> Self {
+        Self {
+            name: name.into(),
+            state: Some(state),
+            context: Some(context.into()),
+            description,
+            tags: Vec::new(),
+            annotation: None,
+            children: Vec::new(),
+            _ref_id: None,
+            _internal_id: None,
+            _modified: None,
+        }
+    }
+
+    /// Parses and validates an item tree from a json string.
+    pub fn parse(string: &str) -> Result<Self, String> {
+        let skeleton: Self =
+            serde_json::from_str(string).map_err(|e| format!("invalid item JSON: {}", e))?;
+        skeleton.validate()?;
+
+        Ok(skeleton)
+    }
+
+    /// Checks that every item on the tree has a name, and that annotations (if given) aren't empty either.
+    fn validate(&self) -> Result<(), String> {
+        if Item::validate_name(&self.name).trim().is_empty() {
+            return Err("invalid item JSON: an item has an empty name".into());
+        }
+
+        if let Some(annotation) = &self.annotation {
+            if Item::validate_name(annotation).trim().is_empty() {
+                return Err("invalid item JSON: an item has an empty annotation".into());
+            }
+        }
+
+        self.children.iter().try_for_each(Self::validate)
+    }
+}
+
+/// Returns the current time as a UNIX timestamp, in seconds.
+pub fn now_timestamp() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
 /// A function that returns only valid characters for a name/context.
 fn validate_char(c: char) -> bool {
     match c {
@@ -2,9 +2,11 @@
 
 use clap::Parser;
 
+use std::borrow::Cow;
 use std::collections::HashSet;
-use std::io;
-use std::path::Path;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
 
 mod cli;
 use cli::*;
@@ -13,51 +15,183 @@ mod item;
 use item::{InternalId, Item, ItemState, RefId};
 
 mod manager;
+use manager::{DataFile, ItemManager, ManagerError, ProgramResult};
 use manager::{Interactable, Searchable};
-use manager::{ItemManager, ManagerError, ProgramResult};
+
+mod diff;
+
+mod flat_index;
+use flat_index::FlatIndex;
+
+mod outline;
+
+mod template;
+use template::Templates;
 
 mod report;
-use report::{FlatReport, Report, ReportConfig, ReportDepth, ReportInfo};
+use report::{
+    ContextColors, FlatReport, IndentStyle, Report, ReportConfig, ReportDepth, ReportInfo,
+};
 
-use utils::data::data_serialize;
-use utils::error::ExitCode;
-use utils::misc::confirm_with_default;
-use utils::tmp;
+use utils::data::{data_serialize, tree, TreeManager};
+use utils::error::{CliError, ExitCode};
+use utils::format::StorageFormat;
+use utils::misc::{confirm_with_default, Fzagnostic, IdBase, Picker};
+use utils::tmp::{self, EditorBackend, RecoveryKey};
 
 fn main() -> ExitCode {
-    let itmn_file = std::env::var("ITMN_FILE")
-        .unwrap_or_else(|_| format!("{}/.local/share/itmn", std::env::var("HOME").unwrap()));
+    let itmn_file = std::env::var("ITMN_FILE").unwrap_or_else(|_| {
+        utils::paths::data_dir()
+            .join("itmn")
+            .to_string_lossy()
+            .into_owned()
+    });
 
     let options = cli::Options::parse();
-    let subcmd = options.subcmd;
+
+    if options.version {
+        utils::version::print_version("itmn", env!("CARGO_PKG_VERSION"));
+        return ExitCode::SUCCESS;
+    }
+
+    utils::log::init(options.verbose);
+
+    let default_context = options
+        .context_default
+        .or_else(|| std::env::var("ITMN_DEFAULT_CONTEXT").ok())
+        .filter(|ctx| !ctx.is_empty());
+
+    let mut subcmd = options.subcmd;
+    if let (Some(subcmd), Some(ctx)) = (subcmd.as_mut(), &default_context) {
+        subcmd.apply_default_context(ctx);
+    }
+
+    let id_base = match options.id_base {
+        Some(base) => base,
+        None => match std::env::var("ITMN_ID_BASE") {
+            Ok(var) if !var.is_empty() => match IdBase::parse(&var) {
+                Ok(base) => base,
+                Err(e) => {
+                    eprintln!("Invalid $ITMN_ID_BASE: {}", e);
+                    return ExitCode::new(1);
+                }
+            },
+            _ => IdBase::Decimal,
+        },
+    };
+
+    let context_colors = match options.context_color {
+        false => None,
+        true => match std::env::var("ITMN_CONTEXT_COLORS") {
+            Ok(var) => match ContextColors::parse(&var) {
+                Ok(colors) => Some(colors),
+                Err(e) => {
+                    eprintln!("Invalid $ITMN_CONTEXT_COLORS: {}", e);
+                    return ExitCode::new(1);
+                }
+            },
+            Err(_) => Some(ContextColors::default()),
+        },
+    };
+
+    let show_internal = options.show_internal;
+    let plain = options.plain;
+    let show_annotations = !options.hide_annotations;
+    let indent_style = options.indent.unwrap_or(IndentStyle::Spaces(2));
+    let no_save = options.no_save;
     let path_string = options.path.unwrap_or(itmn_file);
     let path = Path::new(&path_string);
 
+    let output = options.output.as_deref().map(Path::new);
+
+    let report_cfg = ReportConfig {
+        indent_style,
+        reverse_children: false,
+        show_internal,
+        plain,
+        id_base,
+        show_annotations,
+        context_colors,
+    };
+
+    utils::signal::release_locks_on_interrupt();
+
     const LOCK_NAME: &str = "itmn";
-    let _lock = match utils::tmp::make_folder_lock(LOCK_NAME) {
-        Ok(lock) => lock,
-        Err(why) => {
-            eprintln!("Failed to create lock `{}`: {}", LOCK_NAME, why);
-            return ExitCode::new(1);
+    let read_only = subcmd.as_ref().is_none_or(SubCmd::is_read_only);
+    let _lock = if options.no_lock {
+        utils::warn!("running without a lock (--no-lock); changes from other instances running at the same time could corrupt the file or be lost");
+        None
+    } else if read_only {
+        utils::debug!("not locking, since the subcommand doesn't change anything");
+        None
+    } else {
+        match utils::tmp::make_folder_lock(LOCK_NAME) {
+            Ok(lock) => {
+                utils::debug!("acquired lock `{}`", LOCK_NAME);
+                Some(lock)
+            }
+            Err(why) => {
+                eprintln!("Failed to create lock `{}`: {}", LOCK_NAME, why);
+                return ExitCode::new(1);
+            }
         }
     };
 
-    let contents = match utils::io::touch_read(&path) {
-        Ok(string) => string,
-        Err(why) => {
-            eprintln!("Failed to load file: {}", why);
-            return ExitCode::new(1);
-        }
+    // This is also how the file is read when its format can't be told (e.g. YAML without an extension).
+    let requested_format = match options.format {
+        Some(format) => Some(format),
+        None => match StorageFormat::from_env("ITMN_FORMAT") {
+            Ok(format) => format,
+            Err(e) => {
+                eprintln!("Invalid $ITMN_FORMAT: {}", e);
+                return ExitCode::new(1);
+            }
+        },
     };
 
-    let data: Vec<Item> = match data_serialize::import(validate_parsed_string(&contents)) {
-        Ok(data) => data,
+    // The watch loads the file by itself on every change, and never changes it.
+    if let Some(SubCmd::Watch(ref args)) = subcmd {
+        let mut out: Box<dyn Write> = match output {
+            Some(output) => match File::create(output) {
+                Ok(file) => Box::new(file),
+                Err(why) => {
+                    eprintln!("Failed to open output file {:?}: {}", output, why);
+                    return ExitCode::new(1);
+                }
+            },
+            None => Box::new(io::stdout()),
+        };
+
+        return subcmd_watch(path, requested_format, args, &report_cfg, &mut *out);
+    }
+
+    let (data, loaded_format) = match load_items(path, requested_format) {
+        Ok(loaded) => loaded,
         Err(why) => {
-            eprintln!("Failed to parse file: {}", why);
+            eprintln!("Failed to load file: {}", why);
             return ExitCode::new(1);
         }
     };
 
+    // Keep the format the file already had, unless told otherwise.
+    let save_format = requested_format.or(loaded_format).unwrap_or_default();
+
+    // The doctor has to look at the data as-is, since the manager refuses (or fixes) some of the problems it reports.
+    if let Some(SubCmd::Doctor) = subcmd {
+        return subcmd_doctor(&data);
+    }
+
+    // Same for the diff, which also never changes anything.
+    if let Some(SubCmd::Diff(ref args)) = subcmd {
+        return match with_report_output(output, |out| subcmd_diff(&data, path, args, out)) {
+            Ok(code) => code,
+            Err(why) => {
+                eprintln!("{}", why);
+                ExitCode::new(1)
+            }
+        };
+    }
+
     let mut manager = match ItemManager::new(data) {
         Ok(manager) => manager,
         Err(ManagerError::RepeatedRefID(RefId(id))) => {
@@ -74,26 +208,87 @@ fn main() -> ExitCode {
             );
             return ExitCode::new(1);
         }
+        Err(ManagerError::InvalidItems(violations)) => {
+            eprintln!("Invalid items in file (found on strict mode):");
+            for violation in violations {
+                eprintln!("  {}", violation);
+            }
+            return ExitCode::new(1);
+        }
     };
 
-    let code = manager.start_program_with_file(&path, |manager| {
-        type UsedReport = report::BasicReport;
-        const DEFAULT_SUBCOMMAND: SubCmd = SubCmd::List;
-        const DEFAULT_SPACES_PER_INDENT: usize = 2;
+    let data_file = DataFile {
+        path,
+        format: save_format,
+        no_save,
+    };
 
-        let report_cfg = ReportConfig {
-            spaces_per_indent: DEFAULT_SPACES_PER_INDENT,
-        };
+    let code = manager.start_program_with_file(&path, save_format, |manager| {
+        type UsedReport = report::BasicReport;
 
-        let result = match subcmd.unwrap_or(DEFAULT_SUBCOMMAND) {
-            SubCmd::SelRefID(args) => subcmd_selection::<UsedReport>(manager, args, &report_cfg),
-            SubCmd::Add(args) => subcmd_add(manager, args),
-            SubCmd::List => subcmd_list::<UsedReport>(manager, &report_cfg),
-            SubCmd::Next => subcmd_next::<UsedReport>(manager, &report_cfg),
-            SubCmd::FlatList => subcmd_flatlist(manager, &report_cfg),
+        let result = match subcmd.unwrap_or_else(|| SubCmd::List(ListOptions::default())) {
+            SubCmd::SelRefID(args) => subcmd_selection::<UsedReport>(
+                manager,
+                args,
+                &report_cfg,
+                &tmp::ExternalEditor,
+                &data_file,
+                &mut io::stdout(),
+            ),
+            SubCmd::SelInternalID(args) => {
+                subcmd_sel_internal::<UsedReport>(manager, args, &report_cfg, &mut io::stdout())
+            }
+            SubCmd::Add(args) => subcmd_add(manager, args, &report_cfg),
+            SubCmd::List(args) => with_report_output(output, |out| {
+                subcmd_list::<UsedReport>(manager, args, &report_cfg, out)
+            })
+            .and_then(|result| result),
+            SubCmd::Next(args) => with_report_output(output, |out| {
+                subcmd_next::<UsedReport>(manager, args, &report_cfg, out)
+            })
+            .and_then(|result| result),
+            SubCmd::FlatList(args) => with_report_output(output, |out| {
+                subcmd_flatlist(manager, args, &report_cfg, path, out)
+            })
+            .and_then(|result| result),
+            SubCmd::Doctor => unreachable!("doctor runs before the manager is created"),
+            SubCmd::Diff(_) => unreachable!("diff runs before the manager is created"),
+            SubCmd::Watch(_) => unreachable!("watch runs before the manager is created"),
+            SubCmd::Recent(args) => with_report_output(output, |out| {
+                subcmd_recent::<UsedReport>(manager, args, &report_cfg, out)
+            })
+            .and_then(|result| result),
+            SubCmd::Completed(args) => with_report_output(output, |out| {
+                subcmd_completed::<UsedReport>(manager, args, &report_cfg, out)
+            })
+            .and_then(|result| result),
+            SubCmd::Badge(args) => {
+                with_report_output(output, |out| subcmd_badge(manager, args, out))
+                    .and_then(|result| result)
+            }
+            SubCmd::DoneContext(args) => {
+                subcmd_done_context::<UsedReport>(manager, args, &report_cfg, &mut io::stdout())
+            }
+            SubCmd::EditAll => subcmd_edit_all(manager, &tmp::ExternalEditor),
+            SubCmd::Menu => subcmd_menu::<UsedReport>(
+                manager,
+                default_context.as_deref(),
+                &report_cfg,
+                &tmp::ExternalEditor,
+                &Fzagnostic,
+                &data_file,
+                &mut io::stdout(),
+            ),
         };
 
         match result {
+            Ok(pr) if no_save && pr.should_save => {
+                eprintln!("Changes were not saved (--no-save).");
+                ProgramResult {
+                    should_save: false,
+                    ..pr
+                }
+            }
             Ok(pr) => pr,
             Err(e) => {
                 eprintln!("Error: {}", e);
@@ -108,25 +303,269 @@ fn main() -> ExitCode {
     ExitCode::new(code)
 }
 
+/// Runs `f` with the writer that the listing reports go to: the standard output or, if `output` (the `--output` file)
+/// is given, a buffer that is written to it afterwards. The file is only created if there's a report to write, so runs
+/// that fail or print nothing leave it alone.
+fn with_report_output<T>(
+    output: Option<&Path>,
+    f: impl FnOnce(&mut dyn Write) -> T,
+) -> Result<T, String> {
+    let output = match output {
+        Some(output) => output,
+        None => return Ok(f(&mut io::stdout())),
+    };
+
+    let mut buffer = Vec::new();
+    let result = f(&mut buffer);
+
+    if !buffer.is_empty() {
+        std::fs::write(output, &buffer)
+            .map_err(|why| format!("Failed to write output file {:?}: {}", output, why))?;
+    }
+
+    Ok(result)
+}
+
+/// Clears the terminal and moves the cursor to its top left corner.
+const CLEAR_SCREEN: &str = "\x1b[2J\x1b[H";
+
+/// Shows a report and then shows it again whenever the file changes, which is checked by polling its modification time
+/// and size. Like the diff, this runs on its own and never saves (nor takes the lock).
+///
+/// Only returns if the output can't be written to anymore.
+fn subcmd_watch(
+    path: &Path,
+    format: Option<StorageFormat>,
+    args: &WatchOptions,
+    report_cfg: &ReportConfig,
+    out: &mut dyn Write,
+) -> ExitCode {
+    let stamp = || {
+        std::fs::metadata(path)
+            .ok()
+            .map(|meta| (meta.modified().ok(), meta.len()))
+    };
+
+    let mut last_stamp = None;
+
+    loop {
+        let current = Some(stamp());
+
+        if current != last_stamp {
+            last_stamp = current;
+
+            let rendered = render_watched(path, format, &args.report, report_cfg);
+            if let Err(why) = write!(out, "{}{}", CLEAR_SCREEN, rendered).and_then(|_| out.flush())
+            {
+                eprintln!("Failed to write report: {}", why);
+                return ExitCode::new(1);
+            }
+        }
+
+        std::thread::sleep(std::time::Duration::from_secs(args.interval));
+    }
+}
+
+/// Loads the file at `path` and renders `report` with it. If the file can't be loaded, the error is rendered instead,
+/// so the watch can go on until the file is fixed.
+fn render_watched(
+    path: &Path,
+    format: Option<StorageFormat>,
+    report: &WatchedReport,
+    report_cfg: &ReportConfig,
+) -> String {
+    type UsedReport = report::BasicReport;
+
+    let manager = match load_read_only(path, format) {
+        Ok(manager) => manager,
+        Err(why) => {
+            return format!(
+                "Failed to load file: {}\n(waiting for {} to change)\n",
+                why,
+                path.display()
+            )
+        }
+    };
+
+    let mut out = Vec::new();
+    let result = match report.clone() {
+        WatchedReport::List(args) => {
+            subcmd_list::<UsedReport>(&manager, args, report_cfg, &mut out)
+        }
+        WatchedReport::Next(args) => {
+            subcmd_next::<UsedReport>(&manager, args, report_cfg, &mut out)
+        }
+        WatchedReport::Recent(args) => {
+            subcmd_recent::<UsedReport>(&manager, args, report_cfg, &mut out)
+        }
+        WatchedReport::Completed(args) => {
+            subcmd_completed::<UsedReport>(&manager, args, report_cfg, &mut out)
+        }
+        WatchedReport::Badge(args) => subcmd_badge(&manager, args, &mut out),
+    };
+
+    let mut rendered = String::from_utf8_lossy(&out).into_owned();
+    if let Err(e) = result {
+        rendered.push_str(&format!("Error: {}\n", e));
+    }
+
+    rendered
+}
+
+/// Reads and parses the items on `path`, creating the file if it doesn't exist. Also returns the format the file was
+/// found to be in, if it could be told; if not, it's read in `format`.
+fn load_items(
+    path: &Path,
+    format: Option<StorageFormat>,
+) -> Result<(Vec<Item>, Option<StorageFormat>), String> {
+    let (contents, replaced) = utils::io::touch_read_lossy(path)?;
+    if replaced {
+        utils::warn!(
+            "file has invalid UTF-8 data, which was replaced; it'll be lost if the file is saved"
+        );
+    }
+
+    let contents = utils::io::sanitize_loaded(&contents);
+    if let Cow::Owned(_) = contents {
+        utils::warn!(
+            "file has control characters, which were removed; they'll be lost if the file is saved"
+        );
+    }
+
+    let loaded_format = StorageFormat::for_file(path, &contents);
+    let data = loaded_format
+        .or(format)
+        .unwrap_or_default()
+        .import(&contents)
+        .map_err(|e| format!("invalid data: {}", e))?;
+
+    Ok((data, loaded_format))
+}
+
+/// Loads the items on `path` into a manager (see [`load_items`]), without creating the file if it doesn't exist.
+fn load_read_only(path: &Path, format: Option<StorageFormat>) -> Result<ItemManager, String> {
+    // touch_read_lossy would create the file, which isn't wanted here
+    if !path.is_file() {
+        return Err(format!("{} doesn't exist", path.display()));
+    }
+
+    let (data, _) = load_items(path, format)?;
+
+    ItemManager::new(data).map_err(|e| match e {
+        ManagerError::RepeatedRefID(RefId(id)) => format!("repeated reference ID: {}", id),
+        ManagerError::RepeatedInternalID(InternalId(id)) => {
+            format!("repeated internal ID: {}", id)
+        }
+        ManagerError::InvalidItems(violations) => {
+            format!("invalid items (strict mode): {}", violations.join("; "))
+        }
+    })
+}
+
 fn subcmd_add(
     manager: &mut ItemManager,
-    ItemAddDetails {
+    details: ItemAddDetails,
+    report_cfg: &ReportConfig,
+) -> Result<ProgramResult, String> {
+    let fmt_id = |id| report_cfg.id_base.format_id(id);
+
+    let description = details.initial_description()?;
+    let skeleton = details.skeleton(&description)?;
+    let after = details.after_id(report_cfg.id_base)?;
+    let under = details.under_id(report_cfg.id_base)?;
+    let ItemAddDetails {
         name,
         context,
         note,
-        description,
-    }: ItemAddDetails,
-) -> Result<ProgramResult, String> {
-    let RefId(ref_id) = manager.add_item_on_root(
-        &name,
-        &context.unwrap_or(String::new()),
-        match note {
-            Some(false) | None => ItemState::Todo,
-            Some(true) => ItemState::Note,
-        },
-        description.unwrap_or_else(String::new), // description
-        Vec::new(),                              // children
-    );
+        template,
+        ..
+    } = details;
+
+    let parent = match under {
+        Some(id) if manager.find(RefId(id)).is_none() => {
+            return Err(format!("invalid ID: #{}", fmt_id(id)))
+        }
+        Some(id) => Some(RefId(id)),
+        None => None,
+    };
+
+    let index = match after {
+        Some(after) => {
+            let siblings = match parent {
+                Some(parent) => &manager.find(parent).unwrap().children,
+                None => &manager.data,
+            };
+
+            match manager::position_after(siblings, RefId(after)) {
+                Some(index) => Some(index),
+                None if manager.find(RefId(after)).is_some() => {
+                    return Err(match parent {
+                        Some(RefId(parent)) => format!(
+                            "item #{} is not a child of #{}",
+                            fmt_id(after),
+                            fmt_id(parent)
+                        ),
+                        None => format!(
+                            "item #{} is not at the root (to add a child after it, use `--under <parent> --after {}`)",
+                            fmt_id(after),
+                            fmt_id(after)
+                        ),
+                    })
+                }
+                None => return Err(format!("invalid ID: #{}", fmt_id(after))),
+            }
+        }
+        None => None,
+    };
+
+    if let Some(skeleton) = skeleton {
+        let item = manager.add_skeleton(parent, &skeleton, index)?;
+
+        match item.ref_id {
+            Some(ref_id) => eprintln!("Item Added! | RefID: {}", ref_id),
+            None => eprintln!("Item Added! | InternalID: {}", item.internal_id),
+        }
+
+        return Ok(ProgramResult {
+            should_save: true,
+            exit_status: 0,
+        });
+    }
+
+    // Load the template before adding anything, so an unknown name doesn't leave a half-made item behind.
+    let templates = match template {
+        Some(_) => Templates::load()?,
+        None => Templates::empty(),
+    };
+    let template_items = match template {
+        Some(ref name) => templates.get(name)?,
+        None => &[],
+    };
+
+    let name = name.expect("clap requires a name without --from-json");
+    let context = context.unwrap_or(String::new());
+    let state = match note {
+        Some(false) | None => ItemState::Todo,
+        Some(true) => ItemState::Note,
+    };
+
+    let RefId(ref_id) = match parent {
+        Some(parent) => manager
+            .add_child(
+                parent,
+                &name,
+                &context,
+                state,
+                description,
+                Vec::new(),
+                index,
+            )
+            .expect("the parent was checked above"),
+        None => manager.add_item_on_root(&name, &context, state, description, Vec::new(), index),
+    };
+
+    template::instantiate(manager, RefId(ref_id), template_items)
+        .expect("newly added item should exist");
 
     eprintln!("Item Added! | RefID: {}", ref_id);
 
@@ -136,29 +575,426 @@ fn subcmd_add(
     })
 }
 
-/// A function for the `list` subcommand.
+/// Collects the surface items of the manager, in the order specified by `args`.
+fn surface_items<'a>(manager: &'a ItemManager, args: &ListOptions) -> Vec<&'a Item> {
+    let mut items: Vec<&Item> = manager
+        .surface_ref_ids()
+        .iter()
+        .map(|&i| manager.find(i).unwrap())
+        .collect();
+
+    if args.reverse {
+        items.reverse();
+    }
+
+    items
+}
+
+/// Keeps the first `limit` items that pass `filter` (all of them if there's no limit), returning them along with how
+/// many were left out. Items that don't pass the filter wouldn't be shown anyways, so they aren't counted.
+fn limit_items<'a>(
+    items: Vec<&'a Item>,
+    limit: Option<usize>,
+    filter: &dyn Fn(&Item) -> bool,
+) -> (Vec<&'a Item>, usize) {
+    let mut shown = items.into_iter().filter(|item| filter(item));
+
+    match limit {
+        Some(limit) => {
+            let kept: Vec<&Item> = shown.by_ref().take(limit).collect();
+            (kept, shown.count())
+        }
+        None => (shown.collect(), 0),
+    }
+}
+
+/// Writes the footer for items left out by `--limit`, if any. Like the header, it's left out on plain reports.
+fn write_remainder(
+    remainder: usize,
+    report_cfg: &ReportConfig,
+    out: &mut dyn Write,
+) -> io::Result<()> {
+    match remainder {
+        0 => Ok(()),
+        _ if report_cfg.plain => Ok(()),
+        n => writeln!(out, "… and {} more", n),
+    }
+}
+
+/// A function for the `list` subcommand.
+///
+/// Type argument `R` is the type of report that should be shown.
+fn subcmd_list<R: Report>(
+    manager: &ItemManager,
+    args: ListOptions,
+    report_cfg: &ReportConfig,
+    out: &mut dyn Write,
+) -> Result<ProgramResult, String> {
+    let surface = surface_items(manager, &args);
+    let report_cfg = &args.report_config(report_cfg);
+    let filter = |i: &Item| args.shows(i);
+
+    let (items, remainder) = limit_items(surface.clone(), args.limit, &filter);
+
+    if args.show_summary(false) {
+        writeln!(
+            out,
+            "{}",
+            report::context_summary(items.iter().cloned(), None)
+        )
+        .unwrap();
+    }
+
+    if args.context_tree {
+        let headers = match args.show_empty {
+            // the filtered out items give their contexts a header, but they're still hidden by the filter
+            true => report::context_tree(
+                surface.iter().cloned().filter(|&item| {
+                    !filter(item) || items.iter().any(|&shown| std::ptr::eq(shown, item))
+                }),
+                None,
+            ),
+            false => report::context_tree(items, Some(&filter)),
+        };
+
+        // the headers aren't real items, so they always pass the filter
+        let filter = |i: &Item| i.internal_id == report::VIRTUAL_ID || args.shows(i);
+
+        R::report(
+            "All items (by context)",
+            &mut headers.iter(),
+            &ReportInfo::new(report_cfg, ReportDepth::Tree, Some(&filter)),
+            out,
+        )
+        .unwrap();
+    } else {
+        R::report(
+            "All items (surface)",
+            &mut items.into_iter(),
+            &ReportInfo::new(
+                report_cfg,
+                if args.collapsed {
+                    ReportDepth::Collapsed
+                } else {
+                    ReportDepth::Tree
+                },
+                Some(&filter),
+            ),
+            out,
+        )
+        .unwrap();
+    }
+    write_remainder(remainder, report_cfg, out).unwrap();
+
+    Ok(ProgramResult {
+        should_save: false,
+        exit_status: 0,
+    })
+}
+
+/// Parses a range of internal IDs, where each number can have a leading `i` (like `i4..i7,i9`).
+fn parse_internal_range(range: &str, base: IdBase) -> Result<Vec<u32>, String> {
+    let range: String = range
+        .split(',')
+        .map(|part| {
+            part.split("..")
+                .map(|end| {
+                    let end = end.trim();
+                    end.strip_prefix('i').unwrap_or(end)
+                })
+                .collect::<Vec<_>>()
+                .join("..")
+        })
+        .collect::<Vec<_>>()
+        .join(",");
+
+    utils::misc::parse_range_str_in(&range, base)
+        .map_err(|e| format!("failed to parse range: {}", e))
+}
+
+/// A function for the `sel-internal` subcommand, which selects items by internal ID so items without reference IDs
+/// (the done ones) can be acted on.
+fn subcmd_sel_internal<R: Report>(
+    manager: &mut ItemManager,
+    args: InternalSelectionDetails,
+    report_cfg: &ReportConfig,
+    out: &mut dyn Write,
+) -> Result<ProgramResult, String> {
+    let range = parse_internal_range(&args.range, report_cfg.id_base)?;
+
+    if range.is_empty() {
+        return Err("no selection was specified".into());
+    }
+
+    let mut selected = Vec::with_capacity(range.len());
+    for &id in &range {
+        match manager.find(InternalId(id)) {
+            Some(item) => selected.push(item),
+            None => {
+                return Err(format!(
+                    "invalid ID on the selection: i{}",
+                    report_cfg.id_base.format_id(id)
+                ))
+            }
+        }
+    }
+
+    match args.action {
+        InternalSelectionAction::ShowPath => {
+            for &id in &range {
+                let path = manager.ancestry(InternalId(id)).unwrap();
+                writeln!(out, "{}", path_line(&path)).unwrap();
+            }
+
+            Ok(ProgramResult {
+                should_save: false,
+                exit_status: 0,
+            })
+        }
+        InternalSelectionAction::Reopen(force) => {
+            if let Some(item) = selected.iter().find(|i| i.state != ItemState::Done) {
+                return Err(format!(
+                    "i{} ({:?}) is not done, so it can't be reopened",
+                    report_cfg.id_base.format_id(item.internal_id),
+                    item.name
+                ));
+            }
+
+            R::report(
+                "Items to be reopened",
+                &mut selected.into_iter(),
+                &ReportInfo::new(report_cfg, ReportDepth::Shallow, None),
+                out,
+            )
+            .unwrap();
+
+            if !force.force.unwrap_or(false) && !confirm_with_default(true) {
+                return Ok(ProgramResult {
+                    should_save: false,
+                    exit_status: 1,
+                });
+            }
+
+            for &id in &range {
+                let RefId(ref_id) = manager.reopen(InternalId(id))?;
+                eprintln!(
+                    "Reopened i{} as #{}",
+                    report_cfg.id_base.format_id(id),
+                    report_cfg.id_base.format_id(ref_id)
+                );
+            }
+
+            Ok(ProgramResult {
+                should_save: true,
+                exit_status: 0,
+            })
+        }
+    }
+}
+
+/// A function for the `flat-list` subcommand.
+///
+/// The numbers shown are saved on the [`FlatIndex`], so they can be used on the selection later.
+///
+/// [`FlatIndex`]: FlatIndex
+fn subcmd_flatlist(
+    manager: &ItemManager,
+    args: ListOptions,
+    report_cfg: &ReportConfig,
+    data_path: &Path,
+    out: &mut dyn Write,
+) -> Result<ProgramResult, String> {
+    if args.context_tree {
+        return Err("--context-tree is only supported on [list]".into());
+    }
+    if args.collapsed {
+        return Err("--collapsed is not supported on [flat-list]".into());
+    }
+
+    let items = surface_items(manager, &args);
+    let report_cfg = &args.report_config(report_cfg);
+    let filter = |i: &Item| args.shows(i);
+
+    let (items, remainder) = limit_items(items, args.limit, &filter);
+
+    if args.show_summary(false) {
+        writeln!(
+            out,
+            "{}",
+            report::context_summary(items.iter().cloned(), None)
+        )
+        .unwrap();
+    }
+
+    let ref_ids = FlatReport::report_numbered(
+        "All items (flat report)",
+        &mut items.into_iter(),
+        &ReportInfo::new(report_cfg, ReportDepth::Tree, Some(&filter)),
+        out,
+    )
+    .unwrap();
+    write_remainder(remainder, report_cfg, out).unwrap();
+
+    if let Err(e) = FlatIndex::new(data_path, ref_ids).save() {
+        utils::warn!("failed to save the flat index: {}", e);
+    }
+
+    Ok(ProgramResult {
+        should_save: false,
+        exit_status: 0,
+    })
+}
+
+/// A function for the `doctor` subcommand.
+///
+/// Unlike the other subcommands, this runs on the raw data, and never saves.
+fn subcmd_doctor(data: &[Item]) -> ExitCode {
+    let problems = manager::diagnose(data);
+
+    if problems.is_empty() {
+        eprintln!("No problems found.");
+        ExitCode::SUCCESS
+    } else {
+        for problem in &problems {
+            eprintln!("* {}", problem);
+        }
+
+        eprintln!("{} problem(s) found.", problems.len());
+        ExitCode::FAILURE
+    }
+}
+
+/// A function for the `diff` subcommand.
+///
+/// Like the doctor, this runs on the raw data, and never saves.
+fn subcmd_diff(data: &[Item], path: &Path, args: &DiffOptions, out: &mut dyn Write) -> ExitCode {
+    let old_path = match args.against {
+        Some(ref against) => PathBuf::from(against),
+        None => utils::io::backup_path(path),
+    };
+
+    // touch_read would create the file, which isn't wanted here
+    if !old_path.is_file() {
+        eprintln!(
+            "Nothing to compare against: {} doesn't exist.",
+            old_path.display()
+        );
+        return ExitCode::FAILURE;
+    }
+
+    let old_data: Vec<Item> = match utils::io::touch_read(&old_path).and_then(|contents| {
+        StorageFormat::for_file(&old_path, &contents)
+            .unwrap_or_default()
+            .import(&contents)
+            .map_err(|e| format!("failed to parse file: {}", e))
+    }) {
+        Ok(data) => data,
+        Err(why) => {
+            eprintln!("Failed to load {}: {}", old_path.display(), why);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let changes = diff::diff(&old_data, data);
+
+    if changes.is_empty() {
+        eprintln!("No changes since {}.", old_path.display());
+    }
+
+    for change in &changes {
+        writeln!(out, "{}", change).unwrap();
+    }
+
+    ExitCode::SUCCESS
+}
+
+/// A function for the `recent` subcommand.
+///
+/// Type argument `R` is the type of report that should be shown.
+fn subcmd_recent<R: Report>(
+    manager: &ItemManager,
+    args: RecentOptions,
+    report_cfg: &ReportConfig,
+    out: &mut dyn Write,
+) -> Result<ProgramResult, String> {
+    let (since, label) = match args.since {
+        Some(ref date) => {
+            let (since, date) = parse_since(date)?;
+            (since, format!("Modified since {}", date))
+        }
+        None => (
+            item::now_timestamp().saturating_sub(args.days.saturating_mul(24 * 60 * 60)),
+            format!("Modified in the last {} day(s)", args.days),
+        ),
+    };
+
+    let mut items: Vec<(u64, &Item)> = Vec::new();
+    manager.walk(|item, _| match item.modified {
+        Some(modified) if modified >= since => items.push((modified, item)),
+        _ => (),
+    });
+
+    // newest first
+    items.sort_by(|(a, _), (b, _)| b.cmp(a));
+
+    R::report(
+        &label,
+        &mut items.into_iter().map(|(_, item)| item),
+        &ReportInfo::new(report_cfg, ReportDepth::Shallow, None),
+        out,
+    )
+    .unwrap();
+
+    Ok(ProgramResult {
+        should_save: false,
+        exit_status: 0,
+    })
+}
+
+/// Parses a `--since` date, returning the timestamp of its start (local midnight) along with the date, formatted.
+fn parse_since(date: &str) -> Result<(u64, String), String> {
+    let date = utils::date::parse_relative_date(date, utils::date::today())?;
+    let midnight = utils::date::start_of_day_timestamp(date)
+        .ok_or_else(|| format!("invalid local time on {}", date))?;
+
+    Ok((midnight.max(0) as u64, date.to_string()))
+}
+
+/// A function for the `completed` subcommand.
 ///
-/// Type argument `R` is the type of report that should be shown.
-fn subcmd_list<R: Report>(
+/// Done items have no reference ID, so they're shown shallowly with their internal IDs instead. There's no completion
+/// date, so `--since` goes by when the item was last modified; items without a modification date are left out then.
+fn subcmd_completed<R: Report>(
     manager: &ItemManager,
+    args: CompletedOptions,
     report_cfg: &ReportConfig,
+    out: &mut dyn Write,
 ) -> Result<ProgramResult, String> {
-    let items: Vec<&Item> = manager
-        .surface_ref_ids()
-        .iter()
-        .map(|&i| manager.find(i).unwrap())
-        .collect();
+    let (since, label) = match args.since {
+        Some(ref date) => {
+            let (since, date) = parse_since(date)?;
+            (Some(since), format!("Done items (modified since {})", date))
+        }
+        None => (None, "Done items".into()),
+    };
+
+    let mut items: Vec<&Item> = Vec::new();
+    manager.walk(|item, _| {
+        let recent_enough = match since {
+            Some(since) => item.modified.is_some_and(|modified| modified >= since),
+            None => true,
+        };
+
+        if item.state == ItemState::Done && recent_enough {
+            items.push(item);
+        }
+    });
 
     R::report(
-        "All items (surface)",
+        &label,
         &mut items.into_iter(),
-        &ReportInfo {
-            config: report_cfg,
-            indent: 0,
-            filter: Some(&|i: &Item| i.state != ItemState::Done),
-            depth: ReportDepth::Tree,
-        },
-        &mut io::stdout(),
+        &ReportInfo::new(report_cfg, ReportDepth::Shallow, None),
+        out,
     )
     .unwrap();
 
@@ -168,32 +1004,83 @@ fn subcmd_list<R: Report>(
     })
 }
 
-/// A function for the `flat-list` subcommand.
-fn subcmd_flatlist(
+/// A function for the `badge` subcommand, which prints only a line with the item counts, so it's easy to use on
+/// scripts.
+fn subcmd_badge(
     manager: &ItemManager,
+    args: BadgeOptions,
+    out: &mut dyn Write,
+) -> Result<ProgramResult, String> {
+    let counts = report::StateCounts::count(&manager.data);
+    writeln!(out, "{}", counts.render(&args.format)).unwrap();
+
+    Ok(ProgramResult {
+        should_save: false,
+        exit_status: 0,
+    })
+}
+
+/// A function for the `done-context` subcommand, which shows the open items with a context and marks them as done
+/// once confirmed.
+///
+/// Type argument `R` is the type of report that should be shown.
+fn subcmd_done_context<R: Report>(
+    manager: &mut ItemManager,
+    args: DoneContextArgs,
     report_cfg: &ReportConfig,
+    out: &mut dyn Write,
 ) -> Result<ProgramResult, String> {
-    let items: Vec<&Item> = manager
-        .surface_ref_ids()
+    let context = args.context();
+    let ids = manager.open_with_context(context, args.recursive);
+
+    if ids.is_empty() {
+        writeln!(out, "No open items with @{}.", context).unwrap();
+        return Ok(ProgramResult {
+            should_save: false,
+            exit_status: 0,
+        });
+    }
+
+    let selected: Vec<&Item> = ids.iter().map(|&id| manager.find(id).unwrap()).collect();
+
+    // with --recursive, the items under other selected items are already shown along with them
+    let roots: Vec<&Item> = selected
         .iter()
-        .map(|&i| manager.find(i).unwrap())
+        .filter(|&&item| !(args.recursive && selected.iter().any(|other| other.has_child(item))))
+        .cloned()
         .collect();
 
-    FlatReport::report(
-        "All items (flat report)",
-        &mut items.into_iter(),
-        &ReportInfo {
-            config: report_cfg,
-            indent: 0,
-            filter: Some(&|i: &Item| i.state != ItemState::Done),
-            depth: ReportDepth::Tree,
-        },
-        &mut io::stdout(),
+    let filter = |item: &Item| item.state == ItemState::Todo;
+    R::report(
+        "Items to be marked as done",
+        &mut roots.into_iter(),
+        &ReportInfo::new(
+            report_cfg,
+            match args.recursive {
+                true => ReportDepth::Tree,
+                false => ReportDepth::Shallow,
+            },
+            Some(&filter),
+        ),
+        out,
     )
     .unwrap();
 
+    if !args.yes && !confirm_with_default(true) {
+        return Ok(ProgramResult {
+            should_save: false,
+            exit_status: 1,
+        });
+    }
+
+    for &id in &ids {
+        manager.change_item_state(id, |_| ItemState::Done).unwrap(); // safe because the IDs were just found
+    }
+
+    writeln!(out, "Marked {} item(s) as done.", ids.len()).unwrap();
+
     Ok(ProgramResult {
-        should_save: false,
+        should_save: true,
         exit_status: 0,
     })
 }
@@ -203,26 +1090,46 @@ fn subcmd_flatlist(
 /// Type argument `R` is the type of report that should be shown.
 fn subcmd_next<R: Report>(
     manager: &ItemManager,
+    args: ListOptions,
     report_cfg: &ReportConfig,
+    out: &mut dyn Write,
 ) -> Result<ProgramResult, String> {
-    let items: Vec<&Item> = manager
-        .surface_ref_ids()
-        .iter()
-        .map(|&i| manager.find(i).unwrap())
-        .collect();
+    if args.context_tree {
+        return Err("--context-tree is only supported on [list]".into());
+    }
+
+    let items = surface_items(manager, &args);
+    let report_cfg = &args.report_config(report_cfg);
+    let filter = |i: &Item| args.shows(i);
+
+    let (items, remainder) = limit_items(items, args.limit, &filter);
+
+    // plain reports are meant to be piped, so only show the summary if asked to
+    if args.show_summary(!report_cfg.plain) {
+        writeln!(
+            out,
+            "{}",
+            report::context_summary(items.iter().cloned(), None)
+        )
+        .unwrap();
+    }
 
     R::report(
         "Next",
         &mut items.into_iter(),
-        &ReportInfo {
-            config: report_cfg,
-            indent: 0,
-            filter: Some(&|i: &Item| i.state != ItemState::Done),
-            depth: ReportDepth::Brief,
-        },
-        &mut io::stdout(),
+        &ReportInfo::new(
+            report_cfg,
+            if args.collapsed {
+                ReportDepth::Collapsed
+            } else {
+                ReportDepth::Brief
+            },
+            Some(&filter),
+        ),
+        out,
     )
     .unwrap();
+    write_remainder(remainder, report_cfg, out).unwrap();
 
     Ok(ProgramResult {
         should_save: false,
@@ -230,6 +1137,250 @@ fn subcmd_next<R: Report>(
     })
 }
 
+/// Builds the line shown for `item` on the menu. It starts with the reference ID, so the chosen line can be mapped
+/// back to the item with [`menu_line_ref_id`].
+///
+/// [`menu_line_ref_id`]: menu_line_ref_id
+fn menu_line(ref_id: u32, item: &Item) -> String {
+    match item.context() {
+        Some(ctx) => format!("#{:>02} {} @{}", ref_id, item.name, ctx),
+        None => format!("#{:>02} {}", ref_id, item.name),
+    }
+}
+
+/// Gets the reference ID back from a line made by [`menu_line`].
+///
+/// [`menu_line`]: menu_line
+fn menu_line_ref_id(line: &str) -> Option<u32> {
+    line.trim_start()
+        .strip_prefix('#')?
+        .split_whitespace()
+        .next()?
+        .parse()
+        .ok()
+}
+
+/// A function for the `edit-all` subcommand, which edits the whole tree as text (see the [`outline`] module).
+///
+/// If the edited text can't be read back, the user is asked whether to edit it again, so the changes aren't lost.
+///
+/// [`outline`]: outline
+fn subcmd_edit_all(
+    manager: &mut ItemManager,
+    editor: &dyn EditorBackend,
+) -> Result<ProgramResult, String> {
+    let recovery_key = RecoveryKey("itmn-edit-all".into());
+    let mut text = outline::render(&manager.data);
+
+    let new_data = loop {
+        text = match editor.edit_recoverable(&text, Some("txt"), &recovery_key) {
+            Ok((new, 0)) => new,
+            Ok((_, code)) => return Err(format!("non-zero exit code: {}", code)),
+            Err(e) => return Err(format!("failed to edit text: {}", e)),
+        };
+
+        match outline::parse(&text)
+            .and_then(|nodes| outline::reconcile(manager.data.clone(), nodes))
+        {
+            Ok(data) => break data,
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                eprintln!("Edit again?");
+
+                if !confirm_with_default(true) {
+                    return Ok(ProgramResult {
+                        should_save: false,
+                        exit_status: 1,
+                    });
+                }
+            }
+        }
+    };
+
+    let changes = diff::diff(&manager.data, &new_data);
+
+    // moving items around isn't a change for `diff`, so compare the shape of the trees as well (only looking at the
+    // items that are on both, since added and removed ones are already listed)
+    let ids = |data: &[Item]| {
+        let mut ids = HashSet::new();
+        tree::walk(data, &mut |item: &Item, _| {
+            ids.insert(item.internal_id);
+        });
+        ids
+    };
+    let kept: HashSet<u32> = ids(&manager.data)
+        .intersection(&ids(&new_data))
+        .copied()
+        .collect();
+
+    let shape = |data: &[Item]| {
+        let mut shape = Vec::new();
+        tree::walk(data, &mut |item: &Item, depth| {
+            if kept.contains(&item.internal_id) {
+                shape.push((depth, item.internal_id));
+            }
+        });
+        shape
+    };
+    let moved = shape(&manager.data) != shape(&new_data);
+
+    if changes.is_empty() && !moved {
+        eprintln!("No changes.");
+        recovery_key.discard(Some("txt"));
+
+        return Ok(ProgramResult {
+            should_save: false,
+            exit_status: 0,
+        });
+    }
+
+    eprintln!("Changes:");
+    for change in &changes {
+        eprintln!("  {}", change);
+    }
+    if moved {
+        eprintln!("  (some items were moved)");
+    }
+
+    let removed = changes
+        .iter()
+        .filter(|change| matches!(change, diff::Change::Removed { .. }))
+        .count();
+
+    if removed > 0 {
+        eprintln!("{} item(s) will be deleted.", removed);
+    }
+
+    if !confirm_with_default(true) {
+        return Ok(ProgramResult {
+            should_save: false,
+            exit_status: 1,
+        });
+    }
+
+    *manager = ItemManager::with_validation(new_data, false)
+        .map_err(|_| "the edited items have repeated IDs".to_string())?;
+    recovery_key.discard(Some("txt"));
+
+    Ok(ProgramResult {
+        should_save: true,
+        exit_status: 0,
+    })
+}
+
+/// A function for the `menu` subcommand.
+///
+/// The chosen action is turned into a selection, so it goes through the same code (and confirmations) as `sel`.
+fn subcmd_menu<R: Report>(
+    manager: &mut ItemManager,
+    default_context: Option<&str>,
+    report_cfg: &ReportConfig,
+    editor: &dyn EditorBackend,
+    picker: &dyn Picker,
+    data_file: &DataFile,
+    out: &mut dyn Write,
+) -> Result<ProgramResult, String> {
+    const ACTIONS: [&str; 5] = [
+        "done",
+        "edit description",
+        "edit name",
+        "add child",
+        "delete",
+    ];
+
+    let cancelled = || {
+        Ok(ProgramResult {
+            should_save: false,
+            exit_status: 1,
+        })
+    };
+
+    let lines: Vec<String> = surface_items(manager, &ListOptions::default())
+        .into_iter()
+        .filter(|item| item.state != ItemState::Done)
+        .filter_map(|item| Some(menu_line(item.ref_id?, item)))
+        .collect();
+
+    if lines.is_empty() {
+        return Err("there are no items to choose from".into());
+    }
+
+    let ref_id = match picker.pick("Item:", &lines, 30) {
+        Ok(line) => menu_line_ref_id(&line)
+            .ok_or_else(|| format!("couldn't find a reference ID on {:?}", line.trim()))?,
+        Err(CliError::Silent) => return cancelled(),
+        Err(CliError::Display(e)) => return Err(e.to_string()),
+    };
+
+    let actions: Vec<String> = ACTIONS.iter().map(|&a| a.into()).collect();
+    let action = match picker.pick("Action:", &actions, 10) {
+        Ok(choice) => match choice.trim() {
+            "done" => SelectionAction::Done,
+            "edit description" => SelectionAction::EditDescription,
+            "edit name" => SelectionAction::EditName,
+            "add child" => {
+                let name = utils::io::read_line("Name of the new child (type nothing to cancel): ")
+                    .map_err(|e| format!("failed to read line: {}", e))?;
+
+                if name.is_empty() {
+                    return cancelled();
+                }
+
+                SelectionAction::Add(ItemAddDetails {
+                    name: Some(name),
+                    context: default_context.map(String::from),
+                    note: None,
+                    done: false,
+                    description: None,
+                    desc_template: None,
+                    template: None,
+                    after: None,
+                    under: None,
+                    from_json: None,
+                    from_json_file: None,
+                })
+            }
+            "delete" => SelectionAction::Delete(ForceArgs { force: None }),
+            other => return Err(format!("unknown action {:?}", other)),
+        },
+        Err(CliError::Silent) => return cancelled(),
+        Err(CliError::Display(e)) => return Err(e.to_string()),
+    };
+
+    subcmd_selection::<R>(
+        manager,
+        SelectionDetails {
+            range: ref_id.to_string(),
+            by_flat_index: false,
+            action: Some(action),
+        },
+        report_cfg,
+        editor,
+        data_file,
+        out,
+    )
+}
+
+/// Joins the names of the items on `path` (as returned by [`ItemManager::ancestry`]) into a line like `Project >
+/// Phase 1 > Task`.
+///
+/// [`ItemManager::ancestry`]: ItemManager::ancestry
+fn path_line(path: &[&Item]) -> String {
+    path.iter()
+        .map(|item| item.name.as_str())
+        .collect::<Vec<_>>()
+        .join(" > ")
+}
+
+/// Prints a description as-is, making sure it ends with a newline.
+fn print_description(description: &str) {
+    // Check which char is the last one
+    match description.chars().rev().nth(0).unwrap_or('\n') {
+        '\n' => eprint!("{}", description),
+        _ => eprintln!("{}", description),
+    }
+}
+
 /// A function for the `sel-ref-id` subcommand.
 ///
 /// Type argument `R` is the type of report that should be shown.
@@ -237,22 +1388,45 @@ fn subcmd_selection<R: Report>(
     manager: &mut ItemManager,
     args: SelectionDetails,
     report_cfg: &ReportConfig,
+    editor: &dyn EditorBackend,
+    data_file: &DataFile,
+    out: &mut dyn Write,
 ) -> Result<ProgramResult, String> {
     type SelAct = SelectionAction;
 
-    let range = match utils::misc::parse_range_str(&args.range) {
+    let range = match utils::misc::parse_range_str_in(&args.range, report_cfg.id_base) {
         Ok(vec) => {
             // check if empty
             if vec.is_empty() {
                 return Err("no selection was specified".into());
             }
 
-            // abort if there's an invalid ID
-            if let Some(RefId(missing)) = manager.first_invalid_ref_id(vec.iter()) {
-                return Err(format!(
-                    "there's at least one invalid ID (#{}) on the selection",
-                    missing,
-                ));
+            let vec = if args.by_flat_index {
+                FlatIndex::load(data_file.path)?.resolve(&vec)?
+            } else {
+                vec
+            };
+
+            // abort if there are invalid IDs
+            let invalid = manager::invalid_ref_ids(manager, vec.iter());
+            match invalid.as_slice() {
+                [] => (),
+                [RefId(id)] => {
+                    return Err(format!(
+                        "invalid ID on the selection: #{}",
+                        report_cfg.id_base.format_id(*id)
+                    ))
+                }
+                _ => {
+                    return Err(format!(
+                        "invalid IDs on the selection: {}",
+                        invalid
+                            .iter()
+                            .map(|RefId(id)| format!("#{}", report_cfg.id_base.format_id(*id)))
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    ))
+                }
             }
 
             vec
@@ -283,13 +1457,8 @@ fn subcmd_selection<R: Report>(
             R::report(
                 "Items to be modified",
                 &mut selected.into_iter(),
-                &ReportInfo {
-                    config: report_cfg,
-                    indent: 0,
-                    filter: None,
-                    depth: ReportDepth::Shallow,
-                },
-                &mut io::stdout(),
+                &ReportInfo::new(report_cfg, ReportDepth::Shallow, None),
+                out,
             )
             .unwrap();
 
@@ -322,47 +1491,275 @@ fn subcmd_selection<R: Report>(
             }
         }
         SelAct::Add(sargs) => {
+            if sargs.under.is_some() {
+                return Err("--under can't be used here, since the selection is the parent".into());
+            }
+
+            let description = sargs.initial_description()?;
+            let skeleton = sargs.skeleton(&description)?;
+            let templates = match sargs.template {
+                Some(_) => Templates::load()?,
+                None => Templates::empty(),
+            };
+            let template_items = match sargs.template {
+                Some(ref name) => templates.get(name)?,
+                None => &[],
+            };
+
+            // where to insert the new child on each of the parents
+            let indexes: Vec<Option<usize>> = match sargs.after_id(report_cfg.id_base)? {
+                Some(after) => range
+                    .iter()
+                    .map(|&id| {
+                        let parent = manager.find(RefId(id)).unwrap();
+                        manager::position_after(&parent.children, RefId(after))
+                            .map(Some)
+                            .ok_or_else(|| {
+                                format!(
+                                    "item #{} is not a child of #{}",
+                                    report_cfg.id_base.format_id(after),
+                                    report_cfg.id_base.format_id(id)
+                                )
+                            })
+                    })
+                    .collect::<Result<_, _>>()?,
+                None => vec![None; range.len()],
+            };
+
             let mut proceed = || {
                 eprintln!("Adding items:");
 
-                for &id in &range {
+                for (&id, &index) in range.iter().zip(&indexes) {
+                    if let Some(ref skeleton) = skeleton {
+                        let item = manager.add_skeleton(Some(RefId(id)), skeleton, index)?;
+
+                        match item.ref_id {
+                            Some(ref_id) => eprintln!("* RefID: {}", ref_id),
+                            None => eprintln!("* InternalID: {}", item.internal_id),
+                        }
+
+                        continue;
+                    }
+
                     let RefId(ref_id) = manager
                         .add_child(
                             RefId(id),
-                            &sargs.name,
+                            sargs
+                                .name
+                                .as_deref()
+                                .expect("clap requires a name without --from-json"),
                             sargs.context.as_ref().map_or("", String::as_ref),
                             match sargs.note {
                                 Some(false) | None => ItemState::Todo,
                                 Some(true) => ItemState::Note,
                             },
-                            sargs.description.clone().unwrap_or_else(String::new),
+                            description.clone(),
                             Vec::new(), // children
+                            index,
                         )
                         .unwrap();
 
-                    eprintln!("* RefID: {}", ref_id);
-                }
+                    template::instantiate(manager, RefId(ref_id), template_items).unwrap();
+
+                    eprintln!("* RefID: {}", ref_id);
+                }
+
+                Ok(ProgramResult {
+                    should_save: true,
+                    exit_status: 0,
+                })
+            };
+
+            if range.len() > 1 {
+                eprintln!("More than one item was selected. All of them will receive new identical children copies.");
+
+                if confirm_with_default(false) {
+                    proceed()
+                } else {
+                    Ok(ProgramResult {
+                        should_save: false,
+                        exit_status: 1,
+                    })
+                }
+            } else {
+                proceed()
+            }
+        }
+        SelAct::SetContext(sargs) => {
+            let selected: Vec<&Item> = range
+                .iter()
+                .map(|&id| manager.find(RefId(id)).unwrap())
+                .collect();
+
+            R::report(
+                "Items to have their context changed",
+                &mut selected.into_iter(),
+                &ReportInfo::new(
+                    report_cfg,
+                    if sargs.recursive {
+                        ReportDepth::Tree
+                    } else {
+                        ReportDepth::Shallow
+                    },
+                    None,
+                ),
+                out,
+            )
+            .unwrap();
+
+            eprintln!();
+
+            let context = sargs.context();
+            if Item::context_translates_to_null(context) {
+                eprintln!("The context will be removed.");
+            } else {
+                eprintln!("New context: @{}", context);
+            }
+
+            if sargs.recursive {
+                eprintln!("All descendants of the selected items will also be changed.");
+            }
+
+            if confirm_with_default(true) {
+                for &id in &range {
+                    let item = manager.find_mut(RefId(id)).unwrap();
+                    item.set_context(context);
+
+                    if sargs.recursive {
+                        tree::walk_mut(&mut item.children, &mut |child, _| {
+                            child.set_context(context)
+                        });
+                    }
+                }
+
+                Ok(ProgramResult {
+                    should_save: true,
+                    exit_status: 0,
+                })
+            } else {
+                Ok(ProgramResult {
+                    should_save: false,
+                    exit_status: 1,
+                })
+            }
+        }
+        SelAct::ExportSubtree(sargs) => {
+            let selected: Vec<&Item> = range
+                .iter()
+                .map(|&id| manager.find(RefId(id)).unwrap())
+                .collect();
+
+            // children of other selected items are already exported along with them
+            let roots: Vec<&Item> = selected
+                .iter()
+                .filter(|&&item| !selected.iter().any(|other| other.has_child(item)))
+                .cloned()
+                .collect();
+
+            R::report(
+                if sargs.move_items {
+                    "Items to be moved"
+                } else {
+                    "Items to be exported"
+                },
+                &mut roots.iter().cloned(),
+                &ReportInfo::new(report_cfg, ReportDepth::Tree, None),
+                out,
+            )
+            .unwrap();
+
+            let path = Path::new(&sargs.path);
+            let overwriting = path.exists() && !sargs.append;
+
+            if sargs.append && path.is_file() {
+                let contents = utils::io::touch_read(path)
+                    .map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+
+                if !contents.trim().is_empty()
+                    && StorageFormat::detect(&contents) != Some(StorageFormat::Lines)
+                {
+                    return Err(format!(
+                        "{} isn't on the lines format, so it can't be appended to",
+                        path.display()
+                    ));
+                }
+            }
+
+            if data_file.no_save {
+                eprintln!("The items were not exported (--no-save).");
+                return Ok(ProgramResult {
+                    should_save: false,
+                    exit_status: 0,
+                });
+            }
+
+            if overwriting {
+                eprintln!("{} already exists and will be overwritten.", path.display());
+            }
+
+            if sargs.move_items {
+                eprintln!("The items will be removed from this file.");
+            }
+
+            let confirmed = match (overwriting, sargs.move_items) {
+                (true, _) => confirm_with_default(false),
+                (false, true) => confirm_with_default(true),
+                (false, false) => true,
+            };
+
+            if !confirmed {
+                return Ok(ProgramResult {
+                    should_save: false,
+                    exit_status: 1,
+                });
+            }
+
+            if sargs.append {
+                let lines = roots
+                    .iter()
+                    .map(serde_json::to_string)
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|e| format!("failed to export items: {}", e))?;
+                utils::io::append_lines_atomic(path, &lines)
+                    .map_err(|e| format!("failed to append to {}: {}", path.display(), e))?;
 
-                Ok(ProgramResult {
-                    should_save: true,
-                    exit_status: 0,
-                })
-            };
+                eprintln!("Appended {} item(s) to {}.", roots.len(), path.display());
+            } else {
+                let roots: Vec<Item> = roots.iter().map(|&item| item.clone()).collect();
+                let contents = data_file
+                    .format
+                    .export(&roots)
+                    .map_err(|e| format!("failed to export items: {}", e))?;
+                let contents = data_serialize::fix_trailing_newline(contents, true);
+                utils::io::write_maybe_gzip(path, contents.as_bytes())
+                    .map_err(|e| format!("failed to write {}: {}", path.display(), e))?;
+
+                eprintln!("Wrote {} item(s) to {}.", roots.len(), path.display());
+            }
 
-            if range.len() > 1 {
-                eprintln!("More than one item was selected. All of them will receive new identical children copies.");
+            if sargs.move_items {
+                let internal_ids: Vec<u32> = roots.iter().map(|item| item.internal_id).collect();
 
-                if confirm_with_default(false) {
-                    proceed()
-                } else {
-                    Ok(ProgramResult {
-                        should_save: false,
-                        exit_status: 1,
-                    })
+                for id in internal_ids {
+                    manager.remove_where(|item| item.internal_id == id);
                 }
-            } else {
-                proceed()
             }
+
+            Ok(ProgramResult {
+                should_save: sargs.move_items,
+                exit_status: 0,
+            })
+        }
+        SelAct::ShowPath => {
+            for &id in &range {
+                let path = manager.ancestry(RefId(id)).unwrap();
+                writeln!(out, "{}", path_line(&path)).unwrap();
+            }
+
+            Ok(ProgramResult {
+                should_save: false,
+                exit_status: 0,
+            })
         }
         SelAct::PrintDescription => {
             if range.len() != 1 {
@@ -371,11 +1768,7 @@ fn subcmd_selection<R: Report>(
 
             manager
                 .interact(RefId(range[0]), |i| {
-                    // Check which char is the last one
-                    match i.description.chars().rev().nth(0).unwrap_or('\n') {
-                        '\n' => eprint!("{}", i.description),
-                        _ => eprintln!("{}", i.description),
-                    }
+                    print_description(&i.description);
 
                     Ok(ProgramResult {
                         should_save: false,
@@ -384,6 +1777,50 @@ fn subcmd_selection<R: Report>(
                 })
                 .unwrap()
         }
+        SelAct::ViewDescription => {
+            if range.len() != 1 {
+                return Err("The selection should have exactly one item.".into());
+            }
+
+            let viewer = std::env::var("MARKDOWN_VIEWER")
+                .ok()
+                .filter(|var| !var.trim().is_empty());
+
+            manager
+                .interact(RefId(range[0]), |i| {
+                    let code = match viewer {
+                        Some(ref viewer) => {
+                            match utils::proc::run(viewer, &[], Some(&i.description)) {
+                                Ok(code) => Some(code),
+                                Err(e) => {
+                                    utils::warn!("{}, printing instead", e);
+                                    None
+                                }
+                            }
+                        }
+                        None => None,
+                    };
+
+                    match code {
+                        Some(code) if code != 0 => {
+                            Err(format!("the markdown viewer failed (exit code {})", code))
+                        }
+                        Some(_) => Ok(ProgramResult {
+                            should_save: false,
+                            exit_status: 0,
+                        }),
+                        None => {
+                            print_description(&i.description);
+
+                            Ok(ProgramResult {
+                                should_save: false,
+                                exit_status: 0,
+                            })
+                        }
+                    }
+                })
+                .unwrap()
+        }
         SelAct::EditName => {
             let name_lines: Vec<(u32, String)> = range
                 .iter()
@@ -399,11 +1836,22 @@ fn subcmd_selection<R: Report>(
 
             let names_string = name_lines.iter().map(|(_, s)| s.as_str()).intersperse("\n").collect::<String>();
 
-            let edited_string = match tmp::edit_text(&names_string, Some("txt")) {
-                Ok((new, 0)) => new,
-                Ok((_, code)) => return Err(format!("non-zero exit code: {}", code)),
-                Err(e) => return Err(format!("failed to edit text: {}", e)),
-            };
+            // a hash, since the IDs of a big selection wouldn't fit on a file name
+            let recovery_key = RecoveryKey::hashed(
+                "itmn-names",
+                &range
+                    .iter()
+                    .map(|&id| format!("i{}", manager.find(RefId(id)).unwrap().internal_id))
+                    .collect::<Vec<_>>()
+                    .join("-"),
+            );
+
+            let edited_string =
+                match editor.edit_recoverable(&names_string, Some("txt"), &recovery_key) {
+                    Ok((new, 0)) => new,
+                    Ok((_, code)) => return Err(format!("non-zero exit code: {}", code)),
+                    Err(e) => return Err(format!("failed to edit text: {}", e)),
+                };
 
             let edited_lines = edited_string
                 .split('\n')
@@ -422,6 +1870,8 @@ fn subcmd_selection<R: Report>(
                     .unwrap();
             }
 
+            recovery_key.discard(Some("txt"));
+
             Ok(ProgramResult {
                 should_save: true,
                 exit_status: 0,
@@ -432,11 +1882,20 @@ fn subcmd_selection<R: Report>(
                 return Err("The selection should have exactly one item.".into());
             }
 
+            let extension = std::env::var("ITMN_DESC_EXT")
+                .ok()
+                .filter(|ext| !ext.is_empty())
+                .unwrap_or_else(|| "md".into());
+
             manager
                 .interact_mut(RefId(range[0]), |i| {
-                    match tmp::edit_text(&i.description, Some("txt")) {
+                    let recovery_key = RecoveryKey(format!("itmn-description-i{}", i.internal_id));
+
+                    match editor.edit_recoverable(&i.description, Some(&extension), &recovery_key) {
                         Ok((new_description, 0)) => {
                             i.description = new_description;
+                            i.touch();
+                            recovery_key.discard(Some(&extension));
 
                             Ok(ProgramResult {
                                 should_save: true,
@@ -458,13 +1917,8 @@ fn subcmd_selection<R: Report>(
             R::report(
                 "Items to be marked as done",
                 &mut selection.into_iter(),
-                &ReportInfo {
-                    config: report_cfg,
-                    indent: 0,
-                    filter: None,
-                    depth: ReportDepth::Tree,
-                },
-                &mut io::stdout(),
+                &ReportInfo::new(report_cfg, ReportDepth::Tree, None),
+                out,
             )
             .unwrap();
 
@@ -499,13 +1953,8 @@ fn subcmd_selection<R: Report>(
             R::report(
                 "Tree listing",
                 &mut selected.into_iter(),
-                &ReportInfo {
-                    config: report_cfg,
-                    indent: 0,
-                    filter: None,
-                    depth: ReportDepth::Tree,
-                },
-                &mut io::stdout(),
+                &ReportInfo::new(report_cfg, ReportDepth::Tree, None),
+                out,
             )
             .expect("Failed to show report");
 
@@ -523,13 +1972,8 @@ fn subcmd_selection<R: Report>(
             R::report(
                 "Brief listing",
                 &mut selected.into_iter(),
-                &ReportInfo {
-                    config: report_cfg,
-                    indent: 0,
-                    filter: None,
-                    depth: ReportDepth::Brief,
-                },
-                &mut io::stdout(),
+                &ReportInfo::new(report_cfg, ReportDepth::Brief, None),
+                out,
             )
             .expect("Failed to show report");
 
@@ -547,13 +1991,8 @@ fn subcmd_selection<R: Report>(
             R::report(
                 "Shallow listing",
                 &mut selected.into_iter(),
-                &ReportInfo {
-                    config: report_cfg,
-                    indent: 0,
-                    filter: None,
-                    depth: ReportDepth::Shallow,
-                },
-                &mut io::stdout(),
+                &ReportInfo::new(report_cfg, ReportDepth::Shallow, None),
+                out,
             )
             .expect("Failed to show report");
 
@@ -607,13 +2046,8 @@ fn subcmd_selection<R: Report>(
                 R::report(
                     "Items to be deleted",
                     &mut selection.into_iter(),
-                    &ReportInfo {
-                        config: report_cfg,
-                        indent: 0,
-                        filter: None,
-                        depth: ReportDepth::Tree,
-                    },
-                    &mut io::stdout(),
+                    &ReportInfo::new(report_cfg, ReportDepth::Tree, None),
+                    out,
                 )
                 .unwrap();
 
@@ -629,6 +2063,117 @@ fn subcmd_selection<R: Report>(
                 proceed(manager)
             }
         }
+        SelAct::PromoteChildren(sargs) => {
+            let selection: Vec<&Item> = range
+                .iter()
+                .map(|&id| manager.find(RefId(id)).unwrap())
+                .filter(|item| !item.children.is_empty())
+                .collect();
+
+            if selection.is_empty() {
+                eprintln!("None of the selected items have children; nothing to do.");
+
+                return Ok(ProgramResult {
+                    should_save: false,
+                    exit_status: 0,
+                });
+            }
+
+            if !sargs.force.unwrap_or(false) {
+                R::report(
+                    "Items whose children will be promoted",
+                    &mut selection.into_iter(),
+                    &ReportInfo::new(report_cfg, ReportDepth::Tree, None),
+                    out,
+                )
+                .unwrap();
+
+                if !confirm_with_default(true) {
+                    return Ok(ProgramResult {
+                        should_save: false,
+                        exit_status: 1,
+                    });
+                }
+            }
+
+            for &id in &range {
+                let amount = manager.promote_children(RefId(id)).unwrap(); // safe unwrap due to range check
+
+                if amount > 0 {
+                    eprintln!("Promoted {} children of #{}", amount, id);
+                }
+            }
+
+            Ok(ProgramResult {
+                should_save: true,
+                exit_status: 0,
+            })
+        }
+        act @ (SelAct::Indent | SelAct::Outdent) => {
+            if range.len() != 1 {
+                return Err(format!(
+                    "only one item can be moved at a time (got {})",
+                    range.len()
+                ));
+            }
+
+            let id = RefId(range[0]);
+            if let SelAct::Indent = act {
+                manager.indent(id)?;
+            } else {
+                manager.outdent(id)?;
+            }
+
+            Ok(ProgramResult {
+                should_save: true,
+                exit_status: 0,
+            })
+        }
+        SelAct::SetRefId(sargs) => {
+            if range.len() != 1 {
+                return Err(format!(
+                    "the amount of arguments should be exactly one (instead of {})",
+                    range.len()
+                ));
+            }
+
+            let new_id = report_cfg
+                .id_base
+                .parse_id(sargs.new_id.trim_start_matches('#'))?;
+            let name = manager.find(RefId(range[0])).unwrap().name.clone();
+            let holder_name = manager
+                .find(RefId(new_id))
+                .map(|holder| holder.name.clone());
+
+            // the change is only kept if it's saved, so it's made before asking and fails before asking if needed
+            match manager.set_ref_id(RefId(range[0]), new_id, sargs.swap)? {
+                Some(_) => eprintln!(
+                    "{:?} will be #{} and {:?} will be #{}.",
+                    name,
+                    report_cfg.id_base.format_id(new_id),
+                    holder_name.unwrap_or_default(),
+                    report_cfg.id_base.format_id(range[0])
+                ),
+                None => eprintln!(
+                    "{:?} will be #{}.",
+                    name,
+                    report_cfg.id_base.format_id(new_id)
+                ),
+            }
+
+            let force: bool = sargs.force.into();
+            if !force && !confirm_with_default(true) {
+                return Ok(ProgramResult {
+                    should_save: false,
+                    exit_status: 1,
+                });
+            }
+
+            Ok(ProgramResult {
+                should_save: true,
+                exit_status: 0,
+            })
+        }
         SelAct::Swap(sargs) => {
             if range.len() != 2 {
                 return Err(format!(
@@ -655,13 +2200,8 @@ fn subcmd_selection<R: Report>(
                 R::report(
                     "Items to be swapped",
                     &mut selection.into_iter(),
-                    &ReportInfo {
-                        config: report_cfg,
-                        indent: 0,
-                        filter: None,
-                        depth: ReportDepth::Brief,
-                    },
-                    &mut io::stdout(),
+                    &ReportInfo::new(report_cfg, ReportDepth::Brief, None),
+                    out,
                 )
                 .unwrap();
 
@@ -686,21 +2226,17 @@ fn subcmd_selection<R: Report>(
             }
 
             impl NewOwner {
-                pub fn parse(arg: &str) -> Result<Self, String> {
+                pub fn parse(arg: &str, base: IdBase) -> Result<Self, String> {
                     if arg == ".ROOT" {
                         // Parse ROOT
                         Ok(Self::Root)
-                    } else if let Some('i') = arg.chars().nth(0) {
+                    } else if let Some(id) = arg.strip_prefix('i') {
                         // Parse Internal ID
-                        if let Ok(num) = (&arg[1..]).parse::<u32>() {
-                            Ok(Self::ByInternal(InternalId(num)))
-                        } else {
-                            Err(format!(
-                                "invalid number after 'i' character: {:?}",
-                                &arg[1..]
-                            ))
+                        match base.parse_id(id) {
+                            Ok(num) => Ok(Self::ByInternal(InternalId(num))),
+                            Err(e) => Err(format!("invalid number after 'i' character: {}", e)),
                         }
-                    } else if let Ok(num) = arg.parse::<u32>() {
+                    } else if let Ok(num) = base.parse_id(arg) {
                         Ok(Self::ByRef(RefId(num)))
                     } else {
                         Err(format!("invalid expression: {:?}", arg))
@@ -716,17 +2252,12 @@ fn subcmd_selection<R: Report>(
             R::report(
                 "Items to be moved",
                 &mut items.into_iter(),
-                &ReportInfo {
-                    config: report_cfg,
-                    indent: 0,
-                    filter: None,
-                    depth: ReportDepth::Shallow,
-                },
-                &mut io::stdout(),
+                &ReportInfo::new(report_cfg, ReportDepth::Shallow, None),
+                out,
             )
             .unwrap();
 
-            let new_owner = match NewOwner::parse(&sargs.new_owner) {
+            let new_owner = match NewOwner::parse(&sargs.new_owner, report_cfg.id_base) {
                 Ok(new) => new,
                 Err(e) => return Err(format!("failed to parse new-owner argument: {}", e)),
             };
@@ -840,12 +2371,599 @@ A is a child of B, but both A and B are on the selection."#,
     }
 }
 
-fn validate_parsed_string(string: &str) -> &str {
-    for ch in string.chars() {
-        if !matches!(ch, '\n' | ' ' | '\t' | '\r') {
-            return string;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use utils::data::data_serialize;
+    use utils::misc::ScriptedPicker;
+    use utils::tmp::FnEditor;
+
+    fn sample_manager() -> ItemManager {
+        ItemManager::new(vec![
+            Item::new(
+                Some(0),
+                0,
+                "first",
+                "",
+                ItemState::Todo,
+                String::new(),
+                Vec::new(),
+            ),
+            Item::new(
+                Some(1),
+                1,
+                "second",
+                "",
+                ItemState::Todo,
+                "old description".into(),
+                Vec::new(),
+            ),
+        ])
+        .ok()
+        .unwrap()
+    }
+
+    fn select(
+        manager: &mut ItemManager,
+        range: &str,
+        action: SelectionAction,
+        editor: &dyn EditorBackend,
+    ) -> Result<ProgramResult, String> {
+        let data_file = DataFile {
+            path: Path::new("test.json"),
+            format: StorageFormat::Pretty,
+            no_save: false,
+        };
+
+        select_with(manager, range, action, editor, &data_file)
+    }
+
+    fn select_with(
+        manager: &mut ItemManager,
+        range: &str,
+        action: SelectionAction,
+        editor: &dyn EditorBackend,
+        data_file: &DataFile,
+    ) -> Result<ProgramResult, String> {
+        let report_cfg = ReportConfig::default();
+
+        subcmd_selection::<report::BasicReport>(
+            manager,
+            SelectionDetails {
+                range: range.into(),
+                by_flat_index: false,
+                action: Some(action),
+            },
+            &report_cfg,
+            editor,
+            data_file,
+            &mut io::sink(),
+        )
+    }
+
+    #[test]
+    fn internal_ranges() {
+        let parse = |range| parse_internal_range(range, IdBase::Decimal);
+
+        assert_eq!(parse("i4..i6, 9,i10"), Ok(vec![4, 5, 6, 9, 10]));
+        assert_eq!(parse("2..i3"), Ok(vec![2, 3]));
+        assert!(parse("1i2").is_err());
+        assert!(parse("ii3").is_err());
+        assert_eq!(parse_internal_range("i0x1f", IdBase::Hex), Ok(vec![31]));
+    }
+
+    #[test]
+    fn watch_renders_load_errors() {
+        let path = tmp::make_tmp(Some("json"));
+        let report_cfg = ReportConfig {
+            plain: true,
+            ..Default::default()
+        };
+        let render = || {
+            render_watched(
+                &path,
+                None,
+                &WatchedReport::Next(ListOptions::default()),
+                &report_cfg,
+            )
+        };
+
+        let missing = render();
+        let created = path.exists();
+
+        std::fs::write(
+            &path,
+            serde_json::to_string(&sample_manager().data).unwrap(),
+        )
+        .unwrap();
+        let valid = render();
+
+        std::fs::write(&path, "[{").unwrap();
+        let broken = render();
+        let _ = std::fs::remove_file(&path);
+
+        assert!(missing.contains("doesn't exist"));
+        assert!(!created);
+        assert!(valid.contains("first") && valid.contains("second"));
+        assert!(broken.starts_with("Failed to load file: invalid data"));
+    }
+
+    #[test]
+    fn report_output_is_only_created_for_a_report() {
+        let path = tmp::make_tmp(Some("txt"));
+
+        let failed = with_report_output(Some(&path), |_| Err::<(), _>("nope")).unwrap();
+        let created_on_failure = path.exists();
+
+        with_report_output(Some(&path), |out| writeln!(out, "report"))
+            .unwrap()
+            .unwrap();
+        let written = std::fs::read_to_string(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!(failed, Err("nope"));
+        assert!(!created_on_failure);
+        assert_eq!(written, "report\n");
+    }
+
+    #[test]
+    fn done_by_context() {
+        let mut manager = sample_manager();
+        manager.find_mut(RefId(1)).unwrap().set_context("errands");
+
+        let args = |context: &str| DoneContextArgs {
+            context: context.into(),
+            recursive: false,
+            yes: true,
+        };
+        let report_cfg = ReportConfig {
+            plain: true,
+            ..Default::default()
+        };
+
+        let mut out = Vec::new();
+        let result = subcmd_done_context::<report::BasicReport>(
+            &mut manager,
+            args("@errands"),
+            &report_cfg,
+            &mut out,
+        );
+
+        assert!(result.unwrap().should_save);
+        assert!(String::from_utf8(out)
+            .unwrap()
+            .ends_with("Marked 1 item(s) as done.\n"));
+        assert!(manager.find(RefId(1)).is_none());
+        assert_eq!(manager.find(RefId(0)).unwrap().state, ItemState::Todo);
+
+        let again = subcmd_done_context::<report::BasicReport>(
+            &mut manager,
+            args("errands"),
+            &report_cfg,
+            &mut io::sink(),
+        );
+        assert!(!again.unwrap().should_save);
+    }
+
+    #[test]
+    fn completed_lists_done_items_with_internal_ids() {
+        let mut first = Item::new(
+            Some(0),
+            0,
+            "first",
+            "",
+            ItemState::Todo,
+            String::new(),
+            Vec::new(),
+        );
+        first.children.push(Item::new(
+            None,
+            2,
+            "nested",
+            "",
+            ItemState::Done,
+            String::new(),
+            Vec::new(),
+        ));
+        let mut second = Item::new(
+            None,
+            1,
+            "second",
+            "",
+            ItemState::Done,
+            String::new(),
+            Vec::new(),
+        );
+        // items without a modification date are left out when filtering by date
+        second.children.push(Item::new(
+            None,
+            3,
+            "undated",
+            "",
+            ItemState::Done,
+            String::new(),
+            Vec::new(),
+        ));
+        second.children[0].modified = None;
+        first.children[0].modified = Some(1_000_000);
+        let manager = ItemManager::new(vec![first, second]).ok().unwrap();
+        let report_cfg = ReportConfig {
+            plain: true,
+            ..Default::default()
+        };
+
+        let completed = |since: Option<&str>| {
+            let mut out = Vec::new();
+            subcmd_completed::<report::BasicReport>(
+                &manager,
+                CompletedOptions {
+                    since: since.map(String::from),
+                },
+                &report_cfg,
+                &mut out,
+            )
+            .unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        assert_eq!(
+            completed(None),
+            "x nested i02\nx second i01\nx undated i03\n"
+        );
+        assert_eq!(completed(Some("today")), "x second i01\n");
+    }
+
+    #[test]
+    fn limit_counts_only_shown_items() {
+        let mut manager = sample_manager();
+        manager.find_mut(RefId(0)).unwrap().state = ItemState::Done;
+        let items = surface_items(&manager, &ListOptions::default());
+        let filter = |i: &Item| i.state != ItemState::Done;
+
+        let (shown, remainder) = limit_items(items.clone(), Some(0), &filter);
+        assert!(shown.is_empty());
+        assert_eq!(remainder, 1);
+
+        let (shown, remainder) = limit_items(items, None, &filter);
+        assert_eq!(shown.len(), 1);
+        assert_eq!(remainder, 0);
+    }
+
+    #[test]
+    fn modify_state_drops_ref_id_when_done() {
+        let mut manager = sample_manager();
+        let mods = ItemBatchMod {
+            name: None,
+            context: None,
+            note: None,
+            state: Some(ItemState::Done),
+            tag: Vec::new(),
+            untag: Vec::new(),
+            note_text: None,
+        };
+
+        manager.interact_mut(RefId(1), |item| mods.mod_item_by_ref(item));
+
+        let item = manager.find(InternalId(1)).unwrap();
+        assert_eq!(item.state, ItemState::Done);
+        assert_eq!(item.ref_id, None);
+        assert_eq!(
+            mods.modifications_description(),
+            vec!["Mark as DONE (removing the reference ID)"]
+        );
+        assert!(Item::parse_state("Note").is_ok());
+        assert!(Item::parse_state("waiting").is_err());
+    }
+
+    #[test]
+    fn open_and_notes_filters() {
+        let mut manager = sample_manager();
+        manager.find_mut(RefId(1)).unwrap().state = ItemState::Note;
+        let first = manager.find(RefId(0)).unwrap();
+        let second = manager.find(RefId(1)).unwrap();
+
+        let open_only = ListOptions {
+            open_only: true,
+            ..ListOptions::default()
+        };
+        let notes_only = ListOptions {
+            notes_only: true,
+            ..ListOptions::default()
+        };
+
+        assert!(ListOptions::default().shows(first) && ListOptions::default().shows(second));
+        assert!(open_only.shows(first) && !open_only.shows(second));
+        assert!(!notes_only.shows(first) && notes_only.shows(second));
+    }
+
+    #[test]
+    fn include_and_exclude_contexts() {
+        let item = |id, context| {
+            Item::new(
+                Some(id),
+                id,
+                "item",
+                context,
+                ItemState::Todo,
+                String::new(),
+                Vec::new(),
+            )
+        };
+        let mut project = item(0, "work");
+        project.children = vec![item(1, "someday"), item(2, "")];
+        let errand = item(3, "errands");
+        let loose = item(4, "");
+
+        let args = |contexts: &[&str], excluded: &[&str]| ListOptions {
+            contexts: contexts.iter().map(|&c| c.into()).collect(),
+            excluded_contexts: excluded.iter().map(|&c| c.into()).collect(),
+            ..ListOptions::default()
+        };
+        // like the report, the children of a hidden item are hidden too
+        fn collect(items: &[Item], args: &ListOptions, acc: &mut Vec<u32>) {
+            for item in items.iter().filter(|i| args.shows(i)) {
+                acc.push(item.internal_id);
+                collect(&item.children, args, acc);
+            }
+        }
+        let items = [project, errand, loose];
+        let shown = |args: &ListOptions| {
+            let mut acc = Vec::new();
+            collect(&items, args, &mut acc);
+            acc
+        };
+
+        assert_eq!(shown(&args(&[], &["@someday"])), [0, 2, 3, 4]);
+        assert_eq!(shown(&args(&["work", "errands"], &[])), [0, 3]);
+        // the project leads to the item without context, so it's still shown
+        assert_eq!(shown(&args(&[".none"], &[])), [0, 2, 4]);
+        assert_eq!(shown(&args(&[".none"], &["work"])), [4]);
+        assert_eq!(shown(&args(&["work", "someday"], &["someday"])), [0]);
+        assert_eq!(shown(&args(&[], &[""])), [0, 1, 3]);
+    }
+
+    #[test]
+    fn modify_tags() {
+        let mut manager = sample_manager();
+        let mods = ItemBatchMod {
+            name: None,
+            context: None,
+            note: None,
+            state: None,
+            tag: vec!["#urgent".into(), "two words".into(), "urgent".into()],
+            untag: Vec::new(),
+            note_text: None,
+        };
+
+        manager.interact_mut(RefId(0), |item| mods.mod_item_by_ref(item));
+
+        let item = manager.find(RefId(0)).unwrap();
+        assert_eq!(item.tags(), ["urgent", "twowords"]);
+        assert!(item.has_tag("#urgent"));
+        assert!(item.validate().is_ok());
+
+        let args = ListOptions {
+            tag: Some("urgent".into()),
+            ..ListOptions::default()
+        };
+        assert!(args.shows(item));
+        assert!(!args.shows(manager.find(RefId(1)).unwrap()));
+
+        let item = manager.find_mut(RefId(0)).unwrap();
+        assert!(item.remove_tag("urgent"));
+        assert!(!item.remove_tag("urgent"));
+    }
+
+    #[test]
+    fn modify_annotation() {
+        let mut manager = sample_manager();
+        let annotate = |manager: &mut ItemManager, text: &str| {
+            let mods = ItemBatchMod {
+                name: None,
+                context: None,
+                note: None,
+                state: None,
+                tag: Vec::new(),
+                untag: Vec::new(),
+                note_text: Some(text.into()),
+            };
+
+            manager.interact_mut(RefId(0), |item| mods.mod_item_by_ref(item));
+            mods.modifications_description()
+        };
+
+        assert_eq!(
+            annotate(&mut manager, " waiting on\nBob "),
+            vec!["Change annotation to \" waiting on\\nBob \""]
+        );
+        let item = manager.find(RefId(0)).unwrap();
+        assert_eq!(item.annotation(), Some("waiting onBob"));
+        assert!(item.validate().is_ok());
+
+        assert_eq!(annotate(&mut manager, "  "), vec!["Remove annotation"]);
+        assert_eq!(manager.find(RefId(0)).unwrap().annotation(), None);
+    }
+
+    #[test]
+    fn menu_lines_map_back_to_ref_ids() {
+        let manager = sample_manager();
+        let line = menu_line(1, manager.find(RefId(1)).unwrap());
+
+        assert_eq!(line, "#01 second");
+        assert_eq!(menu_line_ref_id(&format!("{}\n", line)), Some(1));
+        assert_eq!(menu_line_ref_id("#12 #3 tricky name @ctx"), Some(12));
+        assert_eq!(menu_line_ref_id("no id here"), None);
+    }
+
+    #[test]
+    fn export_subtree_keeps_items() {
+        let mut manager = sample_manager();
+        let path = utils::tmp::make_tmp(Some("json"));
+        let action = SelectionAction::ExportSubtree(ExportSubtreeArgs {
+            path: path.to_string_lossy().into_owned(),
+            move_items: false,
+            append: false,
+        });
+
+        let result = select(&mut manager, "1", action, &FnEditor(|t: &str| t.into()));
+        let exported: Result<Vec<Item>, String> = data_serialize::import_from_file(&path);
+        let _ = std::fs::remove_file(&path);
+
+        assert!(!result.unwrap().should_save);
+        assert_eq!(
+            exported.map(|items| items.into_iter().map(|i| i.name).collect::<Vec<_>>()),
+            Ok(vec!["second".to_string()])
+        );
+        assert!(manager.find(RefId(1)).is_some());
+    }
+
+    #[test]
+    fn export_subtree_follows_the_data_file() {
+        let mut manager = sample_manager();
+        let path = utils::tmp::make_tmp(Some("json"));
+        let action = |append| {
+            SelectionAction::ExportSubtree(ExportSubtreeArgs {
+                path: path.to_string_lossy().into_owned(),
+                move_items: false,
+                append,
+            })
+        };
+        let editor = FnEditor(|t: &str| t.into());
+        let mut data_file = DataFile {
+            path: Path::new("test.json"),
+            format: StorageFormat::Lines,
+            no_save: true,
+        };
+
+        // nothing is written with --no-save, even when appending
+        for append in [false, true] {
+            let result = select_with(&mut manager, "1", action(append), &editor, &data_file);
+            assert!(!result.unwrap().should_save);
+            assert!(!path.exists());
+        }
+
+        // otherwise, it's written in the same format as the data file
+        data_file.no_save = false;
+        let result = select_with(&mut manager, "1", action(false), &editor, &data_file);
+        let exported = std::fs::read_to_string(&path);
+        let _ = std::fs::remove_file(&path);
+
+        assert!(!result.unwrap().should_save);
+        assert_eq!(
+            StorageFormat::detect(&exported.unwrap()),
+            Some(StorageFormat::Lines)
+        );
+    }
+
+    #[test]
+    fn edit_name_with_fake_editor() {
+        let mut manager = sample_manager();
+        let editor = FnEditor(|text: &str| text.to_uppercase());
+
+        let result = select(&mut manager, "0,1", SelectionAction::EditName, &editor).unwrap();
+
+        assert!(result.should_save);
+        assert_eq!(manager.find(RefId(0)).unwrap().name, "FIRST");
+        assert_eq!(manager.find(RefId(1)).unwrap().name, "SECOND");
+    }
+
+    #[test]
+    fn edit_name_rejects_line_count_mismatch() {
+        let mut manager = sample_manager();
+        let editor = FnEditor(|_: &str| "only one line".into());
+
+        assert!(select(&mut manager, "0,1", SelectionAction::EditName, &editor).is_err());
+        assert_eq!(manager.find(RefId(0)).unwrap().name, "first");
+    }
+
+    #[test]
+    fn add_after_a_hex_id() {
+        let mut manager = sample_manager();
+        let report_cfg = ReportConfig {
+            id_base: IdBase::Hex,
+            ..Default::default()
+        };
+        let details = ItemAddDetails::try_parse_from(["add", "between", "--after", "0x0"]).unwrap();
+
+        assert!(
+            subcmd_add(&mut manager, details, &report_cfg)
+                .unwrap()
+                .should_save
+        );
+        assert_eq!(manager.data[1].name, "between");
+
+        let details = ItemAddDetails::try_parse_from(["add", "x", "--under", "0xz"]).unwrap();
+        assert!(subcmd_add(&mut manager, details, &report_cfg).is_err());
+    }
+
+    #[test]
+    fn add_done_item() {
+        let mut manager = sample_manager();
+        let details =
+            ItemAddDetails::try_parse_from(["add", "logged", "--done", "-c", "home"]).unwrap();
+
+        assert!(
+            subcmd_add(&mut manager, details, &ReportConfig::default())
+                .unwrap()
+                .should_save
+        );
+
+        let added = manager.data.last().unwrap();
+        assert_eq!(
+            (added.name.as_str(), added.state, added.ref_id),
+            ("logged", ItemState::Done, None)
+        );
+        assert_eq!(added.context(), Some("home"));
+        assert!(ItemAddDetails::try_parse_from(["add", "x", "--done", "--note", "true"]).is_err());
+    }
+
+    #[test]
+    fn menu_with_scripted_picker() {
+        let mut manager = sample_manager();
+        let editor = FnEditor(|text: &str| format!("{} (edited)", text));
+        let report_cfg = ReportConfig::default();
+        let menu = |picker: &ScriptedPicker, manager: &mut ItemManager| {
+            subcmd_menu::<report::BasicReport>(
+                manager,
+                None,
+                &report_cfg,
+                &editor,
+                picker,
+                &DataFile {
+                    path: Path::new("test.json"),
+                    format: StorageFormat::Pretty,
+                    no_save: false,
+                },
+                &mut io::sink(),
+            )
+        };
+
+        let picker = ScriptedPicker::new(vec![Some("second"), Some("edit name")]);
+        let result = menu(&picker, &mut manager).unwrap();
+        assert!(result.should_save);
+        assert_eq!(manager.find(RefId(1)).unwrap().name, "second (edited)");
+        assert_eq!(picker.prompts(), vec!["Item:", "Action:"]);
+
+        // cancelling on either menu does nothing
+        for answers in [vec![None], vec![Some("first"), None]] {
+            let picker = ScriptedPicker::new(answers);
+            assert_eq!(menu(&picker, &mut manager).unwrap().exit_status, 1);
         }
+        assert_eq!(manager.find(RefId(0)).unwrap().name, "first");
     }
 
-    "[]"
+    #[test]
+    fn edit_description_with_fake_editor() {
+        let mut manager = sample_manager();
+        let editor = FnEditor(|text: &str| format!("{}\nnew line\n", text));
+
+        let result = select(&mut manager, "1", SelectionAction::EditDescription, &editor).unwrap();
+
+        assert!(result.should_save);
+        assert_eq!(
+            manager.find(RefId(1)).unwrap().description,
+            "old description\nnew line\n"
+        );
+    }
 }
@@ -3,9 +3,10 @@
 use std::collections::HashSet;
 use std::path::Path;
 
-use crate::item::{InternalId, Item, ItemState, RefId};
+use crate::item::{InternalId, Item, ItemSkeleton, ItemState, RefId};
 
-use utils::data::data_serialize;
+use utils::data::TreeManager;
+use utils::format::StorageFormat;
 
 /// The utils structure of the database.
 pub struct ItemManager {
@@ -24,6 +25,131 @@ pub enum ManagerError {
     RepeatedRefID(RefId),
     /// At least two of the items have a repeated internal ID.
     RepeatedInternalID(InternalId),
+    /// Some items didn't pass [`Item::validate`] on strict mode. Each string describes one of them.
+    ///
+    /// [`Item::validate`]: Item::validate
+    InvalidItems(Vec<String>),
+}
+
+/// Checks if strict mode is enabled (`ITMN_STRICT=1`), in which case the items are validated when loaded.
+pub fn strict_mode() -> bool {
+    std::env::var("ITMN_STRICT").is_ok_and(|var| var == "1")
+}
+
+/// Runs [`Item::validate`] over the whole tree, returning a description (with the item name and ID) for each invalid
+/// item.
+///
+/// [`Item::validate`]: Item::validate
+pub fn validate_all(data: &[Item]) -> Vec<String> {
+    let mut violations = Vec::new();
+
+    utils::data::tree::walk(data, &mut |item: &Item, _| {
+        if let Err(why) = item.validate() {
+            let id = match item.ref_id {
+                Some(id) => format!("#{}", id),
+                None => format!("i{}", item.internal_id),
+            };
+
+            violations.push(format!("{:?} ({}): {}", item.name, id, why));
+        }
+    });
+
+    violations
+}
+
+/// A problem found by [`diagnose`] on a database.
+///
+/// [`diagnose`]: diagnose
+#[derive(Debug, PartialEq, Eq)]
+pub enum Problem {
+    /// More than one item has this reference ID.
+    RepeatedRefID(RefId),
+    /// More than one item has this internal ID.
+    RepeatedInternalID(InternalId),
+    /// A done item still holds a reference ID.
+    DoneWithRefID(InternalId, RefId),
+    /// An item inside a done item still holds a reference ID.
+    OrphanedRefID(InternalId, RefId),
+    /// An item that isn't done (nor inside a done item) doesn't have a reference ID.
+    MissingRefID(InternalId),
+}
+
+impl std::fmt::Display for Problem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::RepeatedRefID(RefId(id)) => write!(f, "repeated reference ID #{}", id),
+            Self::RepeatedInternalID(InternalId(id)) => write!(f, "repeated internal ID i{}", id),
+            Self::DoneWithRefID(InternalId(i), RefId(r)) => {
+                write!(f, "done item i{} still has reference ID #{}", i, r)
+            }
+            Self::OrphanedRefID(InternalId(i), RefId(r)) => write!(
+                f,
+                "item i{} is inside a done item but still has reference ID #{}",
+                i, r
+            ),
+            Self::MissingRefID(InternalId(i)) => {
+                write!(f, "pending item i{} doesn't have a reference ID", i)
+            }
+        }
+    }
+}
+
+/// Checks a database for inconsistencies, returning all problems found.
+///
+/// [`ItemManager::new`] refuses the data if any IDs are repeated, but unlike it, this doesn't stop at the first problem
+/// and doesn't fix anything.
+///
+/// [`ItemManager::new`]: ItemManager::new
+pub fn diagnose(data: &[Item]) -> Vec<Problem> {
+    fn travel(
+        data: &[Item],
+        under_done: bool,
+        ref_set: &mut HashSet<u32>,
+        in_set: &mut HashSet<u32>,
+        problems: &mut Vec<Problem>,
+    ) {
+        for item in data {
+            let internal_id = InternalId(item.internal_id);
+            let is_done = item.state == ItemState::Done;
+
+            if let Some(id) = item.ref_id {
+                if !ref_set.insert(id) {
+                    problems.push(Problem::RepeatedRefID(RefId(id)));
+                }
+
+                if item.is_done_with_ref_id() {
+                    problems.push(Problem::DoneWithRefID(internal_id, RefId(id)));
+                } else if under_done {
+                    problems.push(Problem::OrphanedRefID(internal_id, RefId(id)));
+                }
+            } else if !is_done && !under_done {
+                problems.push(Problem::MissingRefID(internal_id));
+            }
+
+            if !in_set.insert(item.internal_id) {
+                problems.push(Problem::RepeatedInternalID(internal_id));
+            }
+
+            travel(
+                &item.children,
+                under_done || is_done,
+                ref_set,
+                in_set,
+                problems,
+            );
+        }
+    }
+
+    let mut problems = Vec::new();
+    travel(
+        data,
+        false,
+        &mut HashSet::new(),
+        &mut HashSet::new(),
+        &mut problems,
+    );
+
+    problems
 }
 
 /// A trait to help on searching through a database with different types of queries.
@@ -64,45 +190,62 @@ pub trait Interactable<T>: Searchable<T> {
 
 impl<T, M> Interactable<T> for M where M: Searchable<T> {}
 
-impl Searchable<RefId> for ItemManager {
-    type Data = Item;
+/// Returns all reference IDs on `ids` that can't be found on `searchable`, without repetitions and in the order they
+/// first appear.
+pub fn invalid_ref_ids<'a, S, I>(searchable: &S, ids: I) -> Vec<RefId>
+where
+    S: Searchable<RefId>,
+    I: IntoIterator<Item = &'a u32>,
+{
+    let mut invalid: Vec<RefId> = Vec::new();
+
+    for &id in ids {
+        let ref_id = RefId(id);
+        if searchable.find(ref_id).is_none() && !invalid.contains(&ref_id) {
+            invalid.push(ref_id);
+        }
+    }
 
-    fn find(&self, query: RefId) -> Option<&Item> {
-        fn search(items: &Vec<Item>, query: RefId) -> Option<&Item> {
-            for item in items {
-                if item.ref_id == Some(query.0) {
-                    return Some(item);
-                }
+    invalid
+}
 
-                let find_result = search(&item.children, query);
-                if find_result.is_some() {
-                    return find_result;
-                }
-            }
+/// Returns the position right after the item with reference ID `after` on `siblings`, if it's there.
+pub fn position_after(siblings: &[Item], RefId(after): RefId) -> Option<usize> {
+    siblings
+        .iter()
+        .position(|item| item.ref_id == Some(after))
+        .map(|pos| pos + 1)
+}
 
-            None
-        }
+/// Inserts `item` on `items` at `index`, or pushes it if the index is `None` or out of bounds.
+fn insert_at(items: &mut Vec<Item>, index: Option<usize>, item: Item) {
+    match index {
+        Some(index) if index <= items.len() => items.insert(index, item),
+        _ => items.push(item),
+    }
+}
 
-        search(&self.data, query)
+impl TreeManager for ItemManager {
+    type Data = Item;
+
+    fn roots(&self) -> &[Item] {
+        &self.data
     }
 
-    fn find_mut(&mut self, query: RefId) -> Option<&mut Item> {
-        fn search(items: &mut Vec<Item>, query: RefId) -> Option<&mut Item> {
-            for item in items {
-                if item.ref_id == Some(query.0) {
-                    return Some(item);
-                }
+    fn roots_mut(&mut self) -> &mut Vec<Item> {
+        &mut self.data
+    }
+}
 
-                let find_result = search(&mut item.children, query);
-                if find_result.is_some() {
-                    return find_result;
-                }
-            }
+impl Searchable<RefId> for ItemManager {
+    type Data = Item;
 
-            None
-        }
+    fn find(&self, query: RefId) -> Option<&Item> {
+        self.find_where(|item| item.ref_id == Some(query.0))
+    }
 
-        search(&mut self.data, query)
+    fn find_mut(&mut self, query: RefId) -> Option<&mut Item> {
+        self.find_where_mut(|item| item.ref_id == Some(query.0))
     }
 }
 
@@ -110,41 +253,11 @@ impl Searchable<InternalId> for ItemManager {
     type Data = Item;
 
     fn find(&self, query: InternalId) -> Option<&Item> {
-        fn search(items: &Vec<Item>, query: InternalId) -> Option<&Item> {
-            for item in items {
-                if item.internal_id == query.0 {
-                    return Some(item);
-                }
-
-                let find_result = search(&item.children, query);
-                if find_result.is_some() {
-                    return find_result;
-                }
-            }
-
-            None
-        }
-
-        search(&self.data, query)
+        self.find_where(|item| item.internal_id == query.0)
     }
 
     fn find_mut(&mut self, query: InternalId) -> Option<&mut Item> {
-        fn search(items: &mut Vec<Item>, query: InternalId) -> Option<&mut Item> {
-            for item in items {
-                if item.internal_id == query.0 {
-                    return Some(item);
-                }
-
-                let find_result = search(&mut item.children, query);
-                if find_result.is_some() {
-                    return find_result;
-                }
-            }
-
-            None
-        }
-
-        search(&mut self.data, query)
+        self.find_where_mut(|item| item.internal_id == query.0)
     }
 }
 
@@ -154,51 +267,57 @@ pub struct ProgramResult {
     pub exit_status: i32,
 }
 
+/// The file the items were loaded from, and how changes are saved - to it, or to the files made from it (like exported
+/// subtrees).
+#[derive(Debug, Clone, Copy)]
+pub struct DataFile<'a> {
+    pub path: &'a Path,
+    pub format: StorageFormat,
+    /// Whether nothing should be written at all (`--no-save`).
+    pub no_save: bool,
+}
+
 impl ItemManager {
     /// Attempts to create an ItemManager instance, returning a [`ManagerError`] if the operation failed.
     ///
+    /// If [`strict_mode`] is enabled, all items are validated first.
+    ///
     /// [`ManagerError`]: ManagerError
-    pub fn new(mut data: Vec<Item>) -> Result<Self, ManagerError> {
-        let mut ref_set: HashSet<u32> = HashSet::new();
-        let mut in_set: HashSet<u32> = HashSet::new();
+    /// [`strict_mode`]: strict_mode
+    pub fn new(data: Vec<Item>) -> Result<Self, ManagerError> {
+        Self::with_validation(data, strict_mode())
+    }
 
-        fn travel(
-            data: &Vec<Item>,
-            ref_set: &mut HashSet<u32>,
-            in_set: &mut HashSet<u32>,
-        ) -> Result<(), ManagerError> {
-            // TODO: store the amount of items to reserve on a file instead of doing this
-            ref_set.reserve(data.len() / 4); // reserve a fraction of the amount of items, since many of them might not have ref IDs.
-            in_set.reserve(data.len());
-
-            for item in data {
-                // add RefID
-                if let Some(id) = item.ref_id {
-                    if ref_set.contains(&id) {
-                        return Err(ManagerError::RepeatedRefID(RefId(id)));
-                    } else {
-                        ref_set.insert(id);
-                    }
-                }
+    /// Like [`ItemManager::new`], but validating the items only if `strict` is set.
+    ///
+    /// [`ItemManager::new`]: ItemManager::new
+    pub fn with_validation(mut data: Vec<Item>, strict: bool) -> Result<Self, ManagerError> {
+        if strict {
+            let violations = validate_all(&data);
 
-                // add InternalID
-                if in_set.contains(&item.internal_id) {
-                    return Err(ManagerError::RepeatedInternalID(InternalId(
-                        item.internal_id,
-                    )));
-                } else {
-                    in_set.insert(item.internal_id);
-                }
+            if !violations.is_empty() {
+                return Err(ManagerError::InvalidItems(violations));
+            }
+        }
 
-                if !item.children.is_empty() {
-                    travel(&item.children, ref_set, in_set)?;
+        // The repeated IDs can't be fixed here; the other problems are either fixed below or left for `itmn doctor`.
+        for problem in diagnose(&data) {
+            match problem {
+                Problem::RepeatedRefID(id) => return Err(ManagerError::RepeatedRefID(id)),
+                Problem::RepeatedInternalID(id) => {
+                    return Err(ManagerError::RepeatedInternalID(id))
                 }
+                _ => (),
             }
-
-            Ok(())
         }
 
-        travel(&data, &mut ref_set, &mut in_set)?;
+        let mut ref_set: HashSet<u32> = HashSet::new();
+        let mut in_set: HashSet<u32> = HashSet::new();
+
+        utils::data::tree::walk(&data, &mut |item: &Item, _| {
+            ref_set.extend(item.ref_id);
+            in_set.insert(item.internal_id);
+        });
 
         // With the now filled IDs set, find free reference IDs for pending/note items that don't have IDs.
         for item in data.iter_mut() {
@@ -223,14 +342,35 @@ impl ItemManager {
 
     /// Starts a program of function signature F, which takes a mutable reference of the manager as an argument and
     /// returns a ProgramResult struct.
-    pub fn start_program_with_file<F>(&mut self, file: &Path, program: F) -> i32
+    ///
+    /// Before saving, the current file is copied to its backup path (see [`utils::io::backup_path`]).
+    ///
+    /// [`utils::io::backup_path`]: utils::io::backup_path
+    pub fn start_program_with_file<F>(
+        &mut self,
+        file: &Path,
+        format: StorageFormat,
+        program: F,
+    ) -> i32
     where
         F: FnOnce(&mut ItemManager) -> ProgramResult,
     {
         let result = program(self);
 
         if result.should_save {
-            if let Err(e) = data_serialize::save_to_file(&self.data, file, true) {
+            // keep the previous version around, so `itmn diff` can show what changed
+            if file.exists() {
+                let backup = utils::io::backup_path(file);
+                utils::debug!("backing up {} to {}", file.display(), backup.display());
+
+                if let Err(e) = std::fs::copy(file, &backup) {
+                    utils::warn!("failed to back up to {}: {}", backup.display(), e);
+                }
+            }
+
+            utils::debug!("saving to {}", file.display());
+
+            if let Err(e) = format.save_to_file(&self.data, file) {
                 eprintln!("Error: failed to save to file: {}", e);
                 return 1;
             }
@@ -239,7 +379,8 @@ impl ItemManager {
         result.exit_status
     }
 
-    /// Constructs and adds an item to the root of the database.
+    /// Constructs and adds an item to the root of the database, at `index` (or at the end, if it's `None` or out of
+    /// bounds).
     ///
     /// Returns the item's RefId.
     pub fn add_item_on_root(
@@ -249,6 +390,7 @@ impl ItemManager {
         state: ItemState,
         description: String,
         children: Vec<Item>,
+        index: Option<usize>,
     ) -> RefId {
         // Might crash with an overflow but seriously, who is gonna have 4,294,967,296 items in a lifetime?
         let free_ref_id = utils::misc::find_lowest_free_value(&self.ref_ids);
@@ -257,7 +399,13 @@ impl ItemManager {
         let free_internal_id = utils::misc::find_highest_free_value(&self.internal_ids);
         self.internal_ids.insert(free_internal_id);
 
-        self.data.push(Item::new(
+        utils::debug!(
+            "allocated ref ID {} and internal ID {} for new root item",
+            free_ref_id,
+            free_internal_id
+        );
+
+        let item = Item::new(
             Some(free_ref_id),
             free_internal_id,
             name,
@@ -265,11 +413,16 @@ impl ItemManager {
             state,
             description,
             children,
-        ));
+        );
+        insert_at(&mut self.data, index, item);
 
         RefId(free_ref_id)
     }
 
+    /// Constructs and adds an item to the children of the item found by `query`, at `index` (or at the end, if it's
+    /// `None` or out of bounds).
+    ///
+    /// Returns the item's RefId, or `Err(())` if the parent wasn't found.
     pub fn add_child<Q>(
         &mut self,
         query: Q,
@@ -278,6 +431,7 @@ impl ItemManager {
         state: ItemState,
         description: String,
         children: Vec<Item>,
+        index: Option<usize>,
     ) -> Result<RefId, ()>
     where
         Self: Searchable<Q, Data = Item>,
@@ -288,8 +442,14 @@ impl ItemManager {
         let free_internal_id = utils::misc::find_highest_free_value(self.internal_ids());
         self.internal_ids.insert(free_internal_id.into());
 
+        utils::debug!(
+            "allocated ref ID {} and internal ID {} for new child item",
+            free_ref_id,
+            free_internal_id
+        );
+
         if let Some(i) = self.find_mut(query) {
-            i.children.push(Item::new(
+            let item = Item::new(
                 Some(free_ref_id),
                 free_internal_id,
                 name,
@@ -297,7 +457,8 @@ impl ItemManager {
                 state,
                 description,
                 children,
-            ));
+            );
+            insert_at(&mut i.children, index, item);
 
             Ok(RefId(free_ref_id))
         } else {
@@ -305,50 +466,118 @@ impl ItemManager {
         }
     }
 
-    pub fn surface_ref_ids(&self) -> Vec<RefId> {
-        self.data
-            .iter()
-            .filter_map(|i| i.ref_id.and_then(|id| Some(RefId(id))))
-            .collect()
-    }
+    /// Adds a whole item tree to the children of `parent` (or to the root, if it's `None`), at `index` (or at the end,
+    /// if it's `None` or out of bounds). Every item gets a fresh internal ID, and a reference ID unless it (or one of
+    /// its ancestors) is done.
+    ///
+    /// Returns the new root item.
+    pub fn add_skeleton(
+        &mut self,
+        parent: Option<RefId>,
+        skeleton: &ItemSkeleton,
+        index: Option<usize>,
+    ) -> Result<&Item, String> {
+        struct Allocator<I: Iterator<Item = u32>> {
+            ref_ids: I,
+            next_internal_id: u32,
+        }
 
-    // pub fn get_all_ref_ids(&self) -> Vec<RefId> {}
+        /// How many items of the tree will need a reference ID.
+        fn count_visible(skeleton: &ItemSkeleton, hidden: bool) -> usize {
+            let hidden = hidden || skeleton.state == Some(ItemState::Done);
+            let own = if hidden { 0 } else { 1 };
 
-    pub fn try_remove(&mut self, ref_id: RefId) -> Option<Item> {
-        fn search(items: &mut Vec<Item>, ref_id: RefId) -> Option<Item> {
-            let mut i = 0;
+            own + skeleton
+                .children
+                .iter()
+                .map(|child| count_visible(child, hidden))
+                .sum::<usize>()
+        }
 
-            while i < items.len() {
-                if items[i].ref_id == Some(ref_id.0) {
-                    // FIXME: should this really be O(n)?
-                    return Some(items.remove(i));
-                }
+        fn build<I: Iterator<Item = u32>>(
+            ids: &mut Allocator<I>,
+            skeleton: &ItemSkeleton,
+            hidden: bool,
+        ) -> Item {
+            let state = skeleton.state.unwrap_or(ItemState::Todo);
+            let hidden = hidden || state == ItemState::Done;
 
-                if let Some(item) = search(&mut items[i].children, ref_id) {
-                    return Some(item);
-                }
+            let internal_id = ids.next_internal_id;
+            ids.next_internal_id += 1;
 
-                i += 1;
+            let ref_id = if hidden { None } else { ids.ref_ids.next() };
+
+            let mut item = Item::new(
+                ref_id,
+                internal_id,
+                &skeleton.name,
+                skeleton.context.as_deref().unwrap_or(""),
+                state,
+                skeleton.description.clone(),
+                Vec::new(),
+            );
+
+            for tag in &skeleton.tags {
+                item.add_tag(tag);
             }
 
-            None
-        }
+            if let Some(annotation) = &skeleton.annotation {
+                item.set_annotation(annotation);
+            }
 
-        search(&mut self.data, ref_id)
-    }
+            item.children = skeleton
+                .children
+                .iter()
+                .map(|child| build(ids, child, hidden))
+                .collect();
 
-    pub fn first_invalid_ref_id<'a, I>(&self, ids: I) -> Option<RefId>
-    where
-        I: Iterator<Item = &'a u32>,
-    {
-        for id in ids {
-            let ref_id = RefId(*id);
-            if self.find(ref_id).is_none() {
-                return Some(ref_id);
+            item
+        }
+
+        if let Some(RefId(id)) = parent {
+            if self.find(RefId(id)).is_none() {
+                return Err(format!("invalid ID: #{}", id));
             }
         }
 
-        None
+        // allocate every ID at once instead of scanning the sets again for each item
+        let ref_ids = utils::misc::find_free_values(&self.ref_ids, count_visible(skeleton, false));
+        let mut ids = Allocator {
+            ref_ids: ref_ids.into_iter(),
+            next_internal_id: utils::misc::find_highest_free_value(&self.internal_ids),
+        };
+
+        let item = build(&mut ids, skeleton, false);
+        utils::data::tree::walk(std::slice::from_ref(&item), &mut |item: &Item, _| {
+            self.internal_ids.insert(item.internal_id);
+            if let Some(id) = item.ref_id {
+                self.ref_ids.insert(id);
+            }
+        });
+
+        let internal_id = item.internal_id;
+        utils::debug!("allocated internal ID {} for new item tree", internal_id);
+
+        let siblings = match parent {
+            Some(parent) => &mut self.find_mut(parent).unwrap().children,
+            None => &mut self.data,
+        };
+        insert_at(siblings, index, item);
+
+        Ok(self.find(InternalId(internal_id)).unwrap())
+    }
+
+    pub fn surface_ref_ids(&self) -> Vec<RefId> {
+        self.data
+            .iter()
+            .filter_map(|i| i.ref_id.and_then(|id| Some(RefId(id))))
+            .collect()
+    }
+
+    // pub fn get_all_ref_ids(&self) -> Vec<RefId> {}
+
+    pub fn try_remove(&mut self, ref_id: RefId) -> Option<Item> {
+        self.remove_where(|item| item.ref_id == Some(ref_id.0))
     }
 
     pub fn swap<T, E>(&mut self, query_1: T, query_2: E) -> Result<(), String>
@@ -381,19 +610,251 @@ impl ItemManager {
         }
     }
 
-    pub fn change_item_state<Q, F>(&mut self, id: Q, mapper: F) -> Result<(), ()>
+    /// Gives the item with `ref_id` the reference ID `new_id`. If another item already has it, the two items exchange
+    /// their IDs if `swap` is true, and it's an error otherwise.
+    ///
+    /// Returns the ID the other item had, if they were swapped.
+    pub fn set_ref_id(
+        &mut self,
+        ref_id: RefId,
+        new_id: u32,
+        swap: bool,
+    ) -> Result<Option<RefId>, String> {
+        let internal_id = match self.find(ref_id) {
+            None => return Err(format!("invalid ID: #{}", ref_id.0)),
+            // done items shouldn't have reference IDs at all, but they surely can't get a new one
+            Some(item) if item.state == ItemState::Done => {
+                return Err(format!(
+                    "#{} is done, so it can't have a reference ID",
+                    ref_id.0
+                ))
+            }
+            Some(_) if ref_id.0 == new_id => return Ok(None),
+            Some(item) => InternalId(item.internal_id),
+        };
+
+        let swapped = match self.find_mut(RefId(new_id)) {
+            Some(holder) if swap => {
+                holder.ref_id = Some(ref_id.0);
+                Some(RefId(new_id))
+            }
+            Some(holder) => {
+                return Err(format!(
+                    "#{} is already used by {:?} (use --swap to exchange their IDs)",
+                    new_id, holder.name
+                ))
+            }
+            None => {
+                self.ref_ids.remove(&ref_id.0);
+                self.ref_ids.insert(new_id);
+                None
+            }
+        };
+
+        // the other item might have the old ID by now, so this one is found by its internal ID
+        self.find_mut(internal_id).unwrap().ref_id = Some(new_id);
+
+        Ok(swapped)
+    }
+
+    /// Moves the direct children of the item with `ref_id` to its own level (the root or its parent's children), right
+    /// after it and keeping their order, leaving the item childless. Returns how many children were moved, or `None`
+    /// if the item couldn't be found.
+    pub fn promote_children(&mut self, ref_id: RefId) -> Option<usize> {
+        fn travel(items: &mut Vec<Item>, ref_id: RefId) -> Option<usize> {
+            match items.iter().position(|i| i.ref_id == Some(ref_id.0)) {
+                Some(index) => {
+                    let children = std::mem::take(&mut items[index].children);
+                    let amount = children.len();
+                    items.splice(index + 1..index + 1, children);
+
+                    Some(amount)
+                }
+                None => items
+                    .iter_mut()
+                    .find_map(|item| travel(&mut item.children, ref_id)),
+            }
+        }
+
+        travel(&mut self.data, ref_id)
+    }
+
+    /// Makes the item with `ref_id` the last child of its preceding sibling.
+    ///
+    /// Fails if the item couldn't be found or if it's the first one among its siblings.
+    pub fn indent(&mut self, ref_id: RefId) -> Result<(), String> {
+        fn travel(items: &mut Vec<Item>, ref_id: RefId) -> Option<Result<(), String>> {
+            match items.iter().position(|i| i.ref_id == Some(ref_id.0)) {
+                Some(0) => Some(Err(format!(
+                    "#{} has no preceding sibling to indent under",
+                    ref_id.0
+                ))),
+                Some(index) => {
+                    let item = items.remove(index);
+                    items[index - 1].children.push(item);
+
+                    Some(Ok(()))
+                }
+                None => items
+                    .iter_mut()
+                    .find_map(|item| travel(&mut item.children, ref_id)),
+            }
+        }
+
+        travel(&mut self.data, ref_id)
+            .unwrap_or_else(|| Err(format!("#{} could not be found", ref_id.0)))
+    }
+
+    /// Makes the item with `ref_id` a sibling of its parent, placing it right after the parent.
+    ///
+    /// Fails if the item couldn't be found or if it's already on the root.
+    pub fn outdent(&mut self, ref_id: RefId) -> Result<(), String> {
+        fn travel(items: &mut Vec<Item>, ref_id: RefId) -> Option<()> {
+            for index in 0..items.len() {
+                let children = &mut items[index].children;
+
+                match children.iter().position(|i| i.ref_id == Some(ref_id.0)) {
+                    Some(child_index) => {
+                        let item = children.remove(child_index);
+                        items.insert(index + 1, item);
+
+                        return Some(());
+                    }
+                    None => {
+                        if travel(children, ref_id).is_some() {
+                            return Some(());
+                        }
+                    }
+                }
+            }
+
+            None
+        }
+
+        if self.data.iter().any(|i| i.ref_id == Some(ref_id.0)) {
+            return Err(format!("#{} is already on the root", ref_id.0));
+        }
+
+        travel(&mut self.data, ref_id).ok_or_else(|| format!("#{} could not be found", ref_id.0))
+    }
+
+    /// Returns the items leading to the one found with `query`, from the surface item to the found item itself.
+    pub fn ancestry<Q>(&self, query: Q) -> Option<Vec<&Item>>
     where
         Self: Searchable<Q, Data = Item>,
-        F: FnOnce(ItemState) -> ItemState,
     {
-        let item = self.find_mut(id).ok_or(())?;
-        let new_state = mapper(item.state);
+        fn travel<'a>(items: &'a [Item], target: u32, path: &mut Vec<&'a Item>) -> bool {
+            for item in items {
+                path.push(item);
 
-        if new_state == ItemState::Done {
-            item.ref_id = None;
+                if item.internal_id == target || travel(&item.children, target, path) {
+                    return true;
+                }
+
+                path.pop();
+            }
+
+            false
         }
 
-        item.state = new_state;
+        let target = self.find(query)?.internal_id;
+        let mut path = Vec::new();
+
+        if travel(&self.data, target, &mut path) {
+            Some(path)
+        } else {
+            None
+        }
+    }
+
+    /// Marks the done item with `internal_id` as TODO again, giving it a fresh reference ID. Its children that aren't
+    /// done (and their children, and so on) get reference IDs too, since they are visible again.
+    ///
+    /// Fails if the item is under another done item, which would still hide it; that one has to be reopened first.
+    ///
+    /// Returns the new reference ID of the item.
+    pub fn reopen(&mut self, internal_id: InternalId) -> Result<RefId, String> {
+        fn assign(item: &mut Item, ref_ids: &mut HashSet<u32>) {
+            if item.state == ItemState::Done {
+                return;
+            }
+
+            if item.ref_id.is_none() {
+                let id = utils::misc::find_lowest_free_value(ref_ids);
+                ref_ids.insert(id);
+                item.ref_id = Some(id);
+            }
+
+            for child in item.children.iter_mut() {
+                assign(child, ref_ids);
+            }
+        }
+
+        let ancestors = self.ancestry(internal_id).unwrap_or_default();
+        if let Some(done) = ancestors
+            .iter()
+            .rev()
+            .skip(1)
+            .find(|item| item.state == ItemState::Done)
+        {
+            return Err(format!(
+                "i{} is under i{}, which is done and has to be reopened first",
+                internal_id.0, done.internal_id
+            ));
+        }
+
+        // taken out for a moment, since the item borrows the whole manager
+        let mut ref_ids = std::mem::take(&mut self.ref_ids);
+        let reopened = match self.find_mut(internal_id) {
+            Some(item) if item.state == ItemState::Done => {
+                item.set_state(ItemState::Todo);
+                assign(item, &mut ref_ids);
+                Ok(RefId(item.ref_id.unwrap()))
+            }
+            Some(_) => Err(format!("i{} is not done", internal_id.0)),
+            None => Err(format!("i{} could not be found", internal_id.0)),
+        };
+        self.ref_ids = ref_ids;
+
+        reopened
+    }
+
+    /// Returns the open (todo) items with `context`, in tree order. With `recursive`, the open items under them come
+    /// along too, whatever their context. Done items are closed along with what's under them, so they aren't looked
+    /// into.
+    pub fn open_with_context(&self, context: &str, recursive: bool) -> Vec<InternalId> {
+        fn visit(
+            items: &[Item],
+            context: &str,
+            recursive: bool,
+            under_match: bool,
+            found: &mut Vec<InternalId>,
+        ) {
+            for item in items.iter().filter(|i| i.state != ItemState::Done) {
+                let matches = item.context() == Some(context);
+
+                if item.state == ItemState::Todo && (matches || under_match) {
+                    found.push(InternalId(item.internal_id));
+                }
+
+                let under_match = recursive && (under_match || matches);
+                visit(&item.children, context, recursive, under_match, found);
+            }
+        }
+
+        let mut found = Vec::new();
+        visit(&self.data, context, recursive, false, &mut found);
+
+        found
+    }
+
+    pub fn change_item_state<Q, F>(&mut self, id: Q, mapper: F) -> Result<(), ()>
+    where
+        Self: Searchable<Q, Data = Item>,
+        F: FnOnce(ItemState) -> ItemState,
+    {
+        let item = self.find_mut(id).ok_or(())?;
+        item.set_state(mapper(item.state));
 
         Ok(())
     }
@@ -410,3 +871,411 @@ impl ItemManager {
         &self.ref_ids
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(ref_id: Option<u32>, internal_id: u32, state: ItemState, children: Vec<Item>) -> Item {
+        Item::new(
+            ref_id,
+            internal_id,
+            "item",
+            "",
+            state,
+            String::new(),
+            children,
+        )
+    }
+
+    #[test]
+    fn diagnose_reports_all_problems() {
+        let data = vec![
+            item(Some(0), 0, ItemState::Todo, Vec::new()),
+            item(
+                Some(0),
+                1,
+                ItemState::Done,
+                vec![item(Some(1), 1, ItemState::Todo, Vec::new())],
+            ),
+            item(None, 2, ItemState::Note, Vec::new()),
+        ];
+
+        assert_eq!(
+            diagnose(&data),
+            vec![
+                Problem::RepeatedRefID(RefId(0)),
+                Problem::DoneWithRefID(InternalId(1), RefId(0)),
+                Problem::OrphanedRefID(InternalId(1), RefId(1)),
+                Problem::RepeatedInternalID(InternalId(1)),
+                Problem::MissingRefID(InternalId(2)),
+            ]
+        );
+    }
+
+    #[test]
+    fn add_after_sibling() {
+        let mut manager = ItemManager::new(vec![
+            item(Some(0), 0, ItemState::Todo, Vec::new()),
+            item(Some(1), 1, ItemState::Todo, Vec::new()),
+        ])
+        .unwrap_or_else(|_| panic!("the data should be valid"));
+
+        let index = position_after(&manager.data, RefId(0));
+        let RefId(id) =
+            manager.add_item_on_root("new", "", ItemState::Todo, String::new(), Vec::new(), index);
+
+        assert_eq!(
+            manager.data.iter().map(|i| i.ref_id).collect::<Vec<_>>(),
+            vec![Some(0), Some(id), Some(1)]
+        );
+        assert_eq!(position_after(&manager.data, RefId(9)), None);
+    }
+
+    #[test]
+    fn promote_children_to_parent_level() {
+        let mut manager = ItemManager::new(vec![item(
+            Some(0),
+            0,
+            ItemState::Todo,
+            vec![
+                item(
+                    Some(1),
+                    1,
+                    ItemState::Todo,
+                    vec![
+                        item(Some(2), 2, ItemState::Todo, Vec::new()),
+                        item(None, 3, ItemState::Done, Vec::new()),
+                    ],
+                ),
+                item(Some(4), 4, ItemState::Todo, Vec::new()),
+            ],
+        )])
+        .unwrap_or_else(|_| panic!("the data should be valid"));
+
+        assert_eq!(manager.promote_children(RefId(1)), Some(2));
+        assert_eq!(
+            manager.data[0]
+                .children
+                .iter()
+                .map(|i| i.internal_id)
+                .collect::<Vec<_>>(),
+            vec![1, 2, 3, 4]
+        );
+        assert!(manager.find(RefId(1)).unwrap().children.is_empty());
+
+        assert_eq!(manager.promote_children(RefId(0)), Some(4));
+        assert_eq!(manager.data.len(), 5);
+        assert_eq!(manager.promote_children(RefId(4)), Some(0));
+        assert_eq!(manager.promote_children(RefId(9)), None);
+    }
+
+    #[test]
+    fn add_skeleton_allocates_fresh_ids() {
+        let mut manager = ItemManager::new(vec![item(Some(0), 0, ItemState::Todo, Vec::new())])
+            .unwrap_or_else(|_| panic!("the data should be valid"));
+
+        let skeleton = ItemSkeleton::parse(
+            r#"{
+                "name": "project",
+                "ref_id": 0,
+                "internal_id": 0,
+                "tags": ["big"],
+                "children": [
+                    {"name": "step", "context": "work"},
+                    {"name": "finished", "state": "Done", "children": [{"name": "leftover"}]}
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        let root = manager.add_skeleton(None, &skeleton, None).unwrap();
+        assert_eq!((root.ref_id, root.internal_id), (Some(1), 1));
+        assert!(root.has_tag("big"));
+
+        let ids: Vec<(Option<u32>, u32)> = root
+            .children
+            .iter()
+            .chain(&root.children[1].children)
+            .map(|i| (i.ref_id, i.internal_id))
+            .collect();
+        assert_eq!(ids, vec![(Some(2), 2), (None, 3), (None, 4)]);
+        assert_eq!(root.children[0].context(), Some("work"));
+
+        // under an existing item
+        let child = manager
+            .add_skeleton(
+                Some(RefId(0)),
+                &ItemSkeleton::parse(r#"{"name": "sub"}"#).unwrap(),
+                None,
+            )
+            .unwrap();
+        assert_eq!(child.ref_id, Some(3));
+        assert_eq!(manager.data[0].children.len(), 1);
+        assert!(manager
+            .add_skeleton(Some(RefId(9)), &skeleton, None)
+            .is_err());
+
+        // strict parsing
+        assert!(ItemSkeleton::parse(r#"{"name": "x", "colour": "red"}"#).is_err());
+        assert!(ItemSkeleton::parse(r#"{"name": "x", "children": [{"name": ""}]}"#).is_err());
+        assert!(ItemSkeleton::parse(r#"{"children": []}"#).is_err());
+    }
+
+    #[test]
+    fn add_skeleton_keeps_annotations() {
+        let mut manager =
+            ItemManager::new(Vec::new()).unwrap_or_else(|_| panic!("the data should be valid"));
+
+        // an exported item, as given by `sel ... export`
+        let mut exported = item(Some(4), 7, ItemState::Todo, Vec::new());
+        exported.set_annotation("waiting on review");
+        let json = serde_json::to_string(&exported).unwrap();
+
+        let skeleton = ItemSkeleton::parse(&json).unwrap();
+        let root = manager.add_skeleton(None, &skeleton, None).unwrap();
+        assert_eq!(root.annotation(), Some("waiting on review"));
+
+        assert!(ItemSkeleton::parse(r#"{"name": "x", "annotation": " \t"}"#).is_err());
+    }
+
+    #[test]
+    fn ancestry_goes_from_the_surface() {
+        let manager = ItemManager::new(vec![
+            item(Some(0), 0, ItemState::Todo, Vec::new()),
+            item(
+                Some(1),
+                1,
+                ItemState::Todo,
+                vec![item(
+                    Some(2),
+                    2,
+                    ItemState::Todo,
+                    vec![
+                        item(Some(3), 3, ItemState::Todo, Vec::new()),
+                        item(None, 4, ItemState::Done, Vec::new()),
+                    ],
+                )],
+            ),
+        ])
+        .unwrap_or_else(|_| panic!("the data should be valid"));
+
+        let ids = |path: Option<Vec<&Item>>| -> Option<Vec<u32>> {
+            path.map(|path| path.iter().map(|i| i.internal_id).collect())
+        };
+
+        assert_eq!(ids(manager.ancestry(RefId(3))), Some(vec![1, 2, 3]));
+        assert_eq!(ids(manager.ancestry(InternalId(4))), Some(vec![1, 2, 4]));
+        assert_eq!(ids(manager.ancestry(RefId(0))), Some(vec![0]));
+        assert_eq!(ids(manager.ancestry(RefId(9))), None);
+    }
+
+    #[test]
+    fn reopen_allocates_ref_ids() {
+        let mut manager = ItemManager::new(vec![
+            item(Some(0), 0, ItemState::Todo, Vec::new()),
+            item(
+                None,
+                1,
+                ItemState::Done,
+                vec![
+                    item(None, 2, ItemState::Todo, Vec::new()),
+                    item(
+                        None,
+                        3,
+                        ItemState::Done,
+                        vec![item(None, 4, ItemState::Todo, Vec::new())],
+                    ),
+                ],
+            ),
+        ])
+        .unwrap_or_else(|_| panic!("the data should be valid"));
+
+        assert!(manager.reopen(InternalId(0)).is_err());
+        assert!(manager.reopen(InternalId(9)).is_err());
+
+        assert_eq!(manager.reopen(InternalId(1)), Ok(RefId(1)));
+        let reopened = manager.find(RefId(1)).unwrap();
+        assert_eq!(reopened.state, ItemState::Todo);
+        assert_eq!(reopened.children[0].ref_id, Some(2));
+        // done children stay hidden, along with what's under them
+        assert_eq!(reopened.children[1].ref_id, None);
+        assert_eq!(reopened.children[1].children[0].ref_id, None);
+        assert!(diagnose(&manager.data).is_empty());
+    }
+
+    #[test]
+    fn reopen_needs_open_ancestors() {
+        let mut manager = ItemManager::new(vec![item(
+            None,
+            0,
+            ItemState::Done,
+            vec![item(
+                None,
+                1,
+                ItemState::Done,
+                vec![item(None, 2, ItemState::Todo, Vec::new())],
+            )],
+        )])
+        .unwrap_or_else(|_| panic!("the data should be valid"));
+
+        // the child would get a reference ID while still hidden under its parent
+        assert!(manager.reopen(InternalId(1)).is_err());
+        assert_eq!(manager.find(InternalId(1)).unwrap().state, ItemState::Done);
+        assert!(manager.ref_ids.is_empty());
+
+        assert_eq!(manager.reopen(InternalId(0)), Ok(RefId(0)));
+        assert_eq!(manager.reopen(InternalId(1)), Ok(RefId(1)));
+        assert_eq!(manager.find(InternalId(2)).unwrap().ref_id, Some(2));
+        assert!(diagnose(&manager.data).is_empty());
+    }
+
+    #[test]
+    fn open_items_by_context() {
+        let with_context = |mut item: Item, context| {
+            item.set_context(context);
+            item
+        };
+        let manager = ItemManager::new(vec![
+            with_context(
+                item(
+                    Some(0),
+                    0,
+                    ItemState::Todo,
+                    vec![
+                        item(Some(1), 1, ItemState::Todo, Vec::new()),
+                        item(Some(2), 2, ItemState::Note, Vec::new()),
+                    ],
+                ),
+                "errands",
+            ),
+            item(
+                Some(3),
+                3,
+                ItemState::Todo,
+                vec![with_context(
+                    item(Some(4), 4, ItemState::Todo, Vec::new()),
+                    "errands",
+                )],
+            ),
+            with_context(item(None, 5, ItemState::Done, Vec::new()), "errands"),
+            with_context(item(Some(6), 6, ItemState::Todo, Vec::new()), "work"),
+        ])
+        .unwrap_or_else(|_| panic!("the data should be valid"));
+
+        let ids = |recursive| {
+            manager
+                .open_with_context("errands", recursive)
+                .into_iter()
+                .map(|InternalId(id)| id)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(ids(false), [0, 4]);
+        assert_eq!(ids(true), [0, 1, 4]);
+        assert!(manager.open_with_context("gym", true).is_empty());
+    }
+
+    #[test]
+    fn set_ref_ids() {
+        let mut manager = ItemManager::new(vec![
+            item(Some(0), 0, ItemState::Todo, Vec::new()),
+            item(
+                Some(1),
+                1,
+                ItemState::Todo,
+                vec![item(Some(2), 2, ItemState::Todo, Vec::new())],
+            ),
+        ])
+        .unwrap_or_else(|_| panic!("the data should be valid"));
+
+        assert_eq!(manager.set_ref_id(RefId(2), 42, false), Ok(None));
+        assert_eq!(manager.find(RefId(42)).unwrap().internal_id, 2);
+        assert!(manager.find(RefId(2)).is_none());
+        assert!(manager.ref_ids().contains(&42) && !manager.ref_ids().contains(&2));
+
+        assert!(manager.set_ref_id(RefId(0), 1, false).is_err());
+        assert_eq!(manager.find(RefId(0)).unwrap().internal_id, 0);
+
+        assert_eq!(manager.set_ref_id(RefId(0), 1, true), Ok(Some(RefId(1))));
+        assert_eq!(manager.find(RefId(1)).unwrap().internal_id, 0);
+        assert_eq!(manager.find(RefId(0)).unwrap().internal_id, 1);
+
+        assert_eq!(manager.set_ref_id(RefId(1), 1, false), Ok(None));
+        assert!(manager.set_ref_id(RefId(7), 8, false).is_err());
+        assert!(diagnose(&manager.data).is_empty());
+    }
+
+    #[test]
+    fn indent_and_outdent() {
+        let mut manager = ItemManager::new(vec![
+            item(
+                Some(0),
+                0,
+                ItemState::Todo,
+                vec![item(Some(1), 1, ItemState::Todo, Vec::new())],
+            ),
+            item(Some(2), 2, ItemState::Todo, Vec::new()),
+            item(Some(3), 3, ItemState::Todo, Vec::new()),
+        ])
+        .unwrap_or_else(|_| panic!("the data should be valid"));
+
+        let shape = |manager: &ItemManager| -> Vec<(u32, Vec<u32>)> {
+            manager
+                .data
+                .iter()
+                .map(|i| {
+                    (
+                        i.internal_id,
+                        i.children.iter().map(|c| c.internal_id).collect(),
+                    )
+                })
+                .collect()
+        };
+
+        // edges
+        assert!(manager.indent(RefId(0)).is_err());
+        assert!(manager.indent(RefId(1)).is_err());
+        assert!(manager.outdent(RefId(2)).is_err());
+        assert!(manager.indent(RefId(9)).is_err());
+        assert!(manager.outdent(RefId(9)).is_err());
+
+        // goes to the end of the preceding sibling's children
+        manager.indent(RefId(2)).unwrap();
+        assert_eq!(shape(&manager), vec![(0, vec![1, 2]), (3, vec![])]);
+
+        // goes right after the parent
+        manager.outdent(RefId(1)).unwrap();
+        assert_eq!(
+            shape(&manager),
+            vec![(0, vec![2]), (1, vec![]), (3, vec![])]
+        );
+    }
+
+    #[test]
+    fn strict_mode_collects_all_violations() {
+        let data: Vec<Item> = serde_json::from_str(
+            r#"[
+                {"name": "", "state": "Todo", "ref_id": 0, "internal_id": 0, "children": [], "context": null},
+                {"name": "ok", "state": "Done", "ref_id": null, "internal_id": 1, "context": "work",
+                 "children": [{"name": "a\tb", "state": "Note", "ref_id": 1, "internal_id": 2, "children": [],
+                               "context": ".none"}]}
+            ]"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            validate_all(&data),
+            vec![
+                "\"\" (#0): name is empty".to_string(),
+                "\"a\\tb\" (#1): name has invalid characters, context should be empty".to_string(),
+            ]
+        );
+
+        assert!(ItemManager::with_validation(Vec::new(), true).is_ok());
+        match ItemManager::with_validation(data, true) {
+            Err(ManagerError::InvalidItems(violations)) => assert_eq!(violations.len(), 2),
+            _ => panic!("expected invalid items"),
+        }
+    }
+}
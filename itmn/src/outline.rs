@@ -0,0 +1,561 @@
+//! Stores the text format used by `itmn edit-all`, which lets the whole tree be edited at once on a text editor.
+//!
+//! Each item is a line, indented by two spaces per level:
+//!
+//! ```text
+//! [ ] Some task @context {i0}
+//!   [x] A finished subtask {i1}
+//!   [-] A note {i2}
+//! ```
+//!
+//! The marker is `[ ]` for TODO items, `[x]` for DONE items and `[-]` for notes; if it's missing, the item is a TODO.
+//! A trailing `@context` (or `@"context with spaces"`) sets the context. The trailing `{iN}` is the item's anchor (its
+//! internal ID), which is how edited lines are matched back to the items they came from: lines without one become new
+//! items, and items whose lines were removed are deleted. Descriptions and tags aren't shown, and are kept as they were.
+//!
+//! Names that would be read back differently (like ones ending in something that looks like a context or an anchor)
+//! are written as JSON strings, e.g. `[ ] "email @bob" {i3}`.
+//!
+//! Blank lines and lines starting with `#` are ignored.
+
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+
+use crate::item::{Item, ItemState};
+
+/// How many spaces are used for each level of indentation when rendering.
+const SPACES_PER_INDENT: usize = 2;
+
+/// The comment placed at the top of the rendered text.
+const HEADER: &str = "\
+# Edit the items below; each line is an item, and children are indented by two spaces.
+# Markers: [ ] todo, [x] done, [-] note. A trailing @context sets the context.
+# Names in \"double quotes\" are read as JSON strings, which keeps an @ or {iN} at their end as part of the name.
+# Keep the {iN} anchors: lines without one become new items, and removed lines delete their items.
+";
+
+/// An item as read from a line of text.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Node {
+    /// The line the item was on (starting from 1), for error messages.
+    pub line: usize,
+    /// The internal ID of the item this line came from, if any.
+    pub anchor: Option<u32>,
+    pub state: ItemState,
+    pub name: String,
+    pub context: Option<String>,
+    pub children: Vec<Node>,
+}
+
+/// Renders `items` (and their children) into the outline format, including a header explaining it.
+pub fn render(items: &[Item]) -> String {
+    fn travel(items: &[Item], depth: usize, out: &mut String) {
+        for item in items {
+            let marker = match item.state {
+                ItemState::Todo => "[ ]",
+                ItemState::Done => "[x]",
+                ItemState::Note => "[-]",
+            };
+
+            let mut suffix = String::new();
+
+            if let Some(context) = item.context() {
+                if context.contains(|c: char| c.is_whitespace() || c == '"') {
+                    write!(suffix, " @\"{}\"", context).unwrap();
+                } else {
+                    write!(suffix, " @{}", context).unwrap();
+                }
+            }
+
+            write!(suffix, " {{i{}}}", item.internal_id).unwrap();
+
+            // quote the name if it wouldn't be read back as-is
+            let plain = format!("{} {}{}", marker, item.name, suffix);
+            let name = match parse_line(&plain, 0) {
+                Ok(node) if node.name == item.name && !item.name.contains(char::is_control) => {
+                    Cow::Borrowed(item.name.as_str())
+                }
+                _ => Cow::Owned(serde_json::to_string(&item.name).unwrap()),
+            };
+
+            writeln!(
+                out,
+                "{:indent$}{} {}{}",
+                "",
+                marker,
+                name,
+                suffix,
+                indent = depth * SPACES_PER_INDENT
+            )
+            .unwrap();
+
+            travel(&item.children, depth + 1, out);
+        }
+    }
+
+    let mut out = String::from(HEADER);
+    travel(items, 0, &mut out);
+    out
+}
+
+/// Parses text in the outline format into a tree of nodes.
+pub fn parse(text: &str) -> Result<Vec<Node>, String> {
+    // each entry is an indentation level and the nodes found on it so far; the last node of an entry is the parent of
+    // the nodes on the next one
+    let mut stack: Vec<(usize, Vec<Node>)> = vec![(0, Vec::new())];
+
+    for (index, raw_line) in text.lines().enumerate() {
+        let number = index + 1;
+        let content = raw_line.trim_start_matches(' ');
+
+        if content.trim().is_empty() || content.starts_with('#') {
+            continue;
+        }
+
+        if content.starts_with('\t') {
+            return Err(format!(
+                "line {}: use spaces for indentation, not tabs",
+                number
+            ));
+        }
+
+        let indent = raw_line.len() - content.len();
+        let node = parse_line(content, number)?;
+
+        // close every level deeper than this line
+        let mut closed = false;
+        while stack.len() > 1 && indent < stack.last().unwrap().0 {
+            fold_last_level(&mut stack);
+            closed = true;
+        }
+
+        let is_root = stack.len() == 1;
+        let (level_indent, nodes) = stack.last_mut().unwrap();
+
+        if indent == *level_indent || (is_root && nodes.is_empty()) {
+            *level_indent = indent;
+            nodes.push(node);
+        } else if indent > *level_indent && !closed {
+            if nodes.is_empty() {
+                return Err(format!(
+                    "line {}: indented, but there's no item above it",
+                    number
+                ));
+            }
+
+            stack.push((indent, vec![node]));
+        } else {
+            return Err(format!(
+                "line {}: indentation doesn't match any of the lines above it",
+                number
+            ));
+        }
+    }
+
+    while stack.len() > 1 {
+        fold_last_level(&mut stack);
+    }
+
+    Ok(stack.pop().unwrap().1)
+}
+
+/// Moves the nodes of the deepest level of `stack` to the children of the last node of the level above it.
+fn fold_last_level(stack: &mut Vec<(usize, Vec<Node>)>) {
+    let (_, children) = stack.pop().unwrap();
+    let (_, parents) = stack.last_mut().unwrap();
+    parents.last_mut().unwrap().children = children;
+}
+
+/// Parses a single line, without its indentation.
+fn parse_line(content: &str, number: usize) -> Result<Node, String> {
+    let mut rest = content.trim_end();
+
+    let state = match rest.get(..3) {
+        Some("[ ]") => Some(ItemState::Todo),
+        Some("[x]") | Some("[X]") => Some(ItemState::Done),
+        Some("[-]") => Some(ItemState::Note),
+        Some(other) if other.starts_with('[') && other.ends_with(']') => {
+            return Err(format!(
+                "line {}: unknown marker {:?} (expected [ ], [x] or [-])",
+                number, other
+            ))
+        }
+        _ => None,
+    };
+
+    if state.is_some() {
+        rest = rest[3..].trim_start();
+    }
+
+    let mut anchor = None;
+    let is_anchor = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+    if let Some(start) = rest
+        .rfind("{i")
+        .filter(|&start| rest.ends_with('}') && is_anchor(&rest[start + 2..rest.len() - 1]))
+    {
+        let id = rest[start + 2..rest.len() - 1]
+            .parse()
+            .map_err(|_| format!("line {}: invalid anchor {:?}", number, &rest[start..]))?;
+
+        anchor = Some(id);
+        rest = rest[..start].trim_end();
+    }
+
+    let mut context = None;
+    if rest.ends_with('"') {
+        if let Some(start) = rest.rfind(" @\"").filter(|&start| start + 3 < rest.len()) {
+            context = Some(rest[start + 3..rest.len() - 1].to_string());
+            rest = rest[..start].trim_end();
+        }
+    } else if let Some(start) = rest.rfind(" @") {
+        let word = &rest[start + 2..];
+
+        if !word.is_empty() && !word.contains(char::is_whitespace) {
+            context = Some(word.to_string());
+            rest = rest[..start].trim_end();
+        }
+    }
+
+    if rest.is_empty() {
+        return Err(format!("line {}: the item has no name", number));
+    }
+
+    // a quoted name is taken as-is; anything else that starts with a quote is a plain name
+    let name = match rest.starts_with('"') {
+        true => serde_json::from_str(rest).unwrap_or_else(|_| rest.to_string()),
+        false => rest.to_string(),
+    };
+
+    Ok(Node {
+        line: number,
+        anchor,
+        state: state.unwrap_or(ItemState::Todo),
+        name,
+        context,
+        children: Vec::new(),
+    })
+}
+
+/// Builds a new version of `items` from the edited nodes.
+///
+/// Anchored nodes keep everything from the item they came from (description, tags, IDs) other than what the format
+/// shows; the item's modification time is only updated if one of those changed. Unanchored nodes become new items.
+/// Items whose anchors aren't on `nodes` are dropped. New and un-finished items get reference IDs not used by any of
+/// the kept items.
+pub fn reconcile(items: Vec<Item>, nodes: Vec<Node>) -> Result<Vec<Item>, String> {
+    fn flatten(items: Vec<Item>, map: &mut HashMap<u32, Item>) {
+        for mut item in items {
+            let children = std::mem::take(&mut item.children);
+            map.insert(item.internal_id, item);
+            flatten(children, map);
+        }
+    }
+
+    fn build(
+        nodes: Vec<Node>,
+        old: &mut HashMap<u32, Item>,
+        internal_ids: &mut HashSet<u32>,
+        used_anchors: &mut HashSet<u32>,
+    ) -> Result<Vec<Item>, String> {
+        let mut items = Vec::with_capacity(nodes.len());
+
+        for node in nodes {
+            let context = node.context.as_deref().unwrap_or("");
+
+            let mut item = match node.anchor {
+                Some(anchor) => {
+                    if !used_anchors.insert(anchor) {
+                        return Err(format!(
+                            "line {}: anchor {{i{}}} is used more than once",
+                            node.line, anchor
+                        ));
+                    }
+
+                    let mut item = old.remove(&anchor).ok_or_else(|| {
+                        format!("line {}: unknown anchor {{i{}}}", node.line, anchor)
+                    })?;
+
+                    if item.name != node.name {
+                        item.set_name(&node.name);
+                    }
+
+                    if item.context() != node.context.as_deref() {
+                        item.set_context(context);
+                    }
+
+                    if item.state != node.state {
+                        item.set_state(node.state);
+                    }
+
+                    item
+                }
+                None => {
+                    let id = utils::misc::find_highest_free_value(internal_ids);
+                    internal_ids.insert(id);
+
+                    Item::new(
+                        None,
+                        id,
+                        &node.name,
+                        context,
+                        node.state,
+                        String::new(),
+                        Vec::new(),
+                    )
+                }
+            };
+
+            item.children = build(node.children, old, internal_ids, used_anchors)?;
+            items.push(item);
+        }
+
+        Ok(items)
+    }
+
+    let mut old = HashMap::new();
+    flatten(items, &mut old);
+
+    let mut internal_ids: HashSet<u32> = old.keys().copied().collect();
+    let mut new_items = build(nodes, &mut old, &mut internal_ids, &mut HashSet::new())?;
+
+    // only now it's known which reference IDs are still in use
+    let mut ref_ids = HashSet::new();
+    utils::data::tree::walk(&new_items, &mut |item: &Item, _| {
+        if let Some(id) = item.ref_id {
+            ref_ids.insert(id);
+        }
+    });
+
+    let mut missing = 0;
+    utils::data::tree::walk(&new_items, &mut |item: &Item, _| {
+        if item.ref_id.is_none() && item.state != ItemState::Done {
+            missing += 1;
+        }
+    });
+
+    let mut free_ids = utils::misc::find_free_values(&ref_ids, missing).into_iter();
+    utils::data::tree::walk_mut(&mut new_items, &mut |item: &mut Item, _| {
+        if item.ref_id.is_none() && item.state != ItemState::Done {
+            item.ref_id = free_ids.next();
+        }
+    });
+
+    Ok(new_items)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(
+        ref_id: Option<u32>,
+        internal_id: u32,
+        name: &str,
+        ctx: &str,
+        state: ItemState,
+        children: Vec<Item>,
+    ) -> Item {
+        Item::new(
+            ref_id,
+            internal_id,
+            name,
+            ctx,
+            state,
+            String::new(),
+            children,
+        )
+    }
+
+    fn sample() -> Vec<Item> {
+        let mut parent = item(
+            Some(0),
+            0,
+            "parent",
+            "work",
+            ItemState::Todo,
+            vec![
+                item(None, 1, "finished", "", ItemState::Done, Vec::new()),
+                item(
+                    Some(1),
+                    2,
+                    "a note",
+                    "two words",
+                    ItemState::Note,
+                    Vec::new(),
+                ),
+            ],
+        );
+        parent.description = "kept".into();
+
+        vec![
+            parent,
+            item(Some(2), 3, "other", "", ItemState::Todo, Vec::new()),
+        ]
+    }
+
+    fn names(items: &[Item]) -> Vec<(usize, u32, String)> {
+        let mut names = Vec::new();
+        utils::data::tree::walk(items, &mut |item: &Item, depth| {
+            names.push((depth, item.internal_id, item.name.clone()))
+        });
+        names
+    }
+
+    #[test]
+    fn render_and_parse_round_trip() {
+        let text = render(&sample());
+
+        assert!(text.ends_with(
+            "[ ] parent @work {i0}\n  [x] finished {i1}\n  [-] a note @\"two words\" {i2}\n[ ] other {i3}\n"
+        ));
+
+        let nodes = parse(&text).unwrap();
+        assert_eq!(nodes.len(), 2);
+        assert_eq!(nodes[0].children[1].context.as_deref(), Some("two words"));
+        assert_eq!(nodes[0].children[1].line, 7);
+
+        let items = reconcile(sample(), nodes).unwrap();
+        assert!(crate::diff::diff(&sample(), &items).is_empty());
+        assert_eq!(items[0].description, "kept");
+    }
+
+    #[test]
+    fn tricky_names_round_trip() {
+        let tricky = [
+            "email @bob",
+            "quote @\"someone\"",
+            "see {i2}",
+            "\"quoted\" from the start",
+            "\"fully quoted\"",
+            "  spaces around  ",
+            "[x] not a marker",
+            "two\nlines",
+            "",
+        ];
+
+        let items: Vec<Item> = tricky
+            .iter()
+            .enumerate()
+            .map(|(i, name)| {
+                let ctx = if i % 2 == 0 { "" } else { "work" };
+                item(
+                    Some(i as u32),
+                    i as u32,
+                    name,
+                    ctx,
+                    ItemState::Todo,
+                    Vec::new(),
+                )
+            })
+            .collect();
+
+        let text = render(&items);
+        assert!(text.contains("[ ] \"email @bob\" {i0}\n"));
+        assert!(text.contains("[ ] [x] not a marker {i6}\n"));
+
+        let reconciled = reconcile(items.clone(), parse(&text).unwrap()).unwrap();
+        assert!(crate::diff::diff(&items, &reconciled).is_empty());
+        assert_eq!(names(&reconciled), names(&items));
+    }
+
+    #[test]
+    fn parse_lines() {
+        let nodes =
+            parse("# comment\n\nplain name\n  [X] done @ctx {i7}\n      deep\n  back @ not {it}\n")
+                .unwrap();
+
+        assert_eq!(
+            nodes,
+            vec![Node {
+                line: 3,
+                anchor: None,
+                state: ItemState::Todo,
+                name: "plain name".into(),
+                context: None,
+                children: vec![
+                    Node {
+                        line: 4,
+                        anchor: Some(7),
+                        state: ItemState::Done,
+                        name: "done".into(),
+                        context: Some("ctx".into()),
+                        children: vec![Node {
+                            line: 5,
+                            anchor: None,
+                            state: ItemState::Todo,
+                            name: "deep".into(),
+                            context: None,
+                            children: Vec::new(),
+                        }],
+                    },
+                    Node {
+                        line: 6,
+                        anchor: None,
+                        state: ItemState::Todo,
+                        name: "back @ not {it}".into(),
+                        context: None,
+                        children: Vec::new(),
+                    },
+                ],
+            }]
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        let cases = [
+            ("  [ ] a\n    b\n   c\n", "line 3: indentation"),
+            ("a\n\tb\n", "line 2: use spaces"),
+            ("[?] a\n", "line 1: unknown marker"),
+            ("[ ] {i3}\n", "line 1: the item has no name"),
+            ("a {i99999999999}\n", "line 1: invalid anchor"),
+        ];
+
+        for (text, expected) in cases.iter() {
+            match parse(text) {
+                Err(e) => assert!(e.starts_with(expected), "{:?} gave {:?}", text, e),
+                Ok(nodes) => panic!("{:?} should fail, got {:?}", text, nodes),
+            }
+        }
+    }
+
+    #[test]
+    fn reconcile_edits() {
+        // rename and finish `other`, move the note to the root, add an item and drop `finished`
+        let text = "[ ] parent @work {i0}\n  new child @home\n[-] a note {i2}\n[x] renamed {i3}\n";
+        let items = reconcile(sample(), parse(text).unwrap()).unwrap();
+
+        assert_eq!(
+            names(&items),
+            vec![
+                (0, 0, "parent".into()),
+                (1, 4, "new child".into()),
+                (0, 2, "a note".into()),
+                (0, 3, "renamed".into()),
+            ]
+        );
+        assert_eq!(items[0].children[0].context(), Some("home"));
+        assert_eq!(items[1].context(), None);
+        assert_eq!(items[2].ref_id, None);
+        // the new item takes the first reference ID that isn't used anymore
+        assert_eq!(items[0].children[0].ref_id, Some(2));
+
+        let changes = crate::diff::diff(&sample(), &items);
+        assert!(changes
+            .iter()
+            .any(|c| matches!(c, crate::diff::Change::Removed { id, .. } if id.0 == 1)));
+    }
+
+    #[test]
+    fn reconcile_errors() {
+        assert_eq!(
+            reconcile(sample(), parse("a {i9}\n").unwrap()).unwrap_err(),
+            "line 1: unknown anchor {i9}"
+        );
+        assert_eq!(
+            reconcile(sample(), parse("a {i0}\nb {i0}\n").unwrap()).unwrap_err(),
+            "line 2: anchor {i0} is used more than once"
+        );
+    }
+}
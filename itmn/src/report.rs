@@ -2,11 +2,15 @@
 
 // TODO: add a way to recursively sort items, like what was done with filters.
 
-use crate::item::{Item, ItemState};
+use crate::item::{Item, ItemState, RefId};
 
+use utils::data::tree;
+use utils::misc::IdBase;
+
+use std::borrow::Cow;
+use std::collections::HashMap;
 use std::io;
 use std::io::Write;
-use std::borrow::Cow;
 
 #[derive(Clone, Copy)]
 /// Specifies the way the items should be shown on the screen.
@@ -17,6 +21,8 @@ pub enum ReportDepth {
     Brief,
     /// Show all children of an item.
     Tree,
+    /// Only show the item itself, with how many children it has after its name.
+    Collapsed,
 }
 
 // #[derive(Clone, Copy)]
@@ -31,18 +37,208 @@ pub enum ReportDepth {
 //     }
 // }
 
+/// How the children of an item are indented on reports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndentStyle {
+    /// This many spaces per level.
+    Spaces(usize),
+    /// A tab per level.
+    Tabs,
+    /// Box-drawing guides connecting each item to its parent, like `tree(1)`.
+    Guides,
+}
+
+impl IndentStyle {
+    /// Parses an indent style: `spaces` (two per level), a number of spaces, `tabs` or `guides`.
+    pub fn parse(string: &str) -> Result<Self, String> {
+        match string.trim().to_lowercase().as_str() {
+            "spaces" => Ok(Self::Spaces(2)),
+            "tabs" => Ok(Self::Tabs),
+            "guides" => Ok(Self::Guides),
+            other => other.parse().map(Self::Spaces).map_err(|_| {
+                format!(
+                    "unknown indent style {:?} (expected spaces, a number of spaces, tabs or guides)",
+                    other
+                )
+            }),
+        }
+    }
+}
+
+/// The ANSI foreground color codes contexts can be shown in, along with their names.
+const CONTEXT_COLORS: &[(&str, u8)] = &[
+    ("red", 31),
+    ("green", 32),
+    ("yellow", 33),
+    ("blue", 34),
+    ("magenta", 35),
+    ("cyan", 36),
+];
+
+/// Decides which color each context is shown in. Contexts without an explicit color get one picked by a hash of their
+/// name (see [`hashed_color`]), so they're always shown the same way.
+///
+/// [`hashed_color`]: hashed_color
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ContextColors {
+    /// The explicitly chosen ANSI color codes, by context.
+    pub explicit: HashMap<String, u8>,
+}
+
+impl ContextColors {
+    /// Parses a mapping like `work=blue,home=green`. The colors are red, green, yellow, blue, magenta and cyan.
+    pub fn parse(string: &str) -> Result<Self, String> {
+        let mut explicit = HashMap::new();
+
+        for pair in string.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let (context, color) = pair
+                .split_once('=')
+                .ok_or_else(|| format!("expected context=color, got {:?}", pair))?;
+            let color = color.trim().to_lowercase();
+
+            let code = CONTEXT_COLORS
+                .iter()
+                .find(|(name, _)| *name == color)
+                .map(|&(_, code)| code)
+                .ok_or_else(|| format!("unknown color {:?}", color))?;
+
+            explicit.insert(context.trim().trim_start_matches('@').into(), code);
+        }
+
+        Ok(Self { explicit })
+    }
+
+    /// Returns the ANSI color code `context` is shown in.
+    pub fn color_of(&self, context: &str) -> u8 {
+        self.explicit
+            .get(context)
+            .copied()
+            .unwrap_or_else(|| hashed_color(context))
+    }
+}
+
+/// Picks one of the context colors from a hash of `context` (FNV-1a, which unlike the standard library's hasher is
+/// the same everywhere).
+pub fn hashed_color(context: &str) -> u8 {
+    let hash = context.bytes().fold(0x811c_9dc5u32, |hash, byte| {
+        (hash ^ byte as u32).wrapping_mul(0x0100_0193)
+    });
+
+    CONTEXT_COLORS[hash as usize % CONTEXT_COLORS.len()].1
+}
+
 /// Stores settings for the report displaying.
 #[derive(Clone)]
 pub struct ReportConfig {
-    /// The amount of spaces used per indent.
-    pub spaces_per_indent: usize,
+    /// How children are indented.
+    pub indent_style: IndentStyle,
+    /// If the children of each item should be shown in reverse order.
+    pub reverse_children: bool,
+    /// If the internal ID should be shown even when the item has a reference ID. Useful for debugging.
+    pub show_internal: bool,
+    /// If the report headers should be left out, so only the items are written.
+    pub plain: bool,
+    /// The base the IDs are shown in.
+    pub id_base: IdBase,
+    /// If item annotations should be shown next to their names.
+    pub show_annotations: bool,
+    /// The colors contexts are shown in, if they should be colored at all. Plain reports are never colored.
+    pub context_colors: Option<ContextColors>,
+}
+
+impl Default for ReportConfig {
+    /// Two-space indentation, decimal IDs and annotations shown, without colors or anything else.
+    fn default() -> Self {
+        Self {
+            indent_style: IndentStyle::Spaces(2),
+            reverse_children: false,
+            show_internal: false,
+            plain: false,
+            id_base: IdBase::Decimal,
+            show_annotations: true,
+            context_colors: None,
+        }
+    }
 }
 
 impl ReportConfig {
-    pub fn get_indent_spaces(&self, indent: usize) -> String {
-        std::iter::repeat(' ')
-            .take(self.spaces_per_indent * indent)
-            .collect()
+    /// Returns the indentation of the line of the item being displayed with `info`.
+    pub fn item_indent(&self, info: &ReportInfo) -> String {
+        self.indent_string(info, true)
+    }
+
+    /// Returns the indentation of the lines under the item being displayed with `info` (like its description), which
+    /// only continue the guides of its ancestors.
+    pub fn continuation_indent(&self, info: &ReportInfo) -> String {
+        self.indent_string(info, false)
+    }
+
+    fn indent_string(&self, info: &ReportInfo, item_line: bool) -> String {
+        match self.indent_style {
+            IndentStyle::Spaces(spaces) => " ".repeat(spaces * info.indent),
+            IndentStyle::Tabs => "\t".repeat(info.indent),
+            IndentStyle::Guides => (0..info.indent)
+                .map(|level| {
+                    let last = info.last_children.get(level).copied().unwrap_or(false);
+
+                    match (item_line && level + 1 == info.indent, last) {
+                        (true, false) => "\u{251C}\u{2500} ",
+                        (true, true) => "\u{2514}\u{2500} ",
+                        (false, false) => "\u{2502}  ",
+                        (false, true) => "   ",
+                    }
+                })
+                .collect(),
+        }
+    }
+
+    /// Returns the ID representation of an item (e.g. `#04`, `i12`, or `#04/i12` if `show_internal` is set), in the
+    /// configured base. Virtual items (see [`VIRTUAL_ID`]) have none.
+    ///
+    /// [`VIRTUAL_ID`]: VIRTUAL_ID
+    pub fn id_repr(&self, item: &Item) -> String {
+        let base = self.id_base;
+
+        match item.ref_id {
+            None if item.internal_id == VIRTUAL_ID => String::new(),
+            Some(id) if self.show_internal => format!(
+                "#{}/i{}",
+                base.format_id(id),
+                base.format_id(item.internal_id)
+            ),
+            Some(id) => format!("#{}", base.format_id(id)),
+            None => format!("i{}", base.format_id(item.internal_id)),
+        }
+    }
+
+    /// Returns a context as it's shown on reports (`@ctx`), colored if context colors are enabled.
+    pub fn context_repr(&self, context: &str) -> String {
+        match self.context_colors {
+            Some(ref colors) if !self.plain => {
+                format!("\x1b[{}m@{}\x1b[0m", colors.color_of(context), context)
+            }
+            _ => format!("@{}", context),
+        }
+    }
+
+    /// Returns the name of an item as it's shown on reports: followed by its annotation in parentheses, if it has one
+    /// and annotations are being shown.
+    pub fn name_repr<'a>(&self, item: &'a Item) -> Cow<'a, str> {
+        match item.annotation() {
+            Some(annotation) if self.show_annotations => {
+                format!("{} ({})", item.name, annotation).into()
+            }
+            _ => item.name.as_str().into(),
+        }
+    }
+
+    /// Returns an iterator over the children of `item`, in the order they should be displayed.
+    pub fn children<'a>(&self, item: &'a Item) -> Box<dyn Iterator<Item = &'a Item> + 'a> {
+        if self.reverse_children {
+            Box::new(item.children.iter().rev())
+        } else {
+            Box::new(item.children.iter())
+        }
     }
 }
 
@@ -52,6 +248,11 @@ pub struct ReportInfo<'a> {
     pub config: &'a ReportConfig,
     /// The indent level. Not the same as the final indent, or the amount of spaces per indent.
     pub indent: usize,
+    /// For each indent level under the surface, whether the item displayed there is the last of its siblings, which
+    /// decides the glyphs of [`IndentStyle::Guides`].
+    ///
+    /// [`IndentStyle::Guides`]: IndentStyle::Guides
+    last_children: Vec<bool>,
     /// The filter that the items must go through to be printed, if any.
     pub filter: Option<&'a dyn Fn(&Item) -> bool>,
     /// The depth that the item displaying must go through.
@@ -59,6 +260,157 @@ pub struct ReportInfo<'a> {
     // pub sort: SortOption,
 }
 
+impl<'a> ReportInfo<'a> {
+    /// Makes the info for a report of surface items (indent level 0) with `config`, going through `depth` and only
+    /// showing the items that pass `filter`, if any.
+    pub fn new(
+        config: &'a ReportConfig,
+        depth: ReportDepth,
+        filter: Option<&'a dyn Fn(&Item) -> bool>,
+    ) -> Self {
+        Self {
+            config,
+            indent: 0,
+            last_children: Vec::new(),
+            filter,
+            depth,
+        }
+    }
+}
+
+/// The internal ID of the virtual items made only for displaying, like the context headers of [`context_tree`]. They
+/// aren't on the database, so their IDs aren't shown.
+///
+/// [`context_tree`]: context_tree
+pub const VIRTUAL_ID: u32 = u32::MAX;
+
+/// Groups `items` under virtual items (see [`VIRTUAL_ID`]) named after their contexts, so they can be shown as a single
+/// tree. The contexts are in the order they first appear, with the items without context last.
+///
+/// If `filter` is given, the contexts where none of the items pass it are left out, so their headers aren't shown on
+/// their own.
+///
+/// The items are cloned, so nothing done with the result affects the database.
+///
+/// [`VIRTUAL_ID`]: VIRTUAL_ID
+pub fn context_tree<'a, I>(items: I, filter: Option<&dyn Fn(&Item) -> bool>) -> Vec<Item>
+where
+    I: IntoIterator<Item = &'a Item>,
+{
+    let mut groups: Vec<(Option<&str>, Vec<Item>)> = Vec::new();
+
+    for item in items {
+        match groups.iter_mut().find(|(ctx, _)| *ctx == item.context()) {
+            Some((_, children)) => children.push(item.clone()),
+            None => groups.push((item.context(), vec![item.clone()])),
+        }
+    }
+
+    if let Some(filter) = filter {
+        groups.retain(|(_, children)| children.iter().any(filter));
+    }
+
+    // the sort is stable, so the contexts keep their order
+    groups.sort_by_key(|(ctx, _)| ctx.is_none());
+
+    groups
+        .into_iter()
+        .map(|(ctx, children)| {
+            let name = match ctx {
+                Some(ctx) => format!("@{}", ctx),
+                None => "(no context)".into(),
+            };
+
+            Item::new(
+                None,
+                VIRTUAL_ID,
+                &name,
+                "",
+                ItemState::Note,
+                String::new(),
+                children,
+            )
+        })
+        .collect()
+}
+
+/// Builds a one-line summary of how many items there are per context (e.g. `work:5 home:2 (no ctx):3`), sorted by
+/// count. Only items that pass `filter` (if any) are counted.
+pub fn context_summary<'a, I>(items: I, filter: Option<&dyn Fn(&Item) -> bool>) -> String
+where
+    I: IntoIterator<Item = &'a Item>,
+{
+    let mut counts: Vec<(Option<&str>, usize)> = Vec::new();
+
+    for item in items {
+        if !filter.is_none_or(|f| f(item)) {
+            continue;
+        }
+
+        match counts.iter_mut().find(|(ctx, _)| *ctx == item.context()) {
+            Some((_, count)) => *count += 1,
+            None => counts.push((item.context(), 1)),
+        }
+    }
+
+    // most items first, with the items without context at the end
+    counts.sort_by(|(ctx_a, a), (ctx_b, b)| {
+        ctx_a
+            .is_none()
+            .cmp(&ctx_b.is_none())
+            .then(b.cmp(a))
+            .then(ctx_a.cmp(ctx_b))
+    });
+
+    counts
+        .iter()
+        .map(|(ctx, count)| format!("{}:{}", ctx.unwrap_or("(no ctx)"), count))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// How many items there are on each state, counting the whole tree (children of done items included).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct StateCounts {
+    pub todo: usize,
+    pub done: usize,
+    pub notes: usize,
+}
+
+impl StateCounts {
+    /// Counts the states of `items` and all of their descendants, in a single walk.
+    pub fn count(items: &[Item]) -> Self {
+        let mut counts = Self::default();
+
+        tree::walk(items, &mut |item: &Item, _| match item.state {
+            ItemState::Todo => counts.todo += 1,
+            ItemState::Done => counts.done += 1,
+            ItemState::Note => counts.notes += 1,
+        });
+
+        counts
+    }
+
+    /// The amount of actionable items (everything but notes).
+    pub fn total(&self) -> usize {
+        self.todo + self.done
+    }
+
+    /// Fills in `template`, replacing `{open}`, `{done}`, `{notes}` and `{total}` with their counts.
+    pub fn render(&self, template: &str) -> String {
+        template
+            .replace("{open}", &self.todo.to_string())
+            .replace("{done}", &self.done.to_string())
+            .replace("{notes}", &self.notes.to_string())
+            .replace("{total}", &self.total().to_string())
+    }
+}
+
+/// Returns the tags of an item as they're shown on reports (e.g. `#urgent #later `), or an empty string.
+fn tags_repr(item: &Item) -> String {
+    item.tags().iter().map(|tag| format!("#{} ", tag)).collect()
+}
+
 pub trait Report {
     fn display(item: &Item, info: &ReportInfo, out: &mut dyn Write) -> io::Result<()>;
 
@@ -68,21 +420,43 @@ pub trait Report {
         out: &mut dyn Write,
     ) -> io::Result<()>;
 
+    /// Writes the header of a report, saying how many (`count`) items are going to be displayed.
+    fn header(label: &str, count: usize, out: &mut dyn Write) -> io::Result<()> {
+        let length_message: Cow<'_, str> = match count {
+            0 => Cow::Borrowed("No items to be displayed"),
+            1 => Cow::Borrowed("1 item to be displayed"),
+            i => Cow::Owned(format!("{} items to be displayed", i)),
+        };
+
+        writeln!(out, "{} | {}", label, length_message)
+    }
+
+    /// Writes the header and then all of the items. The header counts the items that pass the filter, so it matches
+    /// what's shown; virtual items (like the headers of [`context_tree`]) count the items under them instead.
+    ///
+    /// [`context_tree`]: context_tree
     fn report(
         label: &str,
         items: &mut dyn Iterator<Item = &Item>,
         info: &ReportInfo,
         out: &mut dyn Write,
     ) -> io::Result<()> {
-        let length_message: Cow<'_, str> = match items.size_hint().0 {
-            0 | 1 => Cow::Borrowed("No items to be displayed"),
-            2 => Cow::Borrowed("1 item to be displayed"),
-            i => Cow::Owned(format!("{} items to be displayed", i - 1)),
-        };
+        let items: Vec<&Item> = items.collect();
 
-        writeln!(out, "{} | {}", label, length_message)?;
+        if !info.config.plain {
+            let shown = |item: &&Item| info.filter.is_none_or(|f| f(item));
+            let count = items
+                .iter()
+                .map(|item| match item.internal_id {
+                    VIRTUAL_ID => item.children.iter().filter(shown).count(),
+                    _ => shown(item) as usize,
+                })
+                .sum();
 
-        Self::display_all(items, info, out)
+            Self::header(label, count, out)?;
+        }
+
+        Self::display_all(&mut items.into_iter(), info, out)
     }
 }
 
@@ -90,85 +464,102 @@ pub struct BasicReport;
 impl Report for BasicReport {
     fn display(item: &Item, info: &ReportInfo, out: &mut dyn Write) -> io::Result<()> {
         let proceed = |out: &mut dyn Write| -> io::Result<()> {
-            writeln!(
-                out,
-                "{indent}{state} {text} {context}{id_repr}{flags}",
-                indent = info.config.get_indent_spaces(info.indent),
+            let line = format!(
+                "{indent}{state} {text} {context}{tags}{id_repr}{flags}",
+                indent = info.config.item_indent(info),
                 state = match item.state {
                     ItemState::Todo => "o",
                     ItemState::Done => "x",
                     ItemState::Note => "-",
                 },
                 context = match item.context() {
-                    Some(ctx) => format!("@{} ", ctx),
+                    Some(ctx) => format!("{} ", info.config.context_repr(ctx)),
                     None => String::new(),
                 },
-                text = item.name,
-                id_repr = match item.ref_id {
-                    Some(id) => format!("#{:>02}", id),
-                    None => format!("i{:>02}", item.internal_id),
+                tags = tags_repr(item),
+                text = match (info.depth, item.children.len()) {
+                    (ReportDepth::Collapsed, 1) => {
+                        Cow::Owned(format!("{} [1 child]", info.config.name_repr(item)))
+                    }
+                    (ReportDepth::Collapsed, n) if n > 1 => {
+                        Cow::Owned(format!("{} [{} children]", info.config.name_repr(item), n))
+                    }
+                    _ => info.config.name_repr(item),
                 },
+                id_repr = info.config.id_repr(item),
                 flags = "",
                 // flags = match item.description.is_empty() {
                 //     true => "",
                 //     false => " (D)",
                 // },
-            )?;
+            );
+
+            // virtual items have no ID, which would leave a trailing space
+            writeln!(out, "{}", line.trim_end())?;
 
             if !item.description.trim().is_empty() {
                 const MAX_SIZE: usize = 35;
                 let trimmed_desc = item.description.trim();
+                let first_line = trimmed_desc.lines().next().unwrap_or("").trim();
 
-                let maxpoint = if let Some((i, _)) = trimmed_desc
-                    .chars()
-                    .enumerate()
-                    .take(MAX_SIZE)
-                    .find(|(_, c)| matches!(c, '\n'))
-                {
-                    i
-                } else {
-                    trimmed_desc.len().min(MAX_SIZE + 1)
-                };
+                let shown = utils::text::truncate(first_line, MAX_SIZE);
 
                 writeln!(
                     out,
                     "{indent}  :: {description}{trail}",
-                    indent = info.config.get_indent_spaces(info.indent),
-                    description = &trimmed_desc[..maxpoint].trim(),
-                    trail = if trimmed_desc.len() != maxpoint {
-                        "..."
-                    } else {
-                        ""
+                    indent = info.config.continuation_indent(info),
+                    description = shown,
+                    // the truncation already adds an ellipsis
+                    trail = match shown {
+                        Cow::Borrowed(_) if first_line.len() != trimmed_desc.len() => {
+                            utils::text::ELLIPSIS
+                        }
+                        _ => "",
                     }
                 )
                 .unwrap();
             }
 
             match info.depth {
-                ReportDepth::Shallow => (),
+                ReportDepth::Shallow | ReportDepth::Collapsed => (),
                 ReportDepth::Brief => {
                     let mut info = info.clone();
                     info.indent += 1;
                     info.depth = ReportDepth::Shallow;
+                    info.last_children.push(item.children.len() == 1);
 
-                    if item.children.len() > 0 {
-                        Self::display(&item.children[0], &info, out)?;
+                    if let Some(child) = info.config.children(item).next() {
+                        Self::display(child, &info, out)?;
 
                         if item.children.len() > 1 {
+                            // shown as the last line under the item
+                            *info.last_children.last_mut().unwrap() = true;
+
                             writeln!(
                                 out,
                                 "{}  {} more...",
-                                info.config.get_indent_spaces(info.indent),
+                                info.config.item_indent(&info),
                                 item.children.len() - 1
                             )?;
                         }
                     }
                 }
                 ReportDepth::Tree => {
+                    // the last child is the last one that passes the filter, so the guides end on it
+                    let children: Vec<&Item> = info
+                        .config
+                        .children(item)
+                        .filter(|child| info.filter.is_none_or(|f| f(child)))
+                        .collect();
+
                     let mut info = info.clone();
                     info.indent += 1;
+                    info.last_children.push(false);
 
-                    Self::display_all(&mut item.children.iter(), &info, out)?;
+                    for (i, child) in children.iter().enumerate() {
+                        *info.last_children.last_mut().unwrap() = i + 1 == children.len();
+                        Self::display(child, &info, out)?;
+                    }
                 }
             }
 
@@ -200,42 +591,102 @@ impl Report for BasicReport {
 }
 
 pub struct FlatReport;
-impl Report for FlatReport {
-    fn display(item: &Item, info: &ReportInfo, out: &mut dyn Write) -> io::Result<()> {
-        let proceed = |out: &mut dyn Write| -> io::Result<()> {
-            writeln!(
-                out,
-                "{state} {text} {context}{id_repr}{flags}",
-                state = match item.state {
-                    ItemState::Todo => "o",
-                    ItemState::Done => "x",
-                    ItemState::Note => "-",
-                },
-                context = match item.context() {
-                    Some(ctx) => format!("@{} ", ctx),
-                    None => String::new(),
-                },
-                text = item.name,
-                id_repr = match item.ref_id {
-                    Some(id) => format!("#{:>02}", id),
-                    None => format!("i{:>02}", item.internal_id),
-                },
-                flags = "",
-            )?;
 
-            let mut info = info.clone();
-            info.indent += 1;
-            Self::display_all(&mut item.children.iter(), &info, out)?;
-
-            Ok(())
+impl FlatReport {
+    /// Writes a single line for `item`, prepended by its flat number (if any) padded to `number_width`.
+    fn display_line(
+        item: &Item,
+        number: Option<usize>,
+        number_width: usize,
+        info: &ReportInfo,
+        out: &mut dyn Write,
+    ) -> io::Result<()> {
+        let number = match number {
+            Some(n) => format!("{:>width$} ", n, width = number_width),
+            None if number_width > 0 => " ".repeat(number_width + 1),
+            None => String::new(),
         };
 
-        if let Some(filter) = info.filter {
-            if filter(item) {
-                proceed(out)?;
+        writeln!(
+            out,
+            "{number}{state} {text} {context}{tags}{id_repr}",
+            number = number,
+            state = match item.state {
+                ItemState::Todo => "o",
+                ItemState::Done => "x",
+                ItemState::Note => "-",
+            },
+            context = match item.context() {
+                Some(ctx) => format!("@{} ", ctx),
+                None => String::new(),
+            },
+            text = info.config.name_repr(item),
+            tags = tags_repr(item),
+            id_repr = info.config.id_repr(item),
+        )
+    }
+
+    /// Collects the items (and their children, recursively) in the order they're shown on a flat report, skipping the
+    /// ones that don't pass the filter (along with their children).
+    pub fn flatten<'a>(
+        items: &mut dyn Iterator<Item = &'a Item>,
+        info: &ReportInfo,
+    ) -> Vec<&'a Item> {
+        fn inner<'a>(
+            items: &mut dyn Iterator<Item = &'a Item>,
+            info: &ReportInfo,
+            result: &mut Vec<&'a Item>,
+        ) {
+            for item in items {
+                if info.filter.is_none_or(|f| f(item)) {
+                    result.push(item);
+                    inner(&mut info.config.children(item), info, result);
+                }
             }
-        } else {
-            proceed(out)?;
+        }
+
+        let mut result = Vec::new();
+        inner(items, info, &mut result);
+        result
+    }
+
+    /// Like [`Report::report`], but numbering every item that has a reference ID with a contiguous number, starting at
+    /// 1. Returns the reference IDs of the numbered items, in order.
+    ///
+    /// [`Report::report`]: Report::report
+    pub fn report_numbered(
+        label: &str,
+        items: &mut dyn Iterator<Item = &Item>,
+        info: &ReportInfo,
+        out: &mut dyn Write,
+    ) -> io::Result<Vec<RefId>> {
+        let flat = Self::flatten(items, info);
+
+        if !info.config.plain {
+            Self::header(label, flat.len(), out)?;
+        }
+
+        let numbered = flat.iter().filter(|item| item.ref_id.is_some()).count();
+        let number_width = numbered.to_string().len();
+        let mut ref_ids = Vec::with_capacity(numbered);
+
+        for item in flat {
+            let number = item.ref_id.map(|id| {
+                ref_ids.push(RefId(id));
+                ref_ids.len()
+            });
+
+            Self::display_line(item, number, number_width, info, out)?;
+        }
+
+        Ok(ref_ids)
+    }
+}
+
+impl Report for FlatReport {
+    fn display(item: &Item, info: &ReportInfo, out: &mut dyn Write) -> io::Result<()> {
+        for item in Self::flatten(&mut std::iter::once(item), info) {
+            Self::display_line(item, None, 0, info, out)?;
         }
 
         Ok(())
@@ -253,3 +704,426 @@ impl Report for FlatReport {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn annotations_toggle() {
+        let mut item = Item::new(
+            Some(1),
+            1,
+            "Task",
+            "",
+            ItemState::Todo,
+            String::new(),
+            Vec::new(),
+        );
+        item.set_annotation("blocked");
+
+        let mut config = ReportConfig::default();
+        assert_eq!(config.name_repr(&item), "Task (blocked)");
+
+        config.show_annotations = false;
+        assert_eq!(config.name_repr(&item), "Task");
+    }
+
+    #[test]
+    fn context_colors_are_stable() {
+        let hashed = ContextColors::default();
+        assert_eq!(hashed.color_of("work"), hashed.color_of("work"));
+        assert_eq!(hashed.color_of("work"), hashed_color("work"));
+
+        let colors = ContextColors::parse("work=blue, @home=Green").unwrap();
+        assert_eq!(colors.color_of("work"), 34);
+        assert_eq!(colors.color_of("home"), 32);
+        assert_eq!(colors.color_of("gym"), hashed_color("gym"));
+        assert!(ContextColors::parse("work=purple").is_err());
+        assert!(ContextColors::parse("work").is_err());
+
+        let mut config = ReportConfig {
+            context_colors: Some(colors),
+            ..Default::default()
+        };
+        assert_eq!(config.context_repr("work"), "\x1b[34m@work\x1b[0m");
+
+        config.plain = true;
+        assert_eq!(config.context_repr("work"), "@work");
+    }
+
+    #[test]
+    fn context_tree_hides_empty_contexts() {
+        let item = |id, ctx: &str, state| {
+            Item::new(Some(id), id, "T", ctx, state, String::new(), Vec::new())
+        };
+        let items = vec![
+            item(0, "work", ItemState::Todo),
+            item(1, "home", ItemState::Note),
+            item(2, "work", ItemState::Note),
+        ];
+        let names = |tree: Vec<Item>| -> Vec<String> {
+            tree.iter()
+                .map(|header| header.name().to_string())
+                .collect()
+        };
+
+        let open_only = |i: &Item| i.state == ItemState::Todo;
+        assert_eq!(names(context_tree(&items, Some(&open_only))), ["@work"]);
+        assert_eq!(names(context_tree(&items, None)), ["@work", "@home"]);
+    }
+
+    #[test]
+    fn state_counts() {
+        let leaf = |state| Item::new(None, 0, "", "", state, String::new(), Vec::new());
+        let mut done = leaf(ItemState::Done);
+        done.children = vec![leaf(ItemState::Todo), leaf(ItemState::Note)];
+        let items = vec![leaf(ItemState::Todo), done, leaf(ItemState::Note)];
+
+        let counts = StateCounts::count(&items);
+        assert_eq!(
+            counts,
+            StateCounts {
+                todo: 2,
+                done: 1,
+                notes: 2
+            }
+        );
+        assert_eq!(counts.render("{open}/{total}"), "2/3");
+        assert_eq!(
+            counts.render("{done} done, {notes} notes"),
+            "1 done, 2 notes"
+        );
+        assert_eq!(StateCounts::count(&[]).render("{open}/{total}"), "0/0");
+    }
+
+    fn render(show_internal: bool, id_base: IdBase) -> String {
+        let item = Item::new(
+            Some(4),
+            12,
+            "Task",
+            "",
+            ItemState::Todo,
+            String::new(),
+            Vec::new(),
+        );
+        let config = ReportConfig {
+            show_internal,
+            id_base,
+            ..Default::default()
+        };
+
+        let mut out = Vec::new();
+        BasicReport::display(
+            &item,
+            &ReportInfo::new(&config, ReportDepth::Shallow, None),
+            &mut out,
+        )
+        .unwrap();
+
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn summary_by_context() {
+        let item = |ctx: &str, state| Item::new(None, 0, "", ctx, state, String::new(), Vec::new());
+        let items = vec![
+            item("home", ItemState::Todo),
+            item("", ItemState::Todo),
+            item("work", ItemState::Todo),
+            item("work", ItemState::Note),
+            item("work", ItemState::Done),
+        ];
+
+        assert_eq!(
+            context_summary(&items, Some(&|i: &Item| i.state != ItemState::Done)),
+            "work:2 home:1 (no ctx):1"
+        );
+    }
+
+    #[test]
+    fn context_tree_groups_clones() {
+        let item = |id, ctx: &str| {
+            Item::new(
+                Some(id),
+                id,
+                "T",
+                ctx,
+                ItemState::Todo,
+                String::new(),
+                Vec::new(),
+            )
+        };
+        let items = vec![
+            item(0, ""),
+            item(1, "work"),
+            item(2, "home"),
+            item(3, "work"),
+        ];
+
+        let tree = context_tree(&items, None);
+        let groups: Vec<(&str, Vec<u32>)> = tree
+            .iter()
+            .map(|header| {
+                (
+                    header.name(),
+                    header.children.iter().map(|i| i.internal_id).collect(),
+                )
+            })
+            .collect();
+
+        assert_eq!(
+            groups,
+            vec![
+                ("@work", vec![1, 3]),
+                ("@home", vec![2]),
+                ("(no context)", vec![0])
+            ]
+        );
+
+        let config = ReportConfig {
+            show_internal: true,
+            ..Default::default()
+        };
+        let mut out = Vec::new();
+        BasicReport::display(
+            &tree[1],
+            &ReportInfo::new(&config, ReportDepth::Tree, None),
+            &mut out,
+        )
+        .unwrap();
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "- @home\n  o T @home #02/i02\n"
+        );
+    }
+
+    #[test]
+    fn collapsed_shows_child_counts() {
+        let item = |id, children| {
+            Item::new(
+                Some(id),
+                id,
+                "T",
+                "",
+                ItemState::Todo,
+                String::new(),
+                children,
+            )
+        };
+        let items = [
+            item(
+                0,
+                vec![item(1, Vec::new()), item(2, vec![item(3, Vec::new())])],
+            ),
+            item(4, vec![item(5, Vec::new())]),
+            item(6, Vec::new()),
+        ];
+        let config = ReportConfig {
+            plain: true,
+            ..Default::default()
+        };
+
+        let mut out = Vec::new();
+        BasicReport::report(
+            "Collapsed",
+            &mut items.iter(),
+            &ReportInfo::new(&config, ReportDepth::Collapsed, None),
+            &mut out,
+        )
+        .unwrap();
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "o T [2 children] #00\no T [1 child] #04\no T #06\n"
+        );
+    }
+
+    fn render_tree(indent_style: IndentStyle) -> String {
+        let item = |id, name, children| {
+            Item::new(
+                Some(id),
+                id,
+                name,
+                "",
+                ItemState::Todo,
+                String::new(),
+                children,
+            )
+        };
+        let items = [item(
+            0,
+            "A",
+            vec![
+                item(1, "B", vec![item(2, "C", Vec::new())]),
+                item(3, "D", Vec::new()),
+            ],
+        )];
+        let config = ReportConfig {
+            indent_style,
+            plain: true,
+            ..Default::default()
+        };
+
+        let mut out = Vec::new();
+        BasicReport::report(
+            "Tree",
+            &mut items.iter(),
+            &ReportInfo::new(&config, ReportDepth::Tree, None),
+            &mut out,
+        )
+        .unwrap();
+
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn indent_styles() {
+        assert_eq!(
+            render_tree(IndentStyle::Spaces(2)),
+            "o A #00\n  o B #01\n    o C #02\n  o D #03\n"
+        );
+        assert_eq!(
+            render_tree(IndentStyle::Tabs),
+            "o A #00\n\to B #01\n\t\to C #02\n\to D #03\n"
+        );
+        assert_eq!(
+            render_tree(IndentStyle::Guides),
+            "o A #00\n├─ o B #01\n│  └─ o C #02\n└─ o D #03\n"
+        );
+    }
+
+    #[test]
+    fn parse_indent_style() {
+        assert_eq!(IndentStyle::parse("spaces"), Ok(IndentStyle::Spaces(2)));
+        assert_eq!(IndentStyle::parse("4"), Ok(IndentStyle::Spaces(4)));
+        assert_eq!(IndentStyle::parse("Tabs"), Ok(IndentStyle::Tabs));
+        assert_eq!(IndentStyle::parse("guides"), Ok(IndentStyle::Guides));
+        assert!(IndentStyle::parse("dots").is_err());
+    }
+
+    #[test]
+    fn flat_numbering_skips_items_without_ref_id() {
+        let item = |ref_id, internal_id, state, children| {
+            Item::new(ref_id, internal_id, "T", "", state, String::new(), children)
+        };
+        let items = [
+            item(
+                Some(3),
+                0,
+                ItemState::Todo,
+                vec![
+                    item(None, 1, ItemState::Note, Vec::new()),
+                    item(
+                        None,
+                        2,
+                        ItemState::Done,
+                        vec![item(None, 3, ItemState::Todo, Vec::new())],
+                    ),
+                    item(Some(0), 4, ItemState::Todo, Vec::new()),
+                ],
+            ),
+            item(Some(1), 5, ItemState::Note, Vec::new()),
+        ];
+        let config = ReportConfig::default();
+
+        let mut out = Vec::new();
+        let ref_ids = FlatReport::report_numbered(
+            "Flat",
+            &mut items.iter(),
+            &ReportInfo::new(
+                &config,
+                ReportDepth::Tree,
+                Some(&|i: &Item| i.state != ItemState::Done),
+            ),
+            &mut out,
+        )
+        .unwrap();
+
+        assert_eq!(ref_ids, vec![RefId(3), RefId(0), RefId(1)]);
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "Flat | 4 items to be displayed\n1 o T #03\n  - T i01\n2 o T #00\n3 - T #01\n"
+        );
+    }
+
+    #[test]
+    fn header_counts_exactly() {
+        let item = |id| {
+            Item::new(
+                Some(id),
+                id,
+                "T",
+                "",
+                ItemState::Todo,
+                String::new(),
+                Vec::new(),
+            )
+        };
+        let items = [item(0), item(1), item(2)];
+        let config = ReportConfig::default();
+
+        let mut out = Vec::new();
+        BasicReport::report(
+            "Label",
+            &mut items.iter(),
+            &ReportInfo::new(&config, ReportDepth::Shallow, None),
+            &mut out,
+        )
+        .unwrap();
+
+        let out = String::from_utf8(out).unwrap();
+        assert_eq!(out.lines().next(), Some("Label | 3 items to be displayed"));
+        assert_eq!(out.lines().count(), 4);
+
+        // only the items that pass the filter are counted, and the context headers count the items under them
+        let filter = |i: &Item| i.internal_id == VIRTUAL_ID || i.internal_id != 1;
+        let headers = context_tree(&items, None);
+        let mut out = Vec::new();
+        for group in [&items[..], &headers[..]] {
+            BasicReport::report(
+                "Label",
+                &mut group.iter(),
+                &ReportInfo::new(&config, ReportDepth::Shallow, Some(&filter)),
+                &mut out,
+            )
+            .unwrap();
+        }
+
+        let out = String::from_utf8(out).unwrap();
+        let headers: Vec<&str> = out.lines().filter(|l| l.starts_with("Label")).collect();
+        assert_eq!(headers, ["Label | 2 items to be displayed"; 2]);
+    }
+
+    #[test]
+    fn plain_report_has_no_header() {
+        let items = [Item::new(
+            Some(0),
+            0,
+            "T",
+            "",
+            ItemState::Todo,
+            String::new(),
+            Vec::new(),
+        )];
+        let config = ReportConfig {
+            plain: true,
+            ..Default::default()
+        };
+        let info = ReportInfo::new(&config, ReportDepth::Shallow, None);
+
+        let mut out = Vec::new();
+        BasicReport::report("Label", &mut items.iter(), &info, &mut out).unwrap();
+        FlatReport::report_numbered("Flat", &mut items.iter(), &info, &mut out).unwrap();
+
+        assert_eq!(String::from_utf8(out).unwrap(), "o T #00\n1 o T #00\n");
+    }
+
+    #[test]
+    fn id_representation() {
+        assert_eq!(render(false, IdBase::Decimal), "o Task #04\n");
+        assert_eq!(render(true, IdBase::Decimal), "o Task #04/i12\n");
+        assert_eq!(render(true, IdBase::Hex), "o Task #0x04/i0x0c\n");
+    }
+}
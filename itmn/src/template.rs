@@ -0,0 +1,169 @@
+//! Stores data structures related to item templates, which are predefined item trees that can be expanded when adding
+//! an item.
+
+use serde::{Deserialize, Serialize};
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use crate::item::{ItemState, RefId};
+use crate::manager::ItemManager;
+
+/// A skeleton of an item, as stored on the templates file. IDs are not stored here, since they're allocated when the
+/// template is expanded.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct TemplateItem {
+    /// The name of the item.
+    pub name: String,
+    /// The context of the item, if any.
+    #[serde(default)]
+    pub context: String,
+    /// If the item is a note.
+    #[serde(default)]
+    pub note: bool,
+    /// The description of the item.
+    #[serde(default)]
+    pub description: String,
+    /// The children of this item, if any.
+    #[serde(default)]
+    pub children: Vec<TemplateItem>,
+}
+
+/// A collection of templates, indexed by their names.
+pub struct Templates {
+    map: BTreeMap<String, Vec<TemplateItem>>,
+}
+
+impl Templates {
+    /// Creates an empty collection of templates.
+    pub fn empty() -> Self {
+        Self {
+            map: BTreeMap::new(),
+        }
+    }
+
+    /// Returns the default path of the templates file (`$XDG_CONFIG_HOME/itmn/templates.json`, falling back to
+    /// `~/.config/itmn/templates.json`).
+    pub fn default_path() -> PathBuf {
+        utils::paths::config_dir()
+            .join("itmn")
+            .join("templates.json")
+    }
+
+    /// Parses the templates from a json string.
+    pub fn parse(string: &str) -> Result<Self, String> {
+        match serde_json::from_str(string) {
+            Ok(map) => Ok(Self { map }),
+            Err(e) => Err(format!("failed to parse templates: {}", e)),
+        }
+    }
+
+    /// Loads the templates from the default path. A missing file is treated as an empty collection.
+    pub fn load() -> Result<Self, String> {
+        let path = Self::default_path();
+
+        if !path.exists() {
+            return Ok(Self::empty());
+        }
+
+        match std::fs::read_to_string(&path) {
+            Ok(contents) => Self::parse(&contents),
+            Err(e) => Err(format!("failed to read {}: {}", path.display(), e)),
+        }
+    }
+
+    /// Gets a template by its name, returning an error listing the available templates if it doesn't exist.
+    pub fn get(&self, name: &str) -> Result<&[TemplateItem], String> {
+        match self.map.get(name) {
+            Some(items) => Ok(items),
+            None if self.map.is_empty() => Err(format!(
+                "unknown template {:?} (no templates available at {})",
+                name,
+                Self::default_path().display()
+            )),
+            None => Err(format!(
+                "unknown template {:?} (available: {})",
+                name,
+                self.map
+                    .keys()
+                    .map(String::as_str)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )),
+        }
+    }
+}
+
+/// Returns the directory where description templates are stored (`$XDG_CONFIG_HOME/itmn/descriptions`). Each template
+/// is a markdown file, named after the template (e.g. `meeting.md`).
+pub fn descriptions_dir() -> PathBuf {
+    utils::paths::config_dir().join("itmn").join("descriptions")
+}
+
+/// Loads a description template by its name, returning an error listing the available templates if it doesn't exist.
+pub fn load_description(name: &str) -> Result<String, String> {
+    let dir = descriptions_dir();
+
+    if !name.is_empty() && !name.contains(['/', '\\']) {
+        let path = dir.join(format!("{}.md", name));
+
+        if path.is_file() {
+            return std::fs::read_to_string(&path)
+                .map_err(|e| format!("failed to read {}: {}", path.display(), e));
+        }
+    }
+
+    let mut available: Vec<String> = std::fs::read_dir(&dir)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            match path.extension() {
+                Some(ext) if ext == "md" => Some(path.file_stem()?.to_string_lossy().into_owned()),
+                _ => None,
+            }
+        })
+        .collect();
+    available.sort();
+
+    if available.is_empty() {
+        Err(format!(
+            "unknown description template {:?} (no templates available at {})",
+            name,
+            dir.display()
+        ))
+    } else {
+        Err(format!(
+            "unknown description template {:?} (available: {})",
+            name,
+            available.join(", ")
+        ))
+    }
+}
+
+/// Expands `template` as children of the item with reference ID `parent`, allocating fresh IDs for each new item.
+pub fn instantiate(
+    manager: &mut ItemManager,
+    parent: RefId,
+    template: &[TemplateItem],
+) -> Result<(), ()> {
+    for titem in template {
+        let child = manager.add_child(
+            parent,
+            &titem.name,
+            &titem.context,
+            if titem.note {
+                ItemState::Note
+            } else {
+                ItemState::Todo
+            },
+            titem.description.clone(),
+            Vec::new(), // children are added below, so they get their own IDs
+            None,
+        )?;
+
+        instantiate(manager, child, &titem.children)?;
+    }
+
+    Ok(())
+}
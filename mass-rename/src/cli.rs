@@ -38,6 +38,9 @@ pub struct PreOptions {
     )]
     pub full_path: Option<bool>,
 
+    #[arg(short = 'V', long, help = "print version and build information")]
+    pub version: bool,
+
     // TODO: keep failed list at /tmp (or prompt to re-edit again)
 }
 
@@ -16,7 +16,14 @@ const PREFIX_LINES: &[&str] = &[
 ];
 
 fn main() -> ExitCode {
-    let _options = cli::PreOptions::parse().process();
+    let pre_options = cli::PreOptions::parse();
+
+    if pre_options.version {
+        utils::version::print_version("mass-rename", env!("CARGO_PKG_VERSION"));
+        return ExitCode::SUCCESS;
+    }
+
+    let _options = pre_options.process();
 
     // decide upon what to rename
     panic!();
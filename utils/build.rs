@@ -0,0 +1,33 @@
+//! Collects build information (like the git commit) for the `version` module.
+
+use std::process::Command;
+
+/// Runs git with `args`, returning its trimmed output if it succeeded.
+fn git(args: &[&str]) -> Option<String> {
+    let output = Command::new("git").args(args).output().ok()?;
+
+    if output.status.success() {
+        Some(String::from_utf8(output.stdout).ok()?.trim().to_string())
+    } else {
+        None
+    }
+}
+
+fn main() {
+    if let Some(hash) = git(&["rev-parse", "--short", "HEAD"]) {
+        println!("cargo:rustc-env=COMPSCRIPTS_GIT_HASH={}", hash);
+    }
+
+    if let Ok(profile) = std::env::var("PROFILE") {
+        println!("cargo:rustc-env=COMPSCRIPTS_BUILD_PROFILE={}", profile);
+    }
+
+    // rebuild when the checked out commit changes
+    if let Some(git_dir) = git(&["rev-parse", "--absolute-git-dir"]) {
+        println!("cargo:rerun-if-changed={}/HEAD", git_dir);
+
+        if let Some(head_ref) = git(&["symbolic-ref", "-q", "HEAD"]) {
+            println!("cargo:rerun-if-changed={}/{}", git_dir, head_ref);
+        }
+    }
+}
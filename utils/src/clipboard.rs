@@ -0,0 +1,70 @@
+//! Copying text to the system clipboard, through whichever clipboard tool is installed.
+
+use crate::proc::RunError;
+
+/// Copies `text` to the clipboard.
+///
+/// The tools are tried in order: `pbcopy` on macOS, `wl-copy` on Wayland sessions, and then `xclip` and `xsel`. A tool
+/// that isn't installed is skipped, but one that fails isn't, since it probably means there's no clipboard to copy to.
+pub fn copy(text: &str) -> Result<(), String> {
+    let tools = tools_with(&|name| std::env::var(name).ok().filter(|var| !var.is_empty()));
+
+    for (cmd, args) in &tools {
+        match crate::proc::run(cmd, args, Some(text)) {
+            Ok(0) => return Ok(()),
+            Ok(code) => return Err(format!("{} failed with exit code {}", cmd, code)),
+            Err(e @ RunError::NotFound(_)) => crate::debug!("{}", e),
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    Err(format!(
+        "no clipboard tool found (tried {})",
+        tools
+            .iter()
+            .map(|(cmd, _)| *cmd)
+            .collect::<Vec<_>>()
+            .join(", ")
+    ))
+}
+
+/// Returns the clipboard tools to try, along with their arguments, in order.
+fn tools_with(
+    getvar: &dyn Fn(&str) -> Option<String>,
+) -> Vec<(&'static str, &'static [&'static str])> {
+    let mut tools: Vec<(&str, &[&str])> = Vec::new();
+
+    if cfg!(target_os = "macos") {
+        tools.push(("pbcopy", &[]));
+    }
+
+    if getvar("WAYLAND_DISPLAY").is_some() {
+        tools.push(("wl-copy", &[]));
+    }
+
+    tools.push(("xclip", &["-selection", "clipboard"]));
+    tools.push(("xsel", &["--clipboard", "--input"]));
+
+    tools
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(not(target_os = "macos"))]
+    #[test]
+    fn wayland_is_tried_first() {
+        let names = |getvar: &dyn Fn(&str) -> Option<String>| -> Vec<&str> {
+            tools_with(getvar).iter().map(|(cmd, _)| *cmd).collect()
+        };
+
+        assert_eq!(names(&|_| None), vec!["xclip", "xsel"]);
+        assert_eq!(
+            names(&|name| Some(name)
+                .filter(|&n| n == "WAYLAND_DISPLAY")
+                .map(String::from)),
+            vec!["wl-copy", "xclip", "xsel"]
+        );
+    }
+}
@@ -59,13 +59,227 @@ pub trait Manager {
         Some(result)
     }
 
+    /// Removes an item by its reference ID, returning it.
+    fn remove(&mut self, ref_id: Id) -> Option<Self::Data> {
+        let index = self
+            .data()
+            .iter()
+            .position(|i| i.ref_id() == Some(ref_id))?;
+
+        let removed = self.data_mut().remove(index);
+        self.after_interact_mut_hook();
+        Some(removed)
+    }
+
+    /// Swaps the positions of two items by their reference IDs. Returns `false` (without changing anything) if any of
+    /// them doesn't exist.
+    fn swap(&mut self, ref_id_1: Id, ref_id_2: Id) -> bool {
+        let position = |ref_id| {
+            self.data()
+                .iter()
+                .position(|i: &Self::Data| i.ref_id() == Some(ref_id))
+        };
+
+        match (position(ref_id_1), position(ref_id_2)) {
+            (Some(a), Some(b)) => {
+                self.data_mut().swap(a, b);
+                self.after_interact_mut_hook();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns how many items there are.
+    fn count(&self) -> usize {
+        self.data().len()
+    }
+
+    /// Returns up to `limit` items, starting from the one at `offset`. The window is clamped to the data, so an offset
+    /// past the end gives an empty slice.
+    fn page(&self, offset: usize, limit: usize) -> &[Self::Data] {
+        let data = self.data();
+        let start = offset.min(data.len());
+        let end = start.saturating_add(limit).min(data.len());
+
+        &data[start..end]
+    }
+
+    /// Sorts the items by their [`Ord`] implementation (usually by ID), calling the hook only if the order changed.
+    ///
+    /// [`Ord`]: std::cmp::Ord
+    fn sort(&mut self) {
+        if self.data().windows(2).any(|pair| pair[0] > pair[1]) {
+            self.data_mut().sort();
+            self.after_interact_mut_hook();
+        }
+    }
+
     /// A hook that is ran after a mutable interaction is made.
     fn after_interact_mut_hook(&mut self);
 }
 
+/// A trait that describes data that can own other data of the same type, forming a tree.
+pub trait HasChildren: Sized {
+    /// Get an immutable reference to the children of this item.
+    fn children(&self) -> &[Self];
+
+    /// Get a mutable reference to the children of this item.
+    fn children_mut(&mut self) -> &mut Vec<Self>;
+}
+
+/// Generic recursive operations over slices of [`HasChildren`] data.
+///
+/// All searches are depth-first and pre-order: an item is checked before its children, and its children before its
+/// next siblings.
+///
+/// [`HasChildren`]: HasChildren
+pub mod tree {
+    use super::HasChildren;
+
+    /// Find the first item that matches `predicate`, returning an immutable reference to it.
+    pub fn find<'a, T, P>(items: &'a [T], predicate: &P) -> Option<&'a T>
+    where
+        T: HasChildren,
+        P: Fn(&T) -> bool,
+    {
+        for item in items {
+            if predicate(item) {
+                return Some(item);
+            }
+
+            if let Some(found) = find(item.children(), predicate) {
+                return Some(found);
+            }
+        }
+
+        None
+    }
+
+    /// Find the first item that matches `predicate`, returning a mutable reference to it.
+    pub fn find_mut<'a, T, P>(items: &'a mut [T], predicate: &P) -> Option<&'a mut T>
+    where
+        T: HasChildren,
+        P: Fn(&T) -> bool,
+    {
+        for item in items {
+            if predicate(item) {
+                return Some(item);
+            }
+
+            if let Some(found) = find_mut(item.children_mut(), predicate) {
+                return Some(found);
+            }
+        }
+
+        None
+    }
+
+    /// Run `f` on every item, passing along its depth (0 for the items on `items` itself).
+    pub fn walk<'a, T, F>(items: &'a [T], f: &mut F)
+    where
+        T: HasChildren,
+        F: FnMut(&'a T, usize),
+    {
+        fn inner<'a, T, F>(items: &'a [T], f: &mut F, depth: usize)
+        where
+            T: HasChildren,
+            F: FnMut(&'a T, usize),
+        {
+            for item in items {
+                f(item, depth);
+                inner(item.children(), f, depth + 1);
+            }
+        }
+
+        inner(items, f, 0)
+    }
+
+    /// Run `f` on every item, passing along its depth, possibly mutating it.
+    pub fn walk_mut<T, F>(items: &mut [T], f: &mut F)
+    where
+        T: HasChildren,
+        F: FnMut(&mut T, usize),
+    {
+        fn inner<T, F>(items: &mut [T], f: &mut F, depth: usize)
+        where
+            T: HasChildren,
+            F: FnMut(&mut T, usize),
+        {
+            for item in items {
+                f(item, depth);
+                inner(item.children_mut(), f, depth + 1);
+            }
+        }
+
+        inner(items, f, 0)
+    }
+
+    /// Remove the first item that matches `predicate` (along with its children), returning it.
+    pub fn remove<T, P>(items: &mut Vec<T>, predicate: &P) -> Option<T>
+    where
+        T: HasChildren,
+        P: Fn(&T) -> bool,
+    {
+        for i in 0..items.len() {
+            if predicate(&items[i]) {
+                return Some(items.remove(i));
+            }
+
+            if let Some(removed) = remove(items[i].children_mut(), predicate) {
+                return Some(removed);
+            }
+        }
+
+        None
+    }
+}
+
+/// A trait for managing tree-like data, where each item might have children of its own.
+///
+/// This is the recursive counterpart of [`Manager`], which only handles flat data.
+///
+/// [`Manager`]: Manager
+pub trait TreeManager {
+    /// The data type used on this manager.
+    type Data: HasChildren;
+
+    /// Get an immutable reference to the root items of the tree.
+    fn roots(&self) -> &[Self::Data];
+
+    /// Get a mutable reference to the root items of the tree.
+    fn roots_mut(&mut self) -> &mut Vec<Self::Data>;
+
+    /// Recursively find the first item that matches `predicate` and return an immutable reference to it.
+    fn find_where<P: Fn(&Self::Data) -> bool>(&self, predicate: P) -> Option<&Self::Data> {
+        tree::find(self.roots(), &predicate)
+    }
+
+    /// Recursively find the first item that matches `predicate` and return a mutable reference to it.
+    fn find_where_mut<P: Fn(&Self::Data) -> bool>(
+        &mut self,
+        predicate: P,
+    ) -> Option<&mut Self::Data> {
+        tree::find_mut(self.roots_mut(), &predicate)
+    }
+
+    /// Run `f` on every item of the tree, passing along its depth.
+    fn walk<'a, F: FnMut(&'a Self::Data, usize)>(&'a self, mut f: F) {
+        tree::walk(self.roots(), &mut f)
+    }
+
+    /// Recursively find the first item that matches `predicate` and remove it (along with its children) from the
+    /// tree.
+    fn remove_where<P: Fn(&Self::Data) -> bool>(&mut self, predicate: P) -> Option<Self::Data> {
+        tree::remove(self.roots_mut(), &predicate)
+    }
+}
+
 pub mod data_serialize {
     use std::path::Path;
 
+    use serde::de::DeserializeOwned;
+
     use super::{Deserialize, JsonError, Serialize};
 
     pub enum SaveToFileError {
@@ -102,17 +316,65 @@ pub mod data_serialize {
         }
     }
 
-    /// Export a T slice into a json string and then save it into a file.
+    /// Import a vector of T from a string with one json object per line, ignoring blank lines. Errors say which line
+    /// (counting from 1) couldn't be parsed.
+    pub fn import_lines<'a, T: Deserialize<'a>>(string: &'a str) -> Result<Vec<T>, String> {
+        string
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(i, line)| {
+                serde_json::from_str(line).map_err(|e| format!("line {}: {}", i + 1, e))
+            })
+            .collect()
+    }
+
+    /// Export a T slice into a string with one json object per line.
+    pub fn export_lines<T: Serialize>(data: &[T]) -> serde_json::Result<String> {
+        let mut string = String::new();
+
+        for item in data {
+            string.push_str(&serde_json::to_string(item)?);
+            string.push('\n');
+        }
+
+        Ok(string)
+    }
+
+    /// Import a vector of T from a json file, decompressing it first if the path ends in `.gz`.
+    pub fn import_from_file<T: DeserializeOwned>(file: &Path) -> Result<Vec<T>, String> {
+        let contents = crate::io::touch_read(file)?;
+        serde_json::from_str(super::empty_or(&contents, "[]"))
+            .map_err(|e| format!("failed to parse file: {}", e))
+    }
+
+    /// Makes `string` end with exactly one newline if `trailing_newline` is set, so saved files don't flip-flop in
+    /// diffs against editors that add one. Otherwise, leaves it as it is.
+    pub fn fix_trailing_newline(mut string: String, trailing_newline: bool) -> String {
+        if trailing_newline {
+            string.truncate(string.trim_end_matches('\n').len());
+            string.push('\n');
+        }
+
+        string
+    }
+
+    /// Export a T slice into a json string and then save it into a file, compressing it if the path ends in `.gz`.
+    ///
+    /// See [`fix_trailing_newline`] for what `trailing_newline` does.
     pub fn save_to_file<'a, T>(
         data: &'a [T],
         file: &'a Path,
         prettified: bool,
+        trailing_newline: bool,
     ) -> Result<(), SaveToFileError>
     where
         T: Deserialize<'a> + Serialize,
     {
-        let export_string = export(data, prettified).map_err(|e| SaveToFileError::Exporting(e))?;
-        std::fs::write(file, &export_string).map_err(|e| SaveToFileError::Saving(e))?;
+        let export_string = export(data, prettified).map_err(SaveToFileError::Exporting)?;
+        let export_string = fix_trailing_newline(export_string, trailing_newline);
+        crate::io::write_maybe_gzip(file, export_string.as_bytes())
+            .map_err(SaveToFileError::Saving)?;
 
         Ok(())
     }
@@ -138,8 +400,9 @@ where
         &'a self,
         file: &'a Path,
         prettified: bool,
+        trailing_newline: bool,
     ) -> Result<(), data_serialize::SaveToFileError> {
-        data_serialize::save_to_file(self.data(), file, prettified)
+        data_serialize::save_to_file(self.data(), file, prettified, trailing_newline)
     }
 }
 
@@ -149,3 +412,318 @@ where
     <M as Manager>::Data: Deserialize<'a> + Serialize,
 {
 }
+
+/// Returns `default` if `string` is empty or only has whitespace, and `string` itself otherwise.
+///
+/// Useful for treating an empty data file as an empty list (e.g. `empty_or(contents, "[]")`).
+pub fn empty_or<'a>(string: &'a str, default: &'a str) -> &'a str {
+    if string.trim().is_empty() {
+        default
+    } else {
+        string
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn import_lines_reports_the_bad_line() {
+        let good = "[1, 2]\n\n[3]\n";
+        let imported: Result<Vec<Vec<u32>>, String> = data_serialize::import_lines(good);
+        assert_eq!(imported, Ok(vec![vec![1, 2], vec![3]]));
+        assert_eq!(
+            data_serialize::export_lines(&imported.unwrap())
+                .ok()
+                .as_deref(),
+            Some("[1,2]\n[3]\n")
+        );
+
+        // blank lines still count
+        let bad = "[1]\n\n[2, oops]\n[3]\n";
+        let error = data_serialize::import_lines::<Vec<u32>>(bad).unwrap_err();
+        assert!(error.starts_with("line 3: "), "got {:?}", error);
+    }
+
+    #[test]
+    fn empty_or_falls_back_on_blank_strings() {
+        assert_eq!(empty_or("", "[]"), "[]");
+        assert_eq!(empty_or(" \n\t\r\n", "[]"), "[]");
+        assert_eq!(empty_or(" [1]\n", "[]"), " [1]\n");
+    }
+
+    struct Node {
+        id: u32,
+        children: Vec<Node>,
+    }
+
+    impl HasChildren for Node {
+        fn children(&self) -> &[Self] {
+            &self.children
+        }
+
+        fn children_mut(&mut self) -> &mut Vec<Self> {
+            &mut self.children
+        }
+    }
+
+    fn node(id: u32, children: Vec<Node>) -> Node {
+        Node { id, children }
+    }
+
+    fn sample() -> Vec<Node> {
+        vec![
+            node(0, vec![node(1, vec![node(2, vec![])])]),
+            node(3, vec![node(4, vec![])]),
+        ]
+    }
+
+    #[test]
+    fn tree_find_and_remove() {
+        let mut items = sample();
+
+        assert_eq!(
+            tree::find(&items, &|n: &Node| n.id == 2).map(|n| n.id),
+            Some(2)
+        );
+        assert!(tree::find(&items, &|n: &Node| n.id == 5).is_none());
+
+        tree::find_mut(&mut items, &|n: &Node| n.id == 4)
+            .unwrap()
+            .id = 5;
+        let removed = tree::remove(&mut items, &|n: &Node| n.id == 1).unwrap();
+
+        assert_eq!(removed.children.len(), 1);
+        assert!(tree::find(&items, &|n: &Node| n.id == 2).is_none());
+        assert!(tree::find(&items, &|n: &Node| n.id == 5).is_some());
+
+        // pre-order: the child of an earlier sibling goes before a later root
+        let mut items = sample();
+        let removed = tree::remove(&mut items, &|n: &Node| n.id == 1 || n.id == 3).unwrap();
+        assert_eq!(removed.id, 1);
+        assert_eq!(items.len(), 2);
+    }
+
+    #[test]
+    fn tree_walk_order() {
+        let mut visited = Vec::new();
+        tree::walk(&sample(), &mut |n: &Node, depth| {
+            visited.push((n.id, depth))
+        });
+
+        assert_eq!(visited, vec![(0, 0), (1, 1), (2, 2), (3, 0), (4, 1)]);
+    }
+
+    #[derive(Debug, Deserialize, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+    struct Entry {
+        ref_id: Option<Id>,
+        value: String,
+    }
+
+    impl Searchable for Entry {
+        fn ref_id(&self) -> Option<Id> {
+            self.ref_id
+        }
+    }
+
+    fn entry(ref_id: Option<Id>, value: &str) -> Entry {
+        Entry {
+            ref_id,
+            value: value.into(),
+        }
+    }
+
+    /// A [`Manager`] over a plain vector, counting how many times the mutation hook was called.
+    ///
+    /// [`Manager`]: Manager
+    struct VecManager<T> {
+        data: Vec<T>,
+        hook_calls: usize,
+    }
+
+    impl<T> VecManager<T> {
+        fn new(data: Vec<T>) -> Self {
+            Self {
+                data,
+                hook_calls: 0,
+            }
+        }
+    }
+
+    impl<T: Searchable> Manager for VecManager<T> {
+        type Data = T;
+
+        fn data(&self) -> &[T] {
+            &self.data
+        }
+
+        fn data_mut(&mut self) -> &mut Vec<T> {
+            &mut self.data
+        }
+
+        fn after_interact_mut_hook(&mut self) {
+            self.hook_calls += 1;
+        }
+    }
+
+    fn sample_manager() -> VecManager<Entry> {
+        VecManager::new(vec![
+            entry(Some(0), "zero"),
+            entry(None, "hidden"),
+            entry(Some(2), "two"),
+        ])
+    }
+
+    #[test]
+    fn manager_find() {
+        let mut manager = sample_manager();
+
+        assert_eq!(manager.find(2).map(|e| e.value.as_str()), Some("two"));
+        assert!(manager.find(1).is_none());
+
+        manager.find_mut(0).unwrap().value = "changed".into();
+        assert_eq!(manager.data()[0].value, "changed");
+        // find_mut doesn't go through the hook
+        assert_eq!(manager.hook_calls, 0);
+    }
+
+    #[test]
+    fn manager_pages() {
+        let manager = sample_manager();
+        let values =
+            |page: &[Entry]| -> Vec<String> { page.iter().map(|e| e.value.clone()).collect() };
+
+        assert_eq!(manager.count(), 3);
+        assert_eq!(values(manager.page(0, 2)), ["zero", "hidden"]);
+        assert_eq!(values(manager.page(1, 10)), ["hidden", "two"]);
+        assert_eq!(values(manager.page(2, usize::MAX)), ["two"]);
+        assert!(manager.page(3, 1).is_empty());
+        assert!(manager.page(10, 5).is_empty());
+        assert!(manager.page(0, 0).is_empty());
+    }
+
+    #[test]
+    fn manager_interact_mut_calls_hook() {
+        let mut manager = sample_manager();
+
+        let old = manager.interact_mut(2, |e| std::mem::replace(&mut e.value, "new".into()));
+        assert_eq!(old.as_deref(), Some("two"));
+        assert_eq!(manager.hook_calls, 1);
+
+        assert!(manager.interact_mut(7, |_| ()).is_none());
+        assert_eq!(manager.hook_calls, 1);
+
+        assert_eq!(
+            manager.interact(2, |e| e.value.clone()).as_deref(),
+            Some("new")
+        );
+    }
+
+    #[test]
+    fn manager_remove_and_swap() {
+        let mut manager = sample_manager();
+
+        assert!(manager.swap(0, 2));
+        assert_eq!(manager.data()[0].value, "two");
+        assert!(!manager.swap(0, 1));
+        assert_eq!(manager.hook_calls, 1);
+
+        assert_eq!(manager.remove(0).map(|e| e.value), Some("zero".into()));
+        assert!(manager.remove(0).is_none());
+        assert_eq!(manager.data().len(), 2);
+        assert_eq!(manager.hook_calls, 2);
+
+        manager.sort();
+        assert_eq!(manager.data()[0].value, "hidden"); // `None` comes first
+        assert_eq!(manager.hook_calls, 3);
+        manager.sort();
+        assert_eq!(manager.hook_calls, 3);
+    }
+
+    #[test]
+    fn json_serializer_round_trip() {
+        let manager = sample_manager();
+
+        let json = JsonSerializer::export(&manager, false).unwrap();
+        let imported = <VecManager<Entry> as JsonSerializer>::import(&json).unwrap();
+
+        assert_eq!(imported, manager.data);
+    }
+
+    #[test]
+    fn gzip_round_trip() {
+        let path = crate::tmp::make_tmp(Some("json.gz"));
+        let data = vec![vec![1u32, 2], vec![3]];
+
+        let saved = data_serialize::save_to_file(&data, &path, false, true).is_ok();
+        let raw = std::fs::read(&path).unwrap_or_default();
+        let loaded: Result<Vec<Vec<u32>>, String> = data_serialize::import_from_file(&path);
+        let _ = std::fs::remove_file(&path);
+
+        assert!(saved);
+        assert_eq!(raw.get(..2), Some(&[0x1f, 0x8b][..])); // gzip magic number
+        assert_eq!(loaded, Ok(data));
+    }
+
+    #[test]
+    fn single_trailing_newline() {
+        for raw in &["[]", "[]\n", "[]\n\n\n"] {
+            assert_eq!(
+                data_serialize::fix_trailing_newline(raw.to_string(), true),
+                "[]\n"
+            );
+        }
+        assert_eq!(
+            data_serialize::fix_trailing_newline("[]".into(), false),
+            "[]"
+        );
+
+        let path = crate::tmp::make_tmp(Some("json"));
+        let saved = data_serialize::save_to_file(&[vec![1u32]], &path, true, true).is_ok();
+        let contents = std::fs::read_to_string(&path).unwrap_or_default();
+        let _ = std::fs::remove_file(&path);
+
+        assert!(saved);
+        assert!(contents.ends_with("]\n") && !contents.ends_with("\n\n"));
+    }
+
+    #[test]
+    fn saving_creates_parent_dirs() {
+        let root = crate::tmp::make_tmp(None);
+        let path = root.join("a/b/c/file.json");
+
+        let saved = data_serialize::save_to_file(&[1u32, 2], &path, false, true).is_ok();
+        let contents = std::fs::read_to_string(&path).unwrap_or_default();
+
+        // a file can't be a parent
+        let under_file = data_serialize::save_to_file(&[1u32], &path.join("x.json"), false, true);
+        let _ = std::fs::remove_dir_all(&root);
+
+        assert!(saved);
+        assert_eq!(contents, "[1,2]\n");
+        assert!(matches!(
+            under_file,
+            Err(data_serialize::SaveToFileError::Saving(_))
+        ));
+    }
+
+    #[test]
+    fn export_failure_is_an_error() {
+        use std::collections::HashMap;
+
+        // json keys must be strings, so this can't be serialized
+        let data = vec![HashMap::from([(vec![1u32], 0u32)])];
+        let path = crate::tmp::make_tmp(Some("json"));
+
+        let result = data_serialize::save_to_file(&data, &path, true, true);
+        let written = path.exists();
+        let _ = std::fs::remove_file(&path);
+
+        assert!(matches!(
+            result,
+            Err(data_serialize::SaveToFileError::Exporting(_))
+        ));
+        assert!(!written);
+    }
+}
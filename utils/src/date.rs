@@ -0,0 +1,152 @@
+//! Parsing of dates given on the command line, either absolute (`2021-03-04`) or relative to the current day (`+3d`,
+//! `-1w`, `yesterday` etc.).
+
+use chrono::{Days, Local, Months, NaiveDate};
+
+/// Returns the current day, on the local timezone.
+pub fn today() -> NaiveDate {
+    Local::now().date_naive()
+}
+
+/// Returns the UNIX timestamp (in seconds) of the start of `date`, on the local timezone.
+pub fn start_of_day_timestamp(date: NaiveDate) -> Option<i64> {
+    date.and_hms_opt(0, 0, 0)?
+        .and_local_timezone(Local)
+        .earliest()
+        .map(|time| time.timestamp())
+}
+
+/// Parses a date relative to `now`. Accepted formats are:
+///
+/// * `today`, `yesterday` and `tomorrow`;
+/// * an offset, made of a sign, an amount and a unit: `d` (days), `w` (weeks) or `m` (months), e.g. `+3d` or `-1m`;
+/// * an absolute ISO date, like `2021-03-04`.
+///
+/// Adding months keeps the day of the month when possible, clamping it to the last day of the month otherwise (so
+/// `+1m` from January 31st is the last day of February).
+pub fn parse_relative_date(string: &str, now: NaiveDate) -> Result<NaiveDate, String> {
+    let string = string.trim();
+
+    match string.to_lowercase().as_str() {
+        "today" => return Ok(now),
+        "yesterday" => return offset(now, false, 1, 'd', string),
+        "tomorrow" => return offset(now, true, 1, 'd', string),
+        _ => {}
+    }
+
+    let forward = match string.chars().next() {
+        Some('+') => true,
+        Some('-') => false,
+        _ => {
+            return NaiveDate::parse_from_str(string, "%Y-%m-%d").map_err(|_| {
+                format!(
+                    "invalid date {:?} (expected YYYY-MM-DD, +N/-N followed by d, w or m, today, yesterday or tomorrow)",
+                    string
+                )
+            })
+        }
+    };
+
+    let unit = string
+        .chars()
+        .last()
+        .filter(|c| c.is_ascii_alphabetic())
+        .ok_or_else(|| format!("missing unit on {:?} (expected d, w or m)", string))?;
+    let amount: u32 = string[1..string.len() - 1]
+        .parse()
+        .map_err(|_| format!("invalid amount on {:?}", string))?;
+
+    offset(now, forward, amount, unit.to_ascii_lowercase(), string)
+}
+
+/// Moves `now` by `amount` of `unit`, forward or backward. `original` is only used on error messages.
+fn offset(
+    now: NaiveDate,
+    forward: bool,
+    amount: u32,
+    unit: char,
+    original: &str,
+) -> Result<NaiveDate, String> {
+    let result = match (unit, forward) {
+        ('d', true) => now.checked_add_days(Days::new(amount.into())),
+        ('d', false) => now.checked_sub_days(Days::new(amount.into())),
+        ('w', true) => now.checked_add_days(Days::new(u64::from(amount) * 7)),
+        ('w', false) => now.checked_sub_days(Days::new(u64::from(amount) * 7)),
+        ('m', true) => now.checked_add_months(Months::new(amount)),
+        ('m', false) => now.checked_sub_months(Months::new(amount)),
+        _ => {
+            return Err(format!(
+                "unknown unit {:?} on {:?} (expected d, w or m)",
+                unit, original
+            ))
+        }
+    };
+
+    result.ok_or_else(|| format!("date {:?} is out of range", original))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    #[test]
+    fn keywords_and_absolute_dates() {
+        let now = date(2021, 3, 1);
+
+        assert_eq!(parse_relative_date("today", now), Ok(now));
+        assert_eq!(parse_relative_date("Yesterday", now), Ok(date(2021, 2, 28)));
+        assert_eq!(parse_relative_date(" tomorrow ", now), Ok(date(2021, 3, 2)));
+        assert_eq!(
+            parse_relative_date("2020-02-29", now),
+            Ok(date(2020, 2, 29))
+        );
+        assert!(parse_relative_date("2021-02-29", now).is_err());
+    }
+
+    #[test]
+    fn offsets() {
+        let now = date(2020, 12, 30);
+
+        assert_eq!(parse_relative_date("+3d", now), Ok(date(2021, 1, 2)));
+        assert_eq!(parse_relative_date("-2w", now), Ok(date(2020, 12, 16)));
+        assert_eq!(parse_relative_date("+0d", now), Ok(now));
+        assert_eq!(parse_relative_date("+2M", now), Ok(date(2021, 2, 28)));
+    }
+
+    #[test]
+    fn month_boundaries() {
+        assert_eq!(
+            parse_relative_date("+1m", date(2021, 1, 31)),
+            Ok(date(2021, 2, 28))
+        );
+        assert_eq!(
+            parse_relative_date("+1m", date(2020, 1, 31)),
+            Ok(date(2020, 2, 29))
+        );
+        assert_eq!(
+            parse_relative_date("-1m", date(2021, 3, 31)),
+            Ok(date(2021, 2, 28))
+        );
+        assert_eq!(
+            parse_relative_date("-12m", date(2021, 5, 15)),
+            Ok(date(2020, 5, 15))
+        );
+    }
+
+    #[test]
+    fn invalid_inputs() {
+        let now = date(2021, 1, 1);
+
+        for input in ["", "+", "+d", "+3", "+3y", "3d", "-x2d", "next week"].iter() {
+            assert!(
+                parse_relative_date(input, now).is_err(),
+                "{:?} should fail",
+                input
+            );
+        }
+    }
+}
@@ -119,6 +119,50 @@ impl<T> CliResult<T> {
         }
     }
 
+    /// Maps the success value with `f`, leaving an error untouched.
+    pub fn map<U, F>(self, f: F) -> CliResult<U>
+    where
+        F: FnOnce(T) -> U,
+    {
+        CliResult {
+            inner: self.inner.map(f),
+        }
+    }
+
+    /// Maps the error with `f`, leaving a success value untouched.
+    pub fn map_err<F>(self, f: F) -> Self
+    where
+        F: FnOnce(CliError) -> CliError,
+    {
+        Self {
+            inner: self.inner.map_err(f),
+        }
+    }
+
+    /// Calls `f` with the success value and returns its result. If there's an error, `f` isn't called and the error is
+    /// returned as-is.
+    pub fn and_then<U, F>(self, f: F) -> CliResult<U>
+    where
+        F: FnOnce(T) -> CliResult<U>,
+    {
+        match self.inner {
+            Ok(val) => f(val),
+            Err(err) => CliResult { inner: Err(err) },
+        }
+    }
+
+    /// Calls `f` with the error and returns its result. If there's a success value, `f` isn't called and the value is
+    /// returned as-is.
+    pub fn or_else<F>(self, f: F) -> Self
+    where
+        F: FnOnce(CliError) -> Self,
+    {
+        match self.inner {
+            Ok(val) => Self { inner: Ok(val) },
+            Err(err) => f(err),
+        }
+    }
+
     /// Process the current value and return an according exit code.
     ///
     /// Might display things to the stderr if needed.
@@ -168,3 +212,56 @@ impl<T> std::ops::Try for CliResult<T> {
         Self { inner: Ok(output) }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_silent<T>(result: &CliResult<T>) -> bool {
+        matches!(result.inner, Err(CliError::Silent))
+    }
+
+    #[test]
+    fn map_and_and_then() {
+        let result = CliResult::ok(2)
+            .map(|x| x * 10)
+            .and_then(|x| CliResult::ok(x + 1));
+
+        assert!(matches!(result.inner, Ok(21)));
+    }
+
+    #[test]
+    fn errors_short_circuit() {
+        let mut called = false;
+        let result = CliResult::<i32>::silent_err()
+            .map(|x| x * 10)
+            .and_then(|x| {
+                called = true;
+                CliResult::ok(x)
+            });
+
+        assert!(is_silent(&result));
+        assert!(!called);
+
+        let result = CliResult::ok(1)
+            .and_then(|_| CliResult::<i32>::display_err("oops"))
+            .and_then(|x| CliResult::ok(x + 1));
+
+        match result.inner {
+            Err(CliError::Display(why)) => assert_eq!(why.to_string(), "oops"),
+            _ => panic!("expected a display error"),
+        }
+    }
+
+    #[test]
+    fn or_else_and_map_err() {
+        let recovered = CliResult::<i32>::silent_err().or_else(|_| CliResult::ok(3));
+        assert!(matches!(recovered.inner, Ok(3)));
+
+        let untouched = CliResult::ok(4).or_else(|_| panic!("shouldn't be called"));
+        assert!(matches!(untouched.inner, Ok(4)));
+
+        let mapped = CliResult::<()>::display_err("loud").map_err(|_| CliError::Silent);
+        assert!(is_silent(&mapped));
+    }
+}
@@ -0,0 +1,265 @@
+//! The formats that data files (lists of records, like items or bookmarks) can be stored in.
+
+use serde::{Deserialize, Serialize};
+
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+use crate::aliases::JsonError;
+use crate::data::{data_serialize, empty_or};
+
+/// The format in which a data file is stored on disk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StorageFormat {
+    /// A single, indented JSON array containing all records. This is the default.
+    #[default]
+    Pretty,
+    /// A single JSON array on one line, which takes less space.
+    Compact,
+    /// One JSON object per line, each with a top-level record, which is friendlier to `grep` and `git diff`.
+    Lines,
+    /// A YAML sequence, which is easier to edit by hand (multi-line strings are written as block scalars).
+    Yaml,
+}
+
+/// An error while exporting data with [`StorageFormat::export`].
+#[derive(Debug)]
+pub enum ExportError {
+    Json(JsonError),
+    Yaml(serde_yaml::Error),
+}
+
+impl fmt::Display for ExportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Json(e) => write!(f, "{}", e),
+            Self::Yaml(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+/// An error while saving data with [`StorageFormat::save_to_file`].
+#[derive(Debug)]
+pub enum SaveError {
+    Exporting(ExportError),
+    Saving(io::Error),
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Exporting(e) => write!(f, "{}", e),
+            Self::Saving(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl StorageFormat {
+    /// Guesses the format of a file from its contents: an array if it starts with `[` (compact if it's on a single
+    /// line) and lines if it starts with `{`.
+    ///
+    /// Returns `None` for empty files, which could be in any format, and for anything else, which is only taken as
+    /// YAML if the extension or the user says so (otherwise a broken JSON file would be reported as broken YAML);
+    /// callers should keep whichever format they'd use otherwise.
+    pub fn detect(contents: &str) -> Option<Self> {
+        let contents = contents.trim();
+
+        match contents.chars().next() {
+            None => None,
+            Some('[') if contents.contains('\n') => Some(Self::Pretty),
+            Some('[') => Some(Self::Compact),
+            Some('{') => Some(Self::Lines),
+            Some(_) => None,
+        }
+    }
+
+    /// Gets the format implied by the extension of `path`, which is only the case for YAML (`.yaml` or `.yml`, also
+    /// before `.gz`).
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let path = match path.extension() {
+            Some(ext) if ext == "gz" => Path::new(path.file_stem()?),
+            _ => path,
+        };
+
+        match path.extension()?.to_str()?.to_lowercase().as_str() {
+            "yaml" | "yml" => Some(Self::Yaml),
+            _ => None,
+        }
+    }
+
+    /// Gets the format of the file on `path` with `contents`, from its extension if possible (see [`from_path`]), so
+    /// even empty files are recognized, or from its contents otherwise (see [`detect`]).
+    ///
+    /// [`from_path`]: StorageFormat::from_path
+    /// [`detect`]: StorageFormat::detect
+    pub fn for_file(path: &Path, contents: &str) -> Option<Self> {
+        Self::from_path(path).or_else(|| Self::detect(contents))
+    }
+
+    /// Parses a format name (`pretty`, `compact`, `lines` or `yaml`). `array` is also accepted, as another name for
+    /// `pretty`.
+    pub fn parse(string: &str) -> Result<Self, String> {
+        match string.trim().to_lowercase().as_str() {
+            "pretty" | "array" => Ok(Self::Pretty),
+            "compact" => Ok(Self::Compact),
+            "lines" => Ok(Self::Lines),
+            "yaml" | "yml" => Ok(Self::Yaml),
+            other => Err(format!(
+                "unknown storage format {:?} (expected pretty, compact, lines or yaml)",
+                other
+            )),
+        }
+    }
+
+    /// Gets the format from the environment variable `var`, if it is set and not empty.
+    pub fn from_env(var: &str) -> Result<Option<Self>, String> {
+        match std::env::var(var) {
+            Ok(var) if !var.is_empty() => Self::parse(&var).map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    /// Imports data from a string in this format. Empty strings are empty lists on every format.
+    pub fn import<'a, T: Deserialize<'a>>(self, string: &'a str) -> Result<Vec<T>, String> {
+        match self {
+            Self::Pretty | Self::Compact => {
+                serde_json::from_str(empty_or(string, "[]")).map_err(|e| e.to_string())
+            }
+            Self::Lines => data_serialize::import_lines(string),
+            Self::Yaml => serde_yaml::from_str(empty_or(string, "[]")).map_err(|e| e.to_string()),
+        }
+    }
+
+    /// Exports data into a string in this format.
+    pub fn export<T: Serialize>(self, data: &[T]) -> Result<String, ExportError> {
+        match self {
+            // an empty pretty array still spans two lines, so it isn't taken for a compact one when loaded again
+            Self::Pretty if data.is_empty() => Ok("[\n]".into()),
+            Self::Pretty => serde_json::to_string_pretty(data).map_err(ExportError::Json),
+            Self::Compact => serde_json::to_string(data).map_err(ExportError::Json),
+            Self::Lines => data_serialize::export_lines(data).map_err(ExportError::Json),
+            Self::Yaml => serde_yaml::to_string(data).map_err(ExportError::Yaml),
+        }
+    }
+
+    /// Exports data in this format and writes it to `path` with a trailing newline, compressing it if the path ends
+    /// in `.gz`.
+    pub fn save_to_file<T: Serialize>(self, data: &[T], path: &Path) -> Result<(), SaveError> {
+        let string = self.export(data).map_err(SaveError::Exporting)?;
+        let string = data_serialize::fix_trailing_newline(string, true);
+
+        crate::io::write_maybe_gzip(path, string.as_bytes()).map_err(SaveError::Saving)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Record {
+        name: String,
+        description: String,
+        children: Vec<Record>,
+    }
+
+    fn record(name: &str, description: &str, children: Vec<Record>) -> Record {
+        Record {
+            name: name.into(),
+            description: description.into(),
+            children,
+        }
+    }
+
+    fn sample() -> Vec<Record> {
+        vec![
+            record(
+                "parent",
+                "line one\nline two",
+                vec![record("child", "", Vec::new())],
+            ),
+            record("other", "", Vec::new()),
+        ]
+    }
+
+    const FORMATS: [StorageFormat; 4] = [
+        StorageFormat::Pretty,
+        StorageFormat::Compact,
+        StorageFormat::Lines,
+        StorageFormat::Yaml,
+    ];
+
+    #[test]
+    fn round_trip() {
+        for format in FORMATS {
+            let exported = format.export(&sample()).unwrap();
+            let detected = StorageFormat::detect(&exported);
+
+            assert_eq!(detected, Some(format).filter(|&f| f != StorageFormat::Yaml));
+            assert_eq!(format.import::<Record>(&exported).unwrap(), sample());
+        }
+
+        // one line per top-level record, children included
+        let lines = StorageFormat::Lines.export(&sample()).unwrap();
+        assert_eq!(lines.lines().count(), 2);
+    }
+
+    #[test]
+    fn empty_files() {
+        assert_eq!(StorageFormat::detect("  \n"), None);
+
+        for format in FORMATS {
+            assert!(format.import::<Record>(" \n").unwrap().is_empty());
+        }
+
+        // the JSON arrays keep their format when emptied
+        for format in [StorageFormat::Pretty, StorageFormat::Compact] {
+            let exported = format.export::<Record>(&[]).unwrap();
+
+            assert_eq!(StorageFormat::detect(&exported), Some(format));
+            assert!(format.import::<Record>(&exported).unwrap().is_empty());
+        }
+
+        assert_eq!(
+            StorageFormat::Yaml.export::<Record>(&[]).unwrap().trim(),
+            "[]"
+        );
+    }
+
+    #[test]
+    fn yaml_files() {
+        let yaml = StorageFormat::Yaml.export(&sample()).unwrap();
+
+        // the multi-line description is kept readable
+        assert!(yaml.contains("description: |-\n"));
+        assert!(yaml.contains("    line one\n    line two\n"));
+
+        assert_eq!(
+            StorageFormat::from_path(Path::new("/a/items.yaml")),
+            Some(StorageFormat::Yaml)
+        );
+        assert_eq!(
+            StorageFormat::from_path(Path::new("items.YML.gz")),
+            Some(StorageFormat::Yaml)
+        );
+        assert_eq!(StorageFormat::from_path(Path::new("itmn")), None);
+        assert_eq!(StorageFormat::from_path(Path::new("items.json.gz")), None);
+        assert_eq!(
+            StorageFormat::for_file(Path::new("items.yaml"), ""),
+            Some(StorageFormat::Yaml)
+        );
+        assert_eq!(StorageFormat::for_file(Path::new("items.json"), ""), None);
+
+        // without the extension, YAML isn't guessed from the contents
+        assert_eq!(StorageFormat::for_file(Path::new("items"), &yaml), None);
+        assert_eq!(StorageFormat::detect("not json"), None);
+    }
+
+    #[test]
+    fn format_names() {
+        assert_eq!(StorageFormat::parse(" Lines "), Ok(StorageFormat::Lines));
+        assert_eq!(StorageFormat::parse("array"), Ok(StorageFormat::Pretty));
+        assert!(StorageFormat::parse("xml").is_err());
+    }
+}
@@ -1,6 +1,11 @@
+use std::borrow::Cow;
 use std::fs::{create_dir_all, File, OpenOptions};
-use std::io::{self, Read, Write};
-use std::path::Path;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
 
 pub fn touch_and_open(path: &Path) -> Result<File, String> {
     if path.exists() {
@@ -13,22 +18,7 @@ pub fn touch_and_open(path: &Path) -> Result<File, String> {
             }
         }
     } else {
-        if let Some(parent) = path.parent() {
-            if parent.exists() {
-                if parent.is_file() {
-                    return Err(format!(
-                        "parent path {} is not a directory",
-                        parent.display()
-                    ));
-                }
-            } else if let Err(e) = create_dir_all(parent) {
-                return Err(format!(
-                    "failed to create parent path {}: {}",
-                    parent.display(),
-                    e
-                ));
-            }
-        }
+        create_parent_dirs(path).map_err(|e| e.to_string())?;
 
         match OpenOptions::new()
             .read(true)
@@ -42,17 +32,186 @@ pub fn touch_and_open(path: &Path) -> Result<File, String> {
     }
 }
 
-pub fn touch_read(path: &Path) -> Result<String, String> {
-    match touch_and_open(path) {
-        Ok(mut f) => {
-            let mut contents = String::new();
-            if let Err(e) = f.read_to_string(&mut contents) {
-                Err(format!("failed to read file buffer: {}", e))
-            } else {
-                Ok(contents)
-            }
+/// Checks if a file should be transparently (de)compressed with gzip, which is the case when its path ends in `.gz`.
+pub fn is_gzip(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "gz")
+}
+
+/// Returns the path of the backup of a file, which is the same path with `.bak` appended (or inserted before `.gz`, so
+/// [`is_gzip`] still recognizes it).
+///
+/// [`is_gzip`]: is_gzip
+pub fn backup_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+
+    if is_gzip(path) {
+        name = Path::new(&name)
+            .file_stem()
+            .unwrap_or_default()
+            .to_os_string();
+        name.push(".bak.gz");
+    } else {
+        name.push(".bak");
+    }
+
+    path.with_file_name(name)
+}
+
+/// Touches `path` and reads all of its bytes, decompressing them if [`is_gzip`] says so. An empty file is read as-is,
+/// since a freshly touched file isn't valid gzip.
+///
+/// [`is_gzip`]: is_gzip
+fn touch_read_bytes(path: &Path) -> Result<Vec<u8>, String> {
+    let mut f = touch_and_open(path).map_err(|e| format!("failed to create file: {}", e))?;
+
+    let mut bytes = Vec::new();
+    f.read_to_end(&mut bytes)
+        .map_err(|e| format!("failed to read file buffer: {}", e))?;
+
+    if is_gzip(path) && !bytes.is_empty() {
+        let mut decompressed = Vec::new();
+        GzDecoder::new(bytes.as_slice())
+            .read_to_end(&mut decompressed)
+            .map_err(|e| format!("failed to decompress file: {}", e))?;
+
+        Ok(decompressed)
+    } else {
+        Ok(bytes)
+    }
+}
+
+/// Creates the directories leading to `path` if they don't exist yet, so a file can be created there.
+///
+/// Fails if one of them is a file, or if they couldn't be created.
+pub fn create_parent_dirs(path: &Path) -> io::Result<()> {
+    match path.parent() {
+        Some(parent) if parent.is_file() => Err(io::Error::other(format!(
+            "parent path {} is not a directory",
+            parent.display()
+        ))),
+        Some(parent) if !parent.as_os_str().is_empty() && !parent.exists() => {
+            create_dir_all(parent).map_err(|e| {
+                io::Error::new(
+                    e.kind(),
+                    format!("failed to create parent path {}: {}", parent.display(), e),
+                )
+            })
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Writes `contents` to `path`, compressing it with gzip if [`is_gzip`] says so. Missing parent directories are
+/// created first (see [`create_parent_dirs`]). Interrupts are held off while writing (see [`defer_interrupts`]).
+///
+/// [`is_gzip`]: is_gzip
+/// [`create_parent_dirs`]: create_parent_dirs
+/// [`defer_interrupts`]: crate::signal::defer_interrupts
+pub fn write_maybe_gzip(path: &Path, contents: &[u8]) -> io::Result<()> {
+    create_parent_dirs(path)?;
+    let _deferred = crate::signal::defer_interrupts();
+
+    if is_gzip(path) {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(contents)?;
+        std::fs::write(path, encoder.finish()?)
+    } else {
+        std::fs::write(path, contents)
+    }
+}
+
+/// Appends each of `lines` and a newline to `path` without rewriting the rest of it, which is how records are added
+/// to files with one record per line. The file (and its parent directories) are created if needed, and the data is
+/// synced to disk before returning.
+///
+/// All the records are written with a single call, and if that fails midway the file is truncated back to its old
+/// size, so a partial record is never left behind. A file that doesn't end in a newline (e.g. edited by hand) gets
+/// one first, so the new records stay on their own lines.
+///
+/// Fails without writing anything if one of `lines` has a newline in it, or if [`is_gzip`] says the file is
+/// compressed. Like on [`write_maybe_gzip`], interrupts are held off while writing.
+///
+/// [`is_gzip`]: is_gzip
+/// [`write_maybe_gzip`]: write_maybe_gzip
+pub fn append_lines_atomic<S: AsRef<str>>(path: &Path, lines: &[S]) -> io::Result<()> {
+    if lines.iter().any(|line| line.as_ref().contains('\n')) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "a record can't span more than one line",
+        ));
+    }
+
+    if is_gzip(path) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "can't append to compressed files",
+        ));
+    }
+
+    create_parent_dirs(path)?;
+    let _deferred = crate::signal::defer_interrupts();
+
+    let mut file = OpenOptions::new()
+        .read(true)
+        .append(true)
+        .create(true)
+        .open(path)?;
+    let old_len = file.metadata()?.len();
+
+    let mut records = String::new();
+    if old_len > 0 {
+        let mut last = [0u8];
+        file.seek(SeekFrom::Start(old_len - 1))?;
+        file.read_exact(&mut last)?;
+
+        if last[0] != b'\n' {
+            records.push('\n');
         }
-        Err(e) => Err(format!("failed to create file: {}", e)),
+    }
+    for line in lines {
+        records.push_str(line.as_ref());
+        records.push('\n');
+    }
+
+    if let Err(e) = file.write_all(records.as_bytes()) {
+        let _ = file.set_len(old_len);
+        return Err(e);
+    }
+
+    file.sync_data()
+}
+
+/// Reads the contents of `path` as a string, creating the file if it doesn't exist.
+///
+/// Files ending in `.gz` are decompressed transparently.
+pub fn touch_read(path: &Path) -> Result<String, String> {
+    String::from_utf8(touch_read_bytes(path)?)
+        .map_err(|e| format!("failed to read file buffer: {}", e))
+}
+
+/// Like [`touch_read`], but doesn't fail on invalid UTF-8, replacing invalid sequences with U+FFFD instead.
+///
+/// Returns the contents along with whether any replacement happened, so the caller can warn about it.
+///
+/// [`touch_read`]: touch_read
+pub fn touch_read_lossy(path: &Path) -> Result<(String, bool), String> {
+    match String::from_utf8(touch_read_bytes(path)?) {
+        Ok(contents) => Ok((contents, false)),
+        Err(e) => Ok((String::from_utf8_lossy(e.as_bytes()).into_owned(), true)),
+    }
+}
+
+/// Removes control characters (other than `\n`, `\t` and `\r`) from loaded file contents, which only show up on
+/// partially corrupted files and would otherwise cause confusing parse errors or mess up the terminal when displayed.
+///
+/// Borrows the input when there's nothing to remove, so callers can check for [`Cow::Owned`] to warn about it.
+pub fn sanitize_loaded(contents: &str) -> Cow<'_, str> {
+    let disallowed = |ch: char| ch.is_control() && !matches!(ch, '\n' | '\t' | '\r');
+
+    if contents.contains(disallowed) {
+        Cow::Owned(contents.chars().filter(|&ch| !disallowed(ch)).collect())
+    } else {
+        Cow::Borrowed(contents)
     }
 }
 
@@ -65,3 +224,72 @@ pub fn read_line(prompt: &str) -> Result<String, io::Error> {
 
     Ok(buffer.trim().into())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backup_paths() {
+        assert_eq!(backup_path(Path::new("/a/itmn")), Path::new("/a/itmn.bak"));
+        assert_eq!(
+            backup_path(Path::new("data.json.gz")),
+            Path::new("data.json.bak.gz")
+        );
+    }
+
+    #[test]
+    fn lossy_read_invalid_utf8() {
+        let path = crate::tmp::make_tmp(Some("txt"));
+        std::fs::write(&path, b"ok \xff\xfe ok").unwrap();
+
+        let strict = touch_read(&path);
+        let lossy = touch_read_lossy(&path);
+        let _ = std::fs::remove_file(&path);
+
+        assert!(strict.is_err());
+        assert_eq!(lossy, Ok(("ok \u{FFFD}\u{FFFD} ok".to_string(), true)));
+    }
+
+    #[test]
+    fn append_lines() {
+        let root = crate::tmp::make_tmp(None);
+        let path = root.join("archive/lines.json");
+
+        let mut results = Vec::new();
+        for n in 0..2u32 {
+            let record = serde_json::to_string(&[n, n * 2]).unwrap();
+            results.push(append_lines_atomic(&path, &[record]).is_ok());
+        }
+
+        // a hand-edited file without a trailing newline
+        std::fs::write(&path, std::fs::read_to_string(&path).unwrap().trim_end()).unwrap();
+        results.push(append_lines_atomic(&path, &["[2,4]", "[3,6]"]).is_ok());
+
+        // nothing is written if any of the records is broken
+        let multiline = append_lines_atomic(&path, &["[4,8]", "[4,\n8]"]);
+        let contents = std::fs::read_to_string(&path).unwrap_or_default();
+        let _ = std::fs::remove_dir_all(&root);
+
+        assert_eq!(results, [true; 3]);
+        assert!(multiline.is_err());
+        assert_eq!(
+            crate::data::data_serialize::import_lines::<Vec<u32>>(&contents),
+            Ok(vec![vec![0, 0], vec![1, 2], vec![2, 4], vec![3, 6]])
+        );
+        assert!(contents.ends_with("[3,6]\n"));
+    }
+
+    #[test]
+    fn sanitize_control_chars() {
+        let clean = "[\n\t{\"name\": \"ok\"}\r\n]";
+        assert!(matches!(sanitize_loaded(clean), Cow::Borrowed(s) if s == clean));
+
+        let dirty = "[{\"name\":\0 \"a\u{1b}b\"}\0]\n";
+        assert!(
+            matches!(sanitize_loaded(dirty), Cow::Owned(ref s) if s == "[{\"name\": \"ab\"}]\n")
+        );
+
+        assert_eq!(sanitize_loaded("\0\0\0"), "");
+    }
+}
@@ -1,9 +1,18 @@
 #![feature(try_trait_v2)]
 
 pub mod aliases;
+pub mod clipboard;
 pub mod cowstr;
 pub mod data;
+pub mod date;
 pub mod error;
+pub mod format;
 pub mod io;
+pub mod log;
 pub mod misc;
+pub mod paths;
+pub mod proc;
+pub mod signal;
+pub mod text;
 pub mod tmp;
+pub mod version;
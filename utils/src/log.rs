@@ -0,0 +1,87 @@
+//! A tiny logging facility, gated by a global verbosity level.
+//!
+//! The level is set by [`init`], usually from a `-v`/`-vv` command-line flag, and can also be raised via the
+//! `COMPSCRIPTS_LOG` environment variable (`warn`, `info`, `debug`, or a number). Everything is written to stderr.
+//!
+//! [`init`]: init
+
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// The name of the environment variable used to set the verbosity level.
+pub const ENV_VAR: &str = "COMPSCRIPTS_LOG";
+
+/// A logging level. Each level also enables the ones before it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Warn = 0,
+    Info = 1,
+    Debug = 2,
+}
+
+static VERBOSITY: AtomicU8 = AtomicU8::new(Level::Warn as u8);
+
+/// Parses a verbosity level from a string, as accepted by the environment variable.
+pub fn parse_level(string: &str) -> Option<u8> {
+    match string.trim().to_lowercase().as_str() {
+        "warn" | "warning" => Some(Level::Warn as u8),
+        "info" => Some(Level::Info as u8),
+        "debug" => Some(Level::Debug as u8),
+        other => other.parse().ok(),
+    }
+}
+
+/// Sets the global verbosity to the highest between `cli_level` (usually the amount of `-v` flags) and the level on
+/// the environment variable, if any.
+pub fn init(cli_level: u8) {
+    let env_level = std::env::var(ENV_VAR)
+        .ok()
+        .and_then(|var| parse_level(&var))
+        .unwrap_or(0);
+
+    set_verbosity(cli_level.max(env_level));
+}
+
+/// Sets the global verbosity level.
+pub fn set_verbosity(level: u8) {
+    VERBOSITY.store(level, Ordering::Relaxed);
+}
+
+/// Returns the global verbosity level.
+pub fn verbosity() -> u8 {
+    VERBOSITY.load(Ordering::Relaxed)
+}
+
+/// Checks if messages of `level` should be shown.
+pub fn enabled(level: Level) -> bool {
+    verbosity() >= level as u8
+}
+
+/// Logs a warning. Always shown.
+#[macro_export]
+macro_rules! warn {
+    ($($arg:tt)*) => {
+        if $crate::log::enabled($crate::log::Level::Warn) {
+            eprintln!("warning: {}", format_args!($($arg)*));
+        }
+    };
+}
+
+/// Logs an informational message. Shown with `-v` or higher.
+#[macro_export]
+macro_rules! info {
+    ($($arg:tt)*) => {
+        if $crate::log::enabled($crate::log::Level::Info) {
+            eprintln!("info: {}", format_args!($($arg)*));
+        }
+    };
+}
+
+/// Logs a debug trace. Shown with `-vv` or higher.
+#[macro_export]
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        if $crate::log::enabled($crate::log::Level::Debug) {
+            eprintln!("debug: {}", format_args!($($arg)*));
+        }
+    };
+}
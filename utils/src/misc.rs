@@ -1,18 +1,24 @@
 use regex::Regex;
+use std::cell::RefCell;
 use std::cmp::Eq;
-use std::collections::HashSet;
+use std::collections::{HashSet, VecDeque};
 use std::hash::Hash;
-use std::io::{Read, Write};
+use std::io::{self, Read, Write};
+use std::num::IntErrorKind;
 use std::process::{Command, Stdio};
 
-use crate::error::CliError;
+use crate::error::{CliError, CliResult};
 
-/// Runs the `fzagnostic` command with data from the arguments.
+/// Runs the `fzagnostic` command with data from the arguments. This is the only implementation in the workspace, so
+/// every menu behaves the same way.
 ///
 /// Returns Ok with the choice if everything went successfully.
 ///
-/// Returns Err with the error if the error was not intended.
-/// Returns Err with an empty string if fzagnostic was cancelled manually. (Ctrl-C, ESC etc.)
+/// Returns Err with a displayable error if the command couldn't be run or talked to.
+/// Returns Err with [`CliError::Silent`] if fzagnostic exited unsuccessfully, which is what happens when it's cancelled
+/// manually (Ctrl-C, ESC etc.)
+///
+/// [`CliError::Silent`]: CliError::Silent
 pub fn fzagnostic<'a, C, S>(prompt: &str, choices: C, height: u32) -> Result<String, CliError>
 where
     C: IntoIterator<Item = S>,
@@ -56,6 +62,106 @@ where
     }
 }
 
+/// A way of picking one of many lines, usually through a menu like [`fzagnostic`].
+///
+/// This mainly exists so menu flows can be tested with canned selections (see [`ScriptedPicker`]).
+///
+/// [`fzagnostic`]: fzagnostic
+/// [`ScriptedPicker`]: ScriptedPicker
+pub trait Picker {
+    /// Shows `choices` with `prompt` on a menu `height` lines tall, returning the chosen line(s). Errors like
+    /// [`fzagnostic`], including [`CliError::Silent`] when cancelled.
+    ///
+    /// [`fzagnostic`]: fzagnostic
+    /// [`CliError::Silent`]: CliError::Silent
+    fn pick(&self, prompt: &str, choices: &[String], height: u32) -> Result<String, CliError>;
+}
+
+/// A [`Picker`] that runs [`fzagnostic`].
+///
+/// [`Picker`]: Picker
+/// [`fzagnostic`]: fzagnostic
+pub struct Fzagnostic;
+
+impl Picker for Fzagnostic {
+    fn pick(&self, prompt: &str, choices: &[String], height: u32) -> Result<String, CliError> {
+        fzagnostic(prompt, choices, height)
+    }
+}
+
+/// A [`Picker`] for tests, which answers with canned selections, in order.
+///
+/// Each answer is some text, and the first choice containing it is picked; `None` cancels the menu, as does running
+/// out of answers. Picking text that isn't on any of the choices is an error, so tests fail loudly when the menu
+/// changes.
+///
+/// [`Picker`]: Picker
+pub struct ScriptedPicker {
+    answers: RefCell<VecDeque<Option<String>>>,
+    prompts: RefCell<Vec<String>>,
+}
+
+impl ScriptedPicker {
+    pub fn new<I, S>(answers: I) -> Self
+    where
+        I: IntoIterator<Item = Option<S>>,
+        S: Into<String>,
+    {
+        Self {
+            answers: RefCell::new(answers.into_iter().map(|a| a.map(Into::into)).collect()),
+            prompts: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// The prompts of the menus shown so far, in order.
+    pub fn prompts(&self) -> Vec<String> {
+        self.prompts.borrow().clone()
+    }
+
+    /// How many answers weren't used.
+    pub fn remaining(&self) -> usize {
+        self.answers.borrow().len()
+    }
+}
+
+impl Picker for ScriptedPicker {
+    fn pick(&self, prompt: &str, choices: &[String], _height: u32) -> Result<String, CliError> {
+        self.prompts.borrow_mut().push(prompt.into());
+
+        match self.answers.borrow_mut().pop_front() {
+            Some(Some(answer)) => choices
+                .iter()
+                .find(|choice| choice.contains(&answer))
+                .cloned()
+                .ok_or_else(|| {
+                    CliError::from_display(format!(
+                        "scripted answer {:?} isn't on the menu {:?}",
+                        answer, prompt
+                    ))
+                }),
+            Some(None) | None => Err(CliError::Silent),
+        }
+    }
+}
+
+/// Parses the index at the start of a line chosen on a menu whose lines are prefixed by their index (e.g. `3` on
+/// `  3 Some bookmark`).
+///
+/// Returns a displayable error instead of panicking if the line is empty or doesn't start with a number.
+pub fn parse_leading_index(line: &str) -> Result<usize, CliError> {
+    let token = line
+        .split_whitespace()
+        .next()
+        .ok_or_else(|| CliError::from_display("the menu returned an empty line"))?;
+
+    token.parse().map_err(|_| {
+        CliError::from_display(format!(
+            "couldn't find an index on the chosen line {:?}",
+            line.trim()
+        ))
+    })
+}
+
 /// Finds the first free value in the set.
 pub fn find_lowest_free_value(set: &HashSet<u32>) -> u32 {
     let mut free_value = 0;
@@ -67,6 +173,24 @@ pub fn find_lowest_free_value(set: &HashSet<u32>) -> u32 {
     }
 }
 
+/// Finds the `n` lowest free values in the set, in ascending order.
+///
+/// This scans the set only once, so it should be preferred over calling [`find_lowest_free_value`] in a loop when
+/// allocating many values at once.
+pub fn find_free_values(set: &HashSet<u32>, n: usize) -> Vec<u32> {
+    let mut values = Vec::with_capacity(n);
+    let mut cursor = 0;
+
+    while values.len() < n {
+        if !set.contains(&cursor) {
+            values.push(cursor);
+        }
+        cursor += 1;
+    }
+
+    values
+}
+
 /// Finds the first free value that is bigger than the highest used value in the set.
 pub fn find_highest_free_value(set: &HashSet<u32>) -> u32 {
     let free_value = set.iter().fold(0, |x, &y| x.max(y));
@@ -78,10 +202,77 @@ pub fn find_highest_free_value(set: &HashSet<u32>) -> u32 {
     }
 }
 
+/// The base used to show and read IDs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IdBase {
+    /// Plain decimal numbers, like `31`.
+    #[default]
+    Decimal,
+    /// Hexadecimal numbers prefixed by `0x`, like `0x1f`. Decimal numbers are still accepted when reading.
+    Hex,
+}
+
+impl IdBase {
+    /// Parses the name of a base (`dec`/`decimal` or `hex`/`hexadecimal`), ignoring case.
+    pub fn parse(string: &str) -> Result<Self, String> {
+        match string.trim().to_lowercase().as_str() {
+            "dec" | "decimal" => Ok(Self::Decimal),
+            "hex" | "hexadecimal" => Ok(Self::Hex),
+            _ => Err(format!(
+                "unknown ID base {:?} (expected dec or hex)",
+                string
+            )),
+        }
+    }
+
+    /// Formats an ID, padding it to at least two digits.
+    pub fn format_id(self, id: u32) -> String {
+        match self {
+            Self::Decimal => format!("{:>02}", id),
+            Self::Hex => format!("0x{:02x}", id),
+        }
+    }
+
+    /// Parses an ID written in this base.
+    pub fn parse_id(self, string: &str) -> Result<u32, String> {
+        let hex = match self {
+            Self::Hex => string
+                .strip_prefix("0x")
+                .or_else(|| string.strip_prefix("0X")),
+            Self::Decimal => None,
+        };
+
+        match hex {
+            Some(digits) => u32::from_str_radix(digits, 16),
+            None => string.parse(),
+        }
+        .map_err(|e| match e.kind() {
+            IntErrorKind::PosOverflow => format!(
+                "number {:?} is too large (the largest ID is {})",
+                string,
+                self.format_id(u32::MAX)
+            ),
+            _ => format!("invalid number {:?}: {}", string, e),
+        })
+    }
+}
+
 pub fn parse_range_str(string: &str) -> Result<Vec<u32>, String> {
+    parse_range_str_in(string, IdBase::Decimal)
+}
+
+/// Like [`parse_range_str`], but reading the numbers in `base`.
+///
+/// [`parse_range_str`]: parse_range_str
+pub fn parse_range_str_in(string: &str, base: IdBase) -> Result<Vec<u32>, String> {
+    let number_pattern = match base {
+        IdBase::Decimal => r"\d+",
+        IdBase::Hex => r"0[xX][0-9a-fA-F]+|\d+",
+    };
+
     let mut result: Vec<u32> = Vec::new();
-    let range_regex = Regex::new(r"^(\d+)\.\.(\d+)$").unwrap();
-    let number_regex = Regex::new(r"^\d+$").unwrap();
+    let range_regex = Regex::new(&format!(r"^({0})\.\.({0})$", number_pattern)).unwrap();
+    let number_regex = Regex::new(&format!(r"^(?:{})$", number_pattern)).unwrap();
 
     for number in string
         .chars()
@@ -90,11 +281,11 @@ pub fn parse_range_str(string: &str) -> Result<Vec<u32>, String> {
         .split(',')
     {
         if number_regex.is_match(number) {
-            result.push(number.parse::<u32>().unwrap())
+            result.push(base.parse_id(number)?)
         } else if range_regex.is_match(number) {
             let captures = range_regex.captures(number).unwrap();
-            let num1: u32 = captures[1].parse().unwrap();
-            let num2: u32 = captures[2].parse().unwrap();
+            let num1: u32 = base.parse_id(&captures[1])?;
+            let num2: u32 = base.parse_id(&captures[2])?;
 
             if num2 < num1 {
                 return Err(format!(
@@ -103,14 +294,8 @@ pub fn parse_range_str(string: &str) -> Result<Vec<u32>, String> {
                 ));
             }
 
-            let mut i: u32 = num1;
-            loop {
-                result.push(i);
-                i += 1;
-                if i > num2 {
-                    break;
-                }
-            }
+            // an inclusive range, since counting past num2 would overflow if it's u32::MAX
+            result.extend(num1..=num2);
         } else {
             return Err(format!("Could not parse {:?}", number));
         }
@@ -133,19 +318,42 @@ pub fn get_first_not_on_set<'a, T: Hash + Eq>(
     None
 }
 
+/// Asks the user to confirm something on stdin, returning `default` if nothing is typed and asking again if the answer
+/// isn't yes or no. The end of the input also counts as nothing typed, so a closed stdin gives `default`.
+///
+/// Errors while reading the answer are returned, so the caller can abort.
+pub fn confirm(default: bool) -> CliResult<bool> {
+    CliResult::from_display_result(
+        ask_confirmation(default).map_err(|e| format!("failed to read answer: {}", e)),
+    )
+}
+
+/// Like [`confirm`], but falls back to `default` (with a warning) if the answer can't be read.
+///
+/// [`confirm`]: confirm
 pub fn confirm_with_default(default: bool) -> bool {
-    loop {
-        let input = crate::io::read_line(&format!(
-            "Confirm? [{}] ",
-            if default { "Y/n" } else { "y/N" }
-        ))
-        .unwrap()
-        .to_lowercase();
+    ask_confirmation(default).unwrap_or_else(|e| {
+        crate::warn!("failed to read answer ({}), assuming {:?}", e, default);
+        default
+    })
+}
 
-        match input.as_str() {
-            "" => break default,
-            "y" | "yes" => break true,
-            "n" | "no" => break false,
+/// Shows the confirmation prompt and reads the answers from stdin.
+fn ask_confirmation(default: bool) -> io::Result<bool> {
+    let prompt = format!("Confirm? [{}] ", if default { "Y/n" } else { "y/N" });
+    confirm_with(default, || crate::io::read_line(&prompt))
+}
+
+/// Keeps getting answers from `read_answer` until one of them is empty, yes or no.
+fn confirm_with<F>(default: bool, mut read_answer: F) -> io::Result<bool>
+where
+    F: FnMut() -> io::Result<String>,
+{
+    loop {
+        match read_answer()?.to_lowercase().as_str() {
+            "" => break Ok(default),
+            "y" | "yes" => break Ok(true),
+            "n" | "no" => break Ok(false),
             _ => (),
         }
     }
@@ -155,6 +363,135 @@ pub fn confirm_with_default(default: bool) -> bool {
 mod tests {
     use super::*;
 
+    #[test]
+    fn confirm_answers() {
+        let answers = |list: &'static [&'static str]| {
+            let mut iter = list.iter();
+            move || -> io::Result<String> { Ok(iter.next().map_or("", |s| s).into()) }
+        };
+
+        assert_eq!(
+            confirm_with(true, answers(&["maybe", "N"])).ok(),
+            Some(false)
+        );
+        assert_eq!(confirm_with(false, answers(&["yes"])).ok(), Some(true));
+        // empty answers and the end of the input look the same
+        assert_eq!(confirm_with(false, answers(&["what"])).ok(), Some(false));
+
+        let broken = || -> io::Result<String> { Err(io::Error::other("broken")) };
+        assert!(confirm_with(true, broken).is_err());
+    }
+
+    #[test]
+    fn scripted_picker() {
+        let choices: Vec<String> = vec!["0 apple".into(), "1 banana".into()];
+        let picker = ScriptedPicker::new(vec![Some("ban"), Some("cherry"), None]);
+
+        assert_eq!(
+            picker.pick("Fruit:", &choices, 10).ok(),
+            Some("1 banana".into())
+        );
+        assert!(matches!(
+            picker.pick("Fruit:", &choices, 10),
+            Err(CliError::Display(_))
+        ));
+        assert!(matches!(
+            picker.pick("Fruit:", &choices, 10),
+            Err(CliError::Silent)
+        ));
+        assert!(matches!(
+            picker.pick("Again:", &choices, 10),
+            Err(CliError::Silent)
+        ));
+        assert_eq!(picker.prompts().len(), 4);
+        assert_eq!(picker.remaining(), 0);
+    }
+
+    #[test]
+    fn not_on_set() {
+        let set: HashSet<u32> = [1, 2, 3].iter().cloned().collect();
+        let selection = [2, 4, 1, 5];
+
+        assert_eq!(get_first_not_on_set(&selection, &set), Some(&4));
+    }
+
+    #[test]
+    fn free_values() {
+        let set: HashSet<u32> = [0, 1, 3, 6].iter().cloned().collect();
+        let values = find_free_values(&set, 4);
+
+        assert_eq!(values, vec![2, 4, 5, 7]);
+        assert!(values.iter().all(|v| !set.contains(v)));
+        assert_eq!(values[0], find_lowest_free_value(&set));
+        assert!(find_free_values(&set, 0).is_empty());
+    }
+
+    #[test]
+    fn leading_index() {
+        assert!(matches!(
+            parse_leading_index("  12 Some title (url)\n"),
+            Ok(12)
+        ));
+        assert!(matches!(parse_leading_index("0"), Ok(0)));
+        assert!(matches!(parse_leading_index(""), Err(CliError::Display(_))));
+        assert!(matches!(
+            parse_leading_index(" \n"),
+            Err(CliError::Display(_))
+        ));
+        assert!(matches!(
+            parse_leading_index("open 1"),
+            Err(CliError::Display(_))
+        ));
+        assert!(matches!(
+            parse_leading_index("-1 x"),
+            Err(CliError::Display(_))
+        ));
+    }
+
+    #[test]
+    fn hex_ids() {
+        for &id in &[0, 4, 31, 255, 4096, u32::MAX] {
+            let printed = IdBase::Hex.format_id(id);
+            assert_eq!(parse_range_str_in(&printed, IdBase::Hex), Ok(vec![id]));
+            assert_eq!(
+                IdBase::Decimal.parse_id(&IdBase::Decimal.format_id(id)),
+                Ok(id)
+            );
+        }
+
+        assert_eq!(IdBase::Hex.format_id(31), "0x1f");
+        assert_eq!(
+            parse_range_str_in("0x1e..0x20, 7", IdBase::Hex),
+            Ok(vec![30, 31, 32, 7])
+        );
+        assert!(parse_range_str("0x1f").is_err());
+        assert!(parse_range_str("99999999999").is_err());
+        assert_eq!(IdBase::parse("HEX"), Ok(IdBase::Hex));
+    }
+
+    #[test]
+    fn oversized_numbers_are_errors() {
+        let too_large = (u32::MAX as u64 + 1).to_string();
+
+        assert_eq!(
+            parse_range_str(&too_large),
+            Err(format!(
+                "number {:?} is too large (the largest ID is {})",
+                too_large,
+                u32::MAX
+            ))
+        );
+        assert!(parse_range_str(&format!("1..{}", too_large)).is_err());
+        assert!(parse_range_str(&format!("{}..{}", too_large, too_large)).is_err());
+        assert!(parse_range_str_in("0x100000000", IdBase::Hex).is_err());
+
+        let max = u32::MAX.to_string();
+        assert_eq!(
+            parse_range_str(&format!("{}..{}", u32::MAX - 1, max)),
+            Ok(vec![u32::MAX - 1, u32::MAX])
+        );
+    }
+
     #[test]
     fn range() {
         let range_str = "1..10,4,5";
@@ -0,0 +1,113 @@
+//! Resolution of the user's data, cache and config directories, following the [XDG base directory specification].
+//!
+//! The `$XDG_*_HOME` variables are honored on every platform. When they aren't set, Windows uses `%APPDATA%` and
+//! `%LOCALAPPDATA%`; everything else (including macOS) uses the usual dotfile directories inside the home directory,
+//! since these are command-line programs and that's where their files have always been.
+//!
+//! [XDG base directory specification]: https://specifications.freedesktop.org/basedir-spec/latest/
+
+use std::path::PathBuf;
+
+/// Returns the user's home directory (`$HOME`, or `%USERPROFILE%` on Windows).
+pub fn home_dir() -> PathBuf {
+    home_dir_with(&env_var)
+}
+
+/// Returns the user's data directory (`$XDG_DATA_HOME`, falling back to `~/.local/share`).
+pub fn data_dir() -> PathBuf {
+    data_dir_with(&env_var)
+}
+
+/// Returns the user's cache directory (`$XDG_CACHE_HOME`, falling back to `~/.cache`).
+pub fn cache_dir() -> PathBuf {
+    cache_dir_with(&env_var)
+}
+
+/// Returns the user's config directory (`$XDG_CONFIG_HOME`, falling back to `~/.config`).
+pub fn config_dir() -> PathBuf {
+    config_dir_with(&env_var)
+}
+
+/// Gets an environment variable, treating empty values as unset.
+fn env_var(name: &str) -> Option<String> {
+    std::env::var(name).ok().filter(|var| !var.is_empty())
+}
+
+/// Gets a variable through `getvar`, only accepting absolute paths (the spec says relative ones should be ignored).
+fn absolute_var(getvar: &dyn Fn(&str) -> Option<String>, name: &str) -> Option<PathBuf> {
+    getvar(name)
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+}
+
+fn home_dir_with(getvar: &dyn Fn(&str) -> Option<String>) -> PathBuf {
+    let var = if cfg!(windows) { "USERPROFILE" } else { "HOME" };
+    PathBuf::from(getvar(var).unwrap_or_default())
+}
+
+fn data_dir_with(getvar: &dyn Fn(&str) -> Option<String>) -> PathBuf {
+    absolute_var(getvar, "XDG_DATA_HOME")
+        .or_else(|| platform_dir(getvar, "APPDATA"))
+        .unwrap_or_else(|| home_dir_with(getvar).join(".local").join("share"))
+}
+
+fn cache_dir_with(getvar: &dyn Fn(&str) -> Option<String>) -> PathBuf {
+    absolute_var(getvar, "XDG_CACHE_HOME")
+        .or_else(|| platform_dir(getvar, "LOCALAPPDATA"))
+        .unwrap_or_else(|| home_dir_with(getvar).join(".cache"))
+}
+
+fn config_dir_with(getvar: &dyn Fn(&str) -> Option<String>) -> PathBuf {
+    absolute_var(getvar, "XDG_CONFIG_HOME")
+        .or_else(|| platform_dir(getvar, "APPDATA"))
+        .unwrap_or_else(|| home_dir_with(getvar).join(".config"))
+}
+
+/// Gets a Windows known folder from its variable. Always `None` on other platforms.
+fn platform_dir(getvar: &dyn Fn(&str) -> Option<String>, name: &str) -> Option<PathBuf> {
+    if cfg!(windows) {
+        absolute_var(getvar, name)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env(vars: &'static [(&'static str, &'static str)]) -> impl Fn(&str) -> Option<String> {
+        move |name| {
+            vars.iter()
+                .find(|(var, _)| *var == name)
+                .map(|(_, value)| value.to_string())
+        }
+    }
+
+    #[test]
+    fn xdg_vars_set() {
+        let vars = env(&[
+            ("HOME", "/home/user"),
+            ("XDG_DATA_HOME", "/data"),
+            ("XDG_CACHE_HOME", "/cache"),
+            ("XDG_CONFIG_HOME", "/config"),
+        ]);
+
+        assert_eq!(data_dir_with(&vars), PathBuf::from("/data"));
+        assert_eq!(cache_dir_with(&vars), PathBuf::from("/cache"));
+        assert_eq!(config_dir_with(&vars), PathBuf::from("/config"));
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn xdg_vars_unset_or_relative() {
+        let vars = env(&[("HOME", "/home/user"), ("XDG_DATA_HOME", "relative/path")]);
+
+        assert_eq!(
+            data_dir_with(&vars),
+            PathBuf::from("/home/user/.local/share")
+        );
+        assert_eq!(cache_dir_with(&vars), PathBuf::from("/home/user/.cache"));
+        assert_eq!(config_dir_with(&vars), PathBuf::from("/home/user/.config"));
+    }
+}
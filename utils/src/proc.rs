@@ -0,0 +1,124 @@
+//! Helpers for running external programs (openers, editors, clipboard tools etc.).
+
+use std::fmt;
+use std::io::{self, Write};
+use std::process::{Command, ExitStatus, Stdio};
+
+/// An error from [`run`].
+#[derive(Debug, PartialEq, Eq)]
+pub enum RunError {
+    /// The program doesn't exist; holds its name.
+    NotFound(String),
+    /// The program couldn't be started or waited for, or it was killed by a signal.
+    Failed(String),
+}
+
+impl fmt::Display for RunError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotFound(cmd) => write!(f, "{}: command not found", cmd),
+            Self::Failed(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl From<RunError> for String {
+    fn from(error: RunError) -> Self {
+        error.to_string()
+    }
+}
+
+/// Runs `cmd` with `args` and waits for it to exit, returning its exit code. If `stdin` is given, it's written to the
+/// program's standard input; otherwise the input is inherited, so interactive programs (like editors) work.
+///
+/// Fails with a readable message if the program couldn't be started (with [`RunError::NotFound`] if it doesn't exist)
+/// or if it was killed by a signal, so callers only have to deal with the exit code.
+pub fn run(cmd: &str, args: &[&str], stdin: Option<&str>) -> Result<i32, RunError> {
+    let mut child = Command::new(cmd)
+        .args(args)
+        .stdin(match stdin {
+            Some(_) => Stdio::piped(),
+            None => Stdio::inherit(),
+        })
+        .spawn()
+        .map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => RunError::NotFound(cmd.into()),
+            _ => RunError::Failed(format!("failed to start {}: {}", cmd, e)),
+        })?;
+
+    if let Some(text) = stdin {
+        let pipe = child.stdin.as_mut().unwrap();
+
+        // the program might exit without reading everything, which isn't our problem
+        match pipe.write_all(text.as_bytes()) {
+            Err(e) if e.kind() != io::ErrorKind::BrokenPipe => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(RunError::Failed(format!(
+                    "failed to write to {}: {}",
+                    cmd, e
+                )));
+            }
+            _ => {}
+        }
+    }
+
+    // waiting closes the program's stdin first, so it sees the end of the input
+    let status = child
+        .wait()
+        .map_err(|e| RunError::Failed(format!("failed to wait for {}: {}", cmd, e)))?;
+
+    status
+        .code()
+        .ok_or_else(|| RunError::Failed(terminated_message(cmd, status)))
+}
+
+/// Describes an exit status without an exit code.
+#[cfg(unix)]
+fn terminated_message(cmd: &str, status: ExitStatus) -> String {
+    use std::os::unix::process::ExitStatusExt;
+
+    match status.signal() {
+        Some(signal) => format!("{} was terminated by signal {}", cmd, signal),
+        None => format!("{} exited abnormally ({})", cmd, status),
+    }
+}
+
+/// Describes an exit status without an exit code.
+#[cfg(not(unix))]
+fn terminated_message(cmd: &str, status: ExitStatus) -> String {
+    format!("{} exited abnormally ({})", cmd, status)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exit_codes() {
+        assert_eq!(run("true", &[], None), Ok(0));
+        assert_eq!(run("false", &[], None), Ok(1));
+        assert_eq!(
+            run("compscripts-nonexistent-command", &[], None),
+            Err(RunError::NotFound("compscripts-nonexistent-command".into()))
+        );
+    }
+
+    #[test]
+    fn stdin_and_signals() {
+        let check = |text| {
+            run(
+                "sh",
+                &["-c", r#"read line; test "$line" = hello"#],
+                Some(text),
+            )
+        };
+
+        assert_eq!(check("hello\n"), Ok(0));
+        assert_eq!(check("bye\n"), Ok(1));
+        assert_eq!(
+            run("sh", &["-c", "kill -9 $$"], None),
+            Err(RunError::Failed("sh was terminated by signal 9".into()))
+        );
+    }
+}
@@ -0,0 +1,44 @@
+//! Handling of interrupts (Ctrl-C and the like), so they don't leave locks behind.
+
+use signal_hook::consts::{SIGHUP, SIGINT, SIGTERM};
+use signal_hook::iterator::Signals;
+
+use std::sync::{PoisonError, RwLock, RwLockReadGuard};
+
+/// Held for reading by the code that must not be interrupted, and for writing by the handler before it exits.
+static CRITICAL: RwLock<()> = RwLock::new(());
+
+/// Makes the program release its folder locks (see [`release_held`]) and exit when it gets `SIGINT`, `SIGTERM` or
+/// `SIGHUP`. The exit code is 128 plus the signal number, like shells do.
+///
+/// The signals are handled on a separate thread, so nothing else has to care about them, other than holding them off
+/// with [`defer_interrupts`] while writing files. If the handler can't be installed, a warning is shown and the
+/// signals keep their default behavior.
+///
+/// [`release_held`]: crate::tmp::folder_lock::release_held
+pub fn release_locks_on_interrupt() {
+    let mut signals = match Signals::new([SIGINT, SIGTERM, SIGHUP]) {
+        Ok(signals) => signals,
+        Err(e) => {
+            crate::warn!("failed to install the interrupt handler: {}", e);
+            return;
+        }
+    };
+
+    std::thread::spawn(move || {
+        if let Some(signal) = signals.forever().next() {
+            crate::debug!("got signal {}, releasing locks", signal);
+
+            // wait for any file that is being written, and keep new writes from starting
+            let _critical = CRITICAL.write().unwrap_or_else(PoisonError::into_inner);
+            crate::tmp::folder_lock::release_held();
+            std::process::exit(128 + signal);
+        }
+    });
+}
+
+/// Keeps interrupts from exiting the program while the returned guard is alive, so something like a file being saved
+/// isn't left halfway. An interrupt that comes meanwhile only takes effect once every guard is dropped.
+pub fn defer_interrupts() -> RwLockReadGuard<'static, ()> {
+    CRITICAL.read().unwrap_or_else(PoisonError::into_inner)
+}
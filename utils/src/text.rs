@@ -0,0 +1,91 @@
+//! Helpers for fitting text on the terminal, where what matters is how many columns a string takes, not its length.
+
+use std::borrow::Cow;
+
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+/// What's appended to truncated strings.
+pub const ELLIPSIS: &str = "\u{2026}";
+
+/// How many columns `string` takes on the terminal (e.g. 2 for most CJK characters and emoji).
+pub fn width(string: &str) -> usize {
+    UnicodeWidthStr::width(string)
+}
+
+/// Truncates `string` so it takes at most `max_cols` columns, ending it with [`ELLIPSIS`] if anything was cut. It's
+/// only cut between graphemes, so combining marks and multi-codepoint emoji are never split.
+///
+/// Strings that already fit are returned as they are.
+pub fn truncate(string: &str, max_cols: usize) -> Cow<'_, str> {
+    if width(string) <= max_cols {
+        return Cow::Borrowed(string);
+    }
+
+    let budget = match max_cols.checked_sub(width(ELLIPSIS)) {
+        Some(budget) => budget,
+        None => return Cow::Borrowed(""),
+    };
+
+    let mut used = 0;
+    let mut end = 0;
+    for (i, grapheme) in string.grapheme_indices(true) {
+        used += width(grapheme);
+        if used > budget {
+            break;
+        }
+        end = i + grapheme.len();
+    }
+
+    Cow::Owned(format!("{}{}", &string[..end], ELLIPSIS))
+}
+
+/// Pads `string` with spaces at the end until it takes `cols` columns, like `{:<cols}` does on `format!` but counting
+/// columns instead of characters. Strings that are already wide enough are returned as they are.
+pub fn pad(string: &str, cols: usize) -> Cow<'_, str> {
+    match cols.checked_sub(width(string)) {
+        Some(missing) if missing > 0 => Cow::Owned(format!("{}{}", string, " ".repeat(missing))),
+        _ => Cow::Borrowed(string),
+    }
+}
+
+/// Truncates `string` to `cols` columns (see [`truncate`]) and then pads it to exactly that (see [`pad`]), for
+/// aligning columns.
+pub fn fit(string: &str, cols: usize) -> String {
+    pad(&truncate(string, cols), cols).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn truncate_by_columns() {
+        assert_eq!(truncate("hello", 5), "hello");
+        assert_eq!(truncate("hello world", 6), "hello\u{2026}");
+
+        // each of these takes two columns, so only two of them fit with the ellipsis
+        assert_eq!(truncate("日本語のテキスト", 6), "日本\u{2026}");
+        assert_eq!(truncate("日本語", 4), "日\u{2026}");
+
+        // a family emoji is many codepoints joined together, and an accent is a separate combining mark
+        let family = "\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}";
+        assert_eq!(
+            truncate(&format!("{}{}x", family, family), 4),
+            format!("{}\u{2026}", family)
+        );
+        assert_eq!(truncate("e\u{301}e\u{301}e\u{301}", 2), "e\u{301}\u{2026}");
+
+        assert_eq!(truncate("hello", 0), "");
+    }
+
+    #[test]
+    fn fit_aligns_wide_text() {
+        assert_eq!(pad("日本", 6), "日本  ");
+        assert_eq!(pad("toolong", 3), "toolong");
+
+        for name in &["abc", "日本語のテキスト", "\u{1F600} smile"] {
+            assert_eq!(width(&fit(name, 8)), 8);
+        }
+    }
+}
@@ -1,19 +1,74 @@
 use rand::distributions::Alphanumeric;
 use rand::Rng;
-use std::fs::OpenOptions;
-use std::io::{Read, Write};
-use std::process::Command;
+use std::fs::{create_dir_all, OpenOptions};
+use std::io::{BufReader, BufWriter, Read, Write};
 
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
 
+/// Returns the directory where temporary files are created: `$COMPSCRIPTS_TMPDIR` if it's set (it's created if
+/// needed), or the system's temporary directory otherwise.
+///
+/// If `$COMPSCRIPTS_TMPDIR` can't be written to, a warning is shown and the system's temporary directory is used. The
+/// directory is only looked up (and checked) once per run.
+pub fn temp_dir() -> PathBuf {
+    static DIR: OnceLock<PathBuf> = OnceLock::new();
+
+    DIR.get_or_init(|| {
+        temp_dir_with(&|name| std::env::var(name).ok().filter(|var| !var.is_empty()))
+    })
+    .clone()
+}
+
+fn temp_dir_with(getvar: &dyn Fn(&str) -> Option<String>) -> PathBuf {
+    let dir = match getvar("COMPSCRIPTS_TMPDIR") {
+        Some(dir) => PathBuf::from(dir),
+        None => return std::env::temp_dir(),
+    };
+
+    match check_writable(&dir) {
+        Ok(()) => dir,
+        Err(e) => {
+            crate::warn!(
+                "$COMPSCRIPTS_TMPDIR ({}) is not usable, falling back to the system temp dir: {}",
+                dir.display(),
+                e
+            );
+            std::env::temp_dir()
+        }
+    }
+}
+
+/// Creates `dir` if needed and checks that files can be created inside it.
+fn check_writable(dir: &Path) -> Result<(), String> {
+    create_dir_all(dir).map_err(|e| format!("failed to create it: {}", e))?;
+
+    let probe = make_tmp_in(dir, Some("probe"));
+    OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&probe)
+        .map_err(|e| format!("failed to create a file inside it: {}", e))?;
+    let _ = std::fs::remove_file(&probe);
+
+    Ok(())
+}
+
+/// Returns a path to a file that doesn't exist yet on the temp dir (see [`temp_dir`]).
+///
+/// [`temp_dir`]: temp_dir
 pub fn make_tmp(extension: Option<&str>) -> PathBuf {
+    make_tmp_in(&temp_dir(), extension)
+}
+
+fn make_tmp_in(dir: &Path, extension: Option<&str>) -> PathBuf {
     loop {
         let path_str = rand::thread_rng()
             .sample_iter(&Alphanumeric)
             .take(6)
             .collect::<String>();
 
-        let mut pathbuf = std::env::temp_dir();
+        let mut pathbuf = dir.to_path_buf();
         pathbuf.push(format!(
             "tmp.{}{}",
             path_str,
@@ -30,9 +85,29 @@ pub fn make_tmp(extension: Option<&str>) -> PathBuf {
 }
 
 pub mod folder_lock {
+    use std::fmt;
     use std::io::{self, ErrorKind};
     use std::path::PathBuf;
-    use std::fmt;
+    use std::sync::{Mutex, MutexGuard};
+
+    /// The paths of the locks currently held by this process, so they can be released even when the program doesn't
+    /// get to drop them (see [`release_held`]).
+    ///
+    /// [`release_held`]: release_held
+    static HELD: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+    fn held() -> MutexGuard<'static, Vec<PathBuf>> {
+        // a panic while holding this can't leave the list in a bad state
+        HELD.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Releases every lock held by this process, ignoring errors. Meant for when the program is about to exit without
+    /// unwinding (e.g. when interrupted), so the locks' `Drop` won't run.
+    pub fn release_held() {
+        for path in held().drain(..) {
+            let _ = std::fs::remove_dir(&path);
+        }
+    }
 
     #[derive(Debug)]
     pub enum LockError {
@@ -45,7 +120,10 @@ pub mod folder_lock {
         fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
             match self {
                 Self::InvalidLockName => write!(f, "Invalid lock name"),
-                Self::AlreadyLocked => write!(f, "Lock already exists (other instance of this application might be running)"),
+                Self::AlreadyLocked => write!(
+                    f,
+                    "Lock already exists (other instance of this application might be running)"
+                ),
                 Self::IoError(err) => write!(f, "I/O error: {}", err),
             }
         }
@@ -57,6 +135,15 @@ pub mod folder_lock {
         IoError(io::Error),
     }
 
+    impl fmt::Display for ReleaseError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                Self::AlreadyReleased => write!(f, "Lock was already released"),
+                Self::IoError(err) => write!(f, "I/O error: {}", err),
+            }
+        }
+    }
+
     pub struct FolderLock {
         lock_path: PathBuf,
         released: bool,
@@ -78,6 +165,8 @@ pub mod folder_lock {
                 });
             }
 
+            held().push(path.clone());
+
             Ok(Self {
                 lock_path: path,
                 released: false,
@@ -85,6 +174,8 @@ pub mod folder_lock {
         }
 
         pub fn release(&mut self) -> Result<(), ReleaseError> {
+            held().retain(|path| *path != self.lock_path);
+
             if let Err(e) = std::fs::remove_dir(&self.lock_path) {
                 return Err(match e.kind() {
                     ErrorKind::NotFound => ReleaseError::AlreadyReleased,
@@ -98,15 +189,63 @@ pub mod folder_lock {
     }
 
     impl Drop for FolderLock {
+        /// Releases the lock if it wasn't released yet. Failures are only logged, since panicking here could abort the
+        /// program if it's already unwinding; use [`FolderLock::release`] to handle them.
+        ///
+        /// [`FolderLock::release`]: FolderLock::release
         fn drop(&mut self) {
             if !self.released {
                 match self.release() {
                     Ok(()) | Err(ReleaseError::AlreadyReleased) => {}
-                    Err(other) => Err(other).expect("failed to release lock"),
+                    Err(other) => crate::warn!(
+                        "failed to release lock {}: {}",
+                        self.lock_path.display(),
+                        other
+                    ),
                 }
             }
         }
     }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn unique_name(label: &str) -> String {
+            format!("compscripts-test-{}-{}", label, std::process::id())
+        }
+
+        #[test]
+        fn release_errors_dont_panic_on_drop() {
+            // already released by someone else: release() reports it, dropping is fine
+            let mut lock = FolderLock::lock(&unique_name("released")).unwrap();
+            std::fs::remove_dir(&lock.lock_path).unwrap();
+            assert!(matches!(lock.release(), Err(ReleaseError::AlreadyReleased)));
+            drop(lock);
+
+            // the directory can't be removed (it isn't empty): release() returns the error, dropping only logs it
+            let mut lock = FolderLock::lock(&unique_name("io-error")).unwrap();
+            let path = lock.lock_path.clone();
+            std::fs::write(path.join("blocker"), "").unwrap();
+            assert!(matches!(lock.release(), Err(ReleaseError::IoError(_))));
+            drop(lock);
+
+            assert!(path.exists());
+            std::fs::remove_dir_all(&path).unwrap();
+        }
+
+        #[test]
+        fn held_locks_are_released_on_interrupt() {
+            // what the interrupt handler does before exiting, since the lock wouldn't be dropped
+            let lock = FolderLock::lock(&unique_name("interrupted")).unwrap();
+            let path = lock.lock_path.clone();
+            release_held();
+            let released = !path.exists();
+            std::mem::forget(lock);
+
+            assert!(released);
+        }
+    }
 }
 
 pub fn make_folder_lock(
@@ -115,54 +254,319 @@ pub fn make_folder_lock(
     folder_lock::FolderLock::lock(lock_name)
 }
 
-pub fn edit_text(text: &str, extension: Option<&str>) -> Result<(String, i32), String> {
-    let tmpbuf = make_tmp(extension);
-
-    {
-        // touch temp file
-        let mut tmpfile = match OpenOptions::new()
-            .write(true)
-            .create(true)
-            .open(tmpbuf.as_path().to_str().unwrap())
-        {
-            Ok(file) => file,
-            Err(e) => return Err(format!("failed to create temp file: {}", e)),
-        };
+/// A key that identifies a piece of text being edited, so it can be recovered if the program (or the editor) crashes
+/// before the changes are committed.
+///
+/// It should only contain characters that are valid on a file name, and be short enough for one (see
+/// [`RecoveryKey::hashed`]).
+///
+/// [`RecoveryKey::hashed`]: RecoveryKey::hashed
+#[derive(Debug, Clone)]
+pub struct RecoveryKey(pub String);
+
+impl RecoveryKey {
+    /// Makes a key out of `prefix` and a hash of `what`, for when `what` (like a list of IDs) could be too long for a
+    /// file name. The hash is FNV-1a, which unlike the standard library's hasher is the same on every run.
+    pub fn hashed(prefix: &str, what: &str) -> Self {
+        let hash = what.bytes().fold(0xcbf2_9ce4_8422_2325u64, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+        });
 
-        write!(tmpfile, "{}", text).unwrap();
+        Self(format!("{}-{:016x}", prefix, hash))
     }
 
-    // edit file
-    let editor = std::env::var("MAYBE_GRAPHICAL_EDITOR")
-        .or_else(|_| std::env::var("EDITOR"))
-        .unwrap_or_else(|_| "compscripts-defaultedit".into());
+    /// Returns the directory where recovery files are stored (`compscripts/recovery`, inside [`cache_dir`]).
+    ///
+    /// [`cache_dir`]: crate::paths::cache_dir
+    pub fn recovery_dir() -> PathBuf {
+        crate::paths::cache_dir()
+            .join("compscripts")
+            .join("recovery")
+    }
 
-    let code = match Command::new(&editor)
-        .args(&[tmpbuf.as_path().to_str().unwrap()])
-        .spawn()
-    {
-        Ok(mut child) => child.wait().unwrap().code().unwrap_or(130),
-        Err(why) => return Err(format!("failed to start process: {}", why)),
-    };
+    /// Returns the path of the recovery file for this key.
+    pub fn path(&self, extension: Option<&str>) -> PathBuf {
+        Self::recovery_dir().join(match extension {
+            Some(ext) => format!("{}.{}", self.0, ext),
+            None => self.0.clone(),
+        })
+    }
+
+    /// Removes the recovery file for this key, if there's one. Should be called once the edited text was applied.
+    pub fn discard(&self, extension: Option<&str>) {
+        let _ = std::fs::remove_file(self.path(extension));
+    }
+}
+
+/// A way of editing text, usually through an external editor.
+///
+/// This mainly exists so code that asks the user to edit text can be tested with a fake editor.
+pub trait EditorBackend {
+    /// Edits `text`, returning the new text along with the editor's exit code.
+    ///
+    /// `extension` is a hint for the file type of the text, mostly used for syntax highlighting.
+    fn edit(&self, text: &str, extension: Option<&str>) -> Result<(String, i32), String>;
+
+    /// Like [`edit`], but keeps the text on a predictable place (see [`RecoveryKey`]), so it can be restored after a
+    /// crash. The file is left there until [`RecoveryKey::discard`] is called, which should be done once the text was
+    /// applied, so it isn't lost if it turns out to be invalid.
+    ///
+    /// The default implementation doesn't do any recovery.
+    ///
+    /// [`edit`]: EditorBackend::edit
+    /// [`RecoveryKey`]: RecoveryKey
+    /// [`RecoveryKey::discard`]: RecoveryKey::discard
+    fn edit_recoverable(
+        &self,
+        text: &str,
+        extension: Option<&str>,
+        _key: &RecoveryKey,
+    ) -> Result<(String, i32), String> {
+        self.edit(text, extension)
+    }
+}
+
+/// Writes `text` to the file on `path`, replacing its contents. Errors (like a full disk) are returned instead of
+/// panicking, and everything is flushed before returning, so the file is complete when the editor opens it.
+fn write_text(path: &Path, text: &str) -> Result<(), String> {
+    let file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(path)
+        .map_err(|e| format!("failed to create temp file: {}", e))?;
+
+    let mut writer = BufWriter::new(file);
+    writer
+        .write_all(text.as_bytes())
+        .and_then(|_| writer.flush())
+        .map_err(|e| format!("failed to write temp file: {}", e))
+}
+
+/// Reads the whole file on `path` back as a string.
+fn read_text(path: &Path) -> Result<String, String> {
+    let file = OpenOptions::new()
+        .read(true)
+        .open(path)
+        .map_err(|e| format!("failed to open temp file: {}", e))?;
 
     let mut buf = String::new();
-    {
-        // read new contents
-        let mut tmpfile = match OpenOptions::new()
-            .read(true)
-            .open(tmpbuf.as_path().to_str().unwrap())
-        {
-            Ok(file) => file,
-            Err(why) => return Err(format!("failed to create temp file: {}", why)),
+    BufReader::new(file)
+        .read_to_string(&mut buf)
+        .map_err(|e| format!("failed to read temp file: {}", e))?;
+
+    Ok(buf)
+}
+
+/// Returns the editor text is edited with, which is the first of these that is set and not empty:
+///
+/// 1. `$COMPSCRIPTS_EDITOR`, to pick an editor only for these scripts;
+/// 2. `$MAYBE_GRAPHICAL_EDITOR`;
+/// 3. `$EDITOR`;
+/// 4. `compscripts-defaultedit`, if none of the above is.
+pub fn resolve_editor() -> String {
+    resolve_editor_with(&|name| std::env::var(name).ok().filter(|var| !var.is_empty()))
+}
+
+fn resolve_editor_with(getvar: &dyn Fn(&str) -> Option<String>) -> String {
+    ["COMPSCRIPTS_EDITOR", "MAYBE_GRAPHICAL_EDITOR", "EDITOR"]
+        .iter()
+        .find_map(|name| getvar(name))
+        .unwrap_or_else(|| "compscripts-defaultedit".into())
+}
+
+/// Writes `text` to the file on `path`, opens it on the user's editor and reads it back, returning the new text along
+/// with the editor's exit code. The file is not removed.
+fn edit_at_path(path: &Path, text: &str) -> Result<(String, i32), String> {
+    write_text(path, text)?;
+
+    let editor = resolve_editor();
+    let code = crate::proc::run(&editor, &[&path.to_string_lossy()], None)?;
+
+    Ok((read_text(path)?, code))
+}
+
+/// The default editor backend, which writes the text to a temporary file and opens it with the editor picked by
+/// [`resolve_editor`].
+///
+/// [`resolve_editor`]: resolve_editor
+pub struct ExternalEditor;
+
+impl EditorBackend for ExternalEditor {
+    fn edit(&self, text: &str, extension: Option<&str>) -> Result<(String, i32), String> {
+        let tmpbuf = make_tmp(extension);
+        let result = edit_at_path(&tmpbuf, text);
+
+        // remove the file, if it still exists
+        let _ = std::fs::remove_file(tmpbuf.as_path());
+
+        result
+    }
+
+    fn edit_recoverable(
+        &self,
+        text: &str,
+        extension: Option<&str>,
+        key: &RecoveryKey,
+    ) -> Result<(String, i32), String> {
+        let path = key.path(extension);
+
+        if let Some(parent) = path.parent() {
+            create_dir_all(parent)
+                .map_err(|e| format!("failed to create recovery directory: {}", e))?;
+        }
+
+        // offer to restore text left behind by a previous session
+        let recovered = std::fs::read_to_string(&path)
+            .ok()
+            .filter(|recovered| recovered != text);
+
+        let text = match recovered {
+            Some(ref recovered) => {
+                eprintln!(
+                    "Found unsaved text from a previous session at {}.",
+                    path.display()
+                );
+                eprintln!("Restore it?");
+
+                if crate::misc::confirm_with_default(true) {
+                    recovered.as_str()
+                } else {
+                    text
+                }
+            }
+            None => text,
         };
 
-        tmpfile
-            .read_to_string(&mut buf)
-            .expect("failed to read buffer to string");
+        edit_at_path(&path, text)
+    }
+}
+
+/// Edits `text` with the [`ExternalEditor`] backend, keeping it on a recovery file until it's discarded (see
+/// [`EditorBackend::edit_recoverable`]).
+///
+/// [`ExternalEditor`]: ExternalEditor
+/// [`EditorBackend::edit_recoverable`]: EditorBackend::edit_recoverable
+pub fn edit_text_recoverable(
+    text: &str,
+    extension: Option<&str>,
+    key: &RecoveryKey,
+) -> Result<(String, i32), String> {
+    ExternalEditor.edit_recoverable(text, extension, key)
+}
+
+/// An editor backend that transforms the text with a function instead of running an editor. Always exits with code 0.
+pub struct FnEditor<F: Fn(&str) -> String>(pub F);
+
+impl<F: Fn(&str) -> String> EditorBackend for FnEditor<F> {
+    fn edit(&self, text: &str, _extension: Option<&str>) -> Result<(String, i32), String> {
+        Ok(((self.0)(text), 0))
+    }
+}
+
+/// Edits `text` with the [`ExternalEditor`] backend.
+///
+/// [`ExternalEditor`]: ExternalEditor
+pub fn edit_text(text: &str, extension: Option<&str>) -> Result<(String, i32), String> {
+    ExternalEditor.edit(text, extension)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn large_text_round_trip() {
+        let line = "some text that will be repeated many times, with unicode: ação\n";
+        let text = line.repeat(4 * 1024 * 1024 / line.len());
+
+        let path = make_tmp(Some("txt"));
+        let written = write_text(&path, &text);
+        let read = read_text(&path);
+        // writing again truncates the file
+        let rewritten = write_text(&path, "short").and_then(|_| read_text(&path));
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!(written, Ok(()));
+        assert!(read == Ok(text), "the text should be read back unchanged");
+        assert_eq!(rewritten, Ok("short".into()));
+    }
+
+    #[test]
+    fn write_errors_are_returned() {
+        let dir = make_tmp(None);
+        create_dir_all(&dir).unwrap();
+
+        // a directory can't be opened as a file
+        let result = write_text(&dir, "text");
+        let _ = std::fs::remove_dir(&dir);
+
+        assert!(result
+            .unwrap_err()
+            .starts_with("failed to create temp file"));
     }
 
-    // remove the file, if it still exists
-    let _ = std::fs::remove_file(tmpbuf.as_path());
+    #[test]
+    fn custom_temp_dir() {
+        let base =
+            std::env::temp_dir().join(format!("compscripts-test-tmpdir-{}", std::process::id()));
+        let custom = base.join("nested");
+        let custom_str = custom.to_string_lossy().into_owned();
 
-    Ok((buf, code))
+        // the directory is created, and temp files land there
+        let dir = temp_dir_with(&|name| (name == "COMPSCRIPTS_TMPDIR").then(|| custom_str.clone()));
+        let path = make_tmp_in(&dir, Some("txt"));
+        let created = custom.is_dir();
+
+        // a path that can't be a directory falls back to the system temp dir
+        let file = base.join("file");
+        std::fs::write(&file, "").unwrap();
+        let file_str = file.to_string_lossy().into_owned();
+        let fallback = temp_dir_with(&|_| Some(file_str.clone()));
+
+        let _ = std::fs::remove_dir_all(&base);
+
+        assert_eq!(dir, custom);
+        assert!(created);
+        assert_eq!(path.parent(), Some(custom.as_path()));
+        assert_eq!(fallback, std::env::temp_dir());
+        assert_eq!(temp_dir_with(&|_| None), std::env::temp_dir());
+    }
+
+    #[test]
+    fn hashed_recovery_keys() {
+        let ids: Vec<String> = (0..1000).map(|id| format!("i{}", id)).collect();
+        let key = RecoveryKey::hashed("itmn-names", &ids.join("-"));
+
+        assert_eq!(key.0.len(), "itmn-names-".len() + 16);
+        assert_eq!(key.0, RecoveryKey::hashed("itmn-names", &ids.join("-")).0);
+        assert_ne!(key.0, RecoveryKey::hashed("itmn-names", "i0-i1").0);
+    }
+
+    #[test]
+    fn editor_precedence() {
+        let with = |vars: &[(&str, &str)]| {
+            let vars: Vec<(String, String)> = vars
+                .iter()
+                .map(|&(name, value)| (name.into(), value.into()))
+                .collect();
+
+            resolve_editor_with(&|name| {
+                vars.iter()
+                    .find(|(var, _)| var == name)
+                    .map(|(_, value)| value.clone())
+            })
+        };
+
+        let all = [
+            ("EDITOR", "vi"),
+            ("MAYBE_GRAPHICAL_EDITOR", "gvim"),
+            ("COMPSCRIPTS_EDITOR", "nano"),
+        ];
+
+        assert_eq!(with(&all), "nano");
+        assert_eq!(with(&all[..2]), "gvim");
+        assert_eq!(with(&all[..1]), "vi");
+        assert_eq!(with(&[]), "compscripts-defaultedit");
+    }
 }
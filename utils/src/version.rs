@@ -0,0 +1,46 @@
+//! Version and build information, shown the same way by every binary.
+
+/// The short hash of the commit the workspace was built from, if it was built from a git checkout.
+pub const GIT_HASH: Option<&str> = option_env!("COMPSCRIPTS_GIT_HASH");
+
+/// The cargo profile used on the build (`debug` or `release`).
+pub const BUILD_PROFILE: Option<&str> = option_env!("COMPSCRIPTS_BUILD_PROFILE");
+
+/// Builds the version line of a binary, e.g. `itmn 0.1.0 (git 1a2b3c4, release build)`.
+///
+/// `version` should be the binary's own `CARGO_PKG_VERSION`, since this crate's one would be used otherwise.
+pub fn version_line(bin_name: &str, version: &str) -> String {
+    let info: Vec<String> = GIT_HASH
+        .map(|hash| format!("git {}", hash))
+        .into_iter()
+        .chain(BUILD_PROFILE.map(|profile| format!("{} build", profile)))
+        .collect();
+
+    if info.is_empty() {
+        format!("{} {}", bin_name, version)
+    } else {
+        format!("{} {} ({})", bin_name, version, info.join(", "))
+    }
+}
+
+/// Prints the version line of a binary (see [`version_line`]) to stdout.
+///
+/// [`version_line`]: version_line
+pub fn print_version(bin_name: &str, version: &str) {
+    println!("{}", version_line(bin_name, version));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn version_line_starts_with_name_and_version() {
+        let line = version_line("itmn", "1.2.3");
+
+        assert!(line.starts_with("itmn 1.2.3"));
+        if let Some(hash) = GIT_HASH {
+            assert!(line.contains(hash));
+        }
+    }
+}